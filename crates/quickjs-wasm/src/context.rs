@@ -1,26 +1,806 @@
-use anyhow::Result;
-use quickjs_wasm_rs::{JSContextRef, JSValue, JSValueRef};
+use anyhow::{anyhow, bail, Result};
+use quickjs_wasm_rs::{from_qjs_value, JSContextRef, JSValue, JSValueRef};
+use std::cell::Cell;
+#[cfg(feature = "console")]
 use std::io::Write;
 
+use crate::{io, runtime};
+
+static CRYPTO_SCRIPT_NAME: &str = "crypto.js";
+
+/// `crypto.getRandomValues` is implemented on top of `Math.random` so both draw from the same generator.
+static CRYPTO: &str = r#"
+globalThis.crypto = {
+    getRandomValues(array) {
+        if (!ArrayBuffer.isView(array) || array instanceof Float32Array || array instanceof Float64Array) {
+            throw new TypeError("getRandomValues requires an integer typed array");
+        }
+        if (array.byteLength > 65536) {
+            throw new RangeError("getRandomValues cannot generate more than 65536 bytes");
+        }
+        for (let i = 0; i < array.length; i++) {
+            array[i] = Math.floor(Math.random() * 4294967296);
+        }
+        return array;
+    },
+};
+"#;
+
+static BASE64_SCRIPT_NAME: &str = "base64.js";
+
+/// `btoa` and `atob` as specified by the HTML standard. a "binary string" holds one byte per code unit so `btoa`
+/// rejects code units above `0xFF`, and `atob` implements forgiving-base64 decoding which ignores ASCII whitespace
+/// and optional padding but rejects anything else malformed. both throw an `InvalidCharacterError`.
+static BASE64: &str = r#"
+(() => {
+    const alphabet = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    const invalidCharacter = (message) => {
+        const error = new Error(message);
+        error.name = "InvalidCharacterError";
+        return error;
+    };
+    globalThis.btoa = function btoa(data) {
+        if (arguments.length === 0) {
+            throw new TypeError("btoa requires 1 argument");
+        }
+        const input = String(data);
+        const output = [];
+        for (let i = 0; i < input.length; i += 3) {
+            const bytes = [];
+            for (let j = i; j < i + 3 && j < input.length; j++) {
+                const code = input.charCodeAt(j);
+                if (code > 0xff) {
+                    throw invalidCharacter(`btoa: character at index ${j} is outside of the Latin1 range`);
+                }
+                bytes.push(code);
+            }
+            const [a, b = 0, c = 0] = bytes;
+            const triple = (a << 16) | (b << 8) | c;
+            output.push(
+                alphabet[(triple >> 18) & 63],
+                alphabet[(triple >> 12) & 63],
+                bytes.length > 1 ? alphabet[(triple >> 6) & 63] : "=",
+                bytes.length > 2 ? alphabet[triple & 63] : "=",
+            );
+        }
+        return output.join("");
+    };
+    globalThis.atob = function atob(data) {
+        if (arguments.length === 0) {
+            throw new TypeError("atob requires 1 argument");
+        }
+        let input = String(data).replace(/[\t\n\f\r ]/g, "");
+        if (input.length % 4 === 0) {
+            input = input.replace(/==?$/, "");
+        }
+        if (input.length % 4 === 1 || /[^A-Za-z0-9+/]/.test(input)) {
+            throw invalidCharacter("atob: the string to be decoded is not correctly encoded");
+        }
+        const output = [];
+        let buffer = 0;
+        let bits = 0;
+        for (let i = 0; i < input.length; i++) {
+            buffer = (buffer << 6) | alphabet.indexOf(input[i]);
+            bits += 6;
+            if (bits >= 8) {
+                bits -= 8;
+                output.push(String.fromCharCode((buffer >> bits) & 0xff));
+                buffer &= (1 << bits) - 1;
+            }
+        }
+        return output.join("");
+    };
+})();
+"#;
+
+#[cfg(feature = "structured-clone")]
+static STRUCTURED_CLONE_SCRIPT_NAME: &str = "structured-clone.js";
+
+/// `structuredClone` and `deepEqual` operate on plain data: primitives, arrays, plain objects, `Date`, `RegExp`,
+/// `Map`, `Set`, `ArrayBuffer` and typed arrays. `structuredClone` preserves cycles and throws for functions.
+#[cfg(feature = "structured-clone")]
+static STRUCTURED_CLONE: &str = r#"
+globalThis.structuredClone = function structuredClone(value) {
+    const seen = new Map();
+    const clone = (value) => {
+        if (typeof value === "function" || typeof value === "symbol") {
+            throw new TypeError(`${String(value)} could not be cloned`);
+        }
+        if (value === null || typeof value !== "object") {
+            return value;
+        }
+        if (seen.has(value)) {
+            return seen.get(value);
+        }
+        let copy;
+        if (Array.isArray(value)) {
+            copy = new Array(value.length);
+            seen.set(value, copy);
+            value.forEach((item, i) => copy[i] = clone(item));
+        } else if (value instanceof Date) {
+            copy = new Date(value.getTime());
+        } else if (value instanceof RegExp) {
+            copy = new RegExp(value.source, value.flags);
+        } else if (value instanceof Map) {
+            copy = new Map();
+            seen.set(value, copy);
+            value.forEach((item, key) => copy.set(clone(key), clone(item)));
+        } else if (value instanceof Set) {
+            copy = new Set();
+            seen.set(value, copy);
+            value.forEach((item) => copy.add(clone(item)));
+        } else if (value instanceof ArrayBuffer) {
+            copy = value.slice(0);
+        } else if (ArrayBuffer.isView(value)) {
+            const length = value instanceof DataView ? value.byteLength : value.length;
+            copy = new value.constructor(clone(value.buffer), value.byteOffset, length);
+        } else {
+            copy = {};
+            seen.set(value, copy);
+            for (const key of Object.keys(value)) {
+                copy[key] = clone(value[key]);
+            }
+        }
+        seen.set(value, copy);
+        return copy;
+    };
+    return clone(value);
+};
+
+globalThis.deepEqual = function deepEqual(a, b) {
+    const seen = new Map();
+    const equal = (a, b) => {
+        if (Object.is(a, b)) {
+            return true;
+        }
+        if (a === null || b === null || typeof a !== "object" || typeof b !== "object") {
+            return false;
+        }
+        if (Object.getPrototypeOf(a) !== Object.getPrototypeOf(b)) {
+            return false;
+        }
+        if (seen.get(a) === b) {
+            return true;
+        }
+        seen.set(a, b);
+        if (a instanceof Date) {
+            return a.getTime() === b.getTime();
+        }
+        if (a instanceof RegExp) {
+            return a.source === b.source && a.flags === b.flags;
+        }
+        if (a instanceof Map) {
+            return a.size === b.size && [...a].every(([key, value]) => b.has(key) && equal(value, b.get(key)));
+        }
+        if (a instanceof Set) {
+            if (a.size !== b.size) {
+                return false;
+            }
+            // each member matches a distinct member of `b`, the same value if `b` has it or else one equal to it
+            const unmatched = [...b];
+            return [...a].every((value) => {
+                let index = unmatched.findIndex((other) => Object.is(value, other));
+                if (index === -1) {
+                    index = unmatched.findIndex((other) => attempt(value, other));
+                }
+                if (index === -1) {
+                    return false;
+                }
+                unmatched.splice(index, 1);
+                return true;
+            });
+        }
+        if (a instanceof ArrayBuffer) {
+            return equal(new Uint8Array(a), new Uint8Array(b));
+        }
+        const keys = Object.keys(a);
+        return keys.length === Object.keys(b).length
+            && keys.every((key) => Object.prototype.hasOwnProperty.call(b, key) && equal(a[key], b[key]));
+    };
+    // compares a candidate pairing, forgetting the pairs it visited if it does not match
+    const attempt = (a, b) => {
+        const visited = new Map(seen);
+        if (equal(a, b)) {
+            return true;
+        }
+        seen.clear();
+        visited.forEach((value, key) => seen.set(key, value));
+        return false;
+    };
+    return equal(a, b);
+};
+"#;
+
+static MODULE_RESULT_NAME: &str = "module-result.js";
+
+/// settles the default export of the module `{name}` into a global read by `MODULE_RESULT_VALUE`. importing the
+/// namespace rather than the default export means a module without a default export results in `undefined`. this is
+/// the only import the host makes; scripts have no other modules to import.
+static MODULE_RESULT: &str = r#"
+import * as module from "{name}";
+Promise.resolve(module.default).then(
+    (value) => { globalThis.__moduleResult = { value }; },
+    (error) => { globalThis.__moduleResult = { error }; },
+);
+"#;
+
+static MODULE_RESULT_VALUE_NAME: &str = "module-result-value.js";
+
+/// returns the settled default export, throwing if it rejected, and removes it from the global object.
+static MODULE_RESULT_VALUE: &str = r#"
+(() => {
+    const result = globalThis.__moduleResult;
+    delete globalThis.__moduleResult;
+    if (result === undefined) {
+        throw new Error("default export did not settle");
+    }
+    if ("error" in result) {
+        throw result.error;
+    }
+    return result.value;
+})()
+"#;
+
+static INVOKE_SCRIPT_NAME: &str = "invoke.js";
+
+static GUARD_SCRIPT_NAME: &str = "guard.js";
+
+/// returns a function which evaluates the guard source passed to it in the global scope and returns why it rejected
+/// the input data, or `null` if it evaluated to `true`. `InternalError`s, e.g. running out of memory or stack, are
+/// failures of the execution rather than rejections so are rethrown, as is an interruption, which cannot be caught.
+static GUARD: &str = r#"
+((source) => {
+    let result;
+    try {
+        result = (0, eval)(source);
+    } catch (err) {
+        if (err instanceof InternalError) {
+            throw err;
+        }
+        return String(err);
+    }
+    if (result === true) {
+        return null;
+    }
+    return typeof result === "boolean" ? "guard returned false" : "guard did not return a boolean";
+})
+"#;
+
+static FREEZE_DATA_SCRIPT_NAME: &str = "freeze-data.js";
+
+/// returns a function which deeply freezes the global named by its argument and prevents it from being reassigned.
+static FREEZE_DATA: &str = r#"
+((name) => {
+    const freeze = (value) => {
+        if (value !== null && typeof value === "object" && !Object.isFrozen(value)) {
+            Object.freeze(value);
+            Object.values(value).forEach(freeze);
+        }
+    };
+    freeze(globalThis[name]);
+    Object.defineProperty(globalThis, name, { writable: false, configurable: false });
+})
+"#;
+
+static LIMIT_LENGTHS_SCRIPT_NAME: &str = "limit-lengths.js";
+
+/// throws a catchable `RangeError` from the builtins which create long strings or arrays once the result would exceed
+/// `{max_string_length}` characters or `{max_array_length}` elements, before the memory is allocated where the length
+/// is known up front. only the builtins are wrapped, in place so the globals keep their identity, which makes this a
+/// diagnostic and not a security boundary: `+`, the `Array` constructor, assigning `length`, typed arrays and any
+/// builtin not listed are not limited, and the memory limit still bounds everything.
+static LIMIT_LENGTHS: &str = r#"
+(() => {
+    const maxString = {max_string_length};
+    const maxArray = {max_array_length};
+    const checkString = (length) => {
+        if (length > maxString) {
+            throw new RangeError(`string length exceeds the maximum of ${maxString}`);
+        }
+    };
+    const checkArray = (length) => {
+        if (length > maxArray) {
+            throw new RangeError(`array length exceeds the maximum of ${maxArray}`);
+        }
+    };
+    const patch = (object, name, wrapper) => {
+        const original = object[name];
+        Object.defineProperty(object, name, {
+            value: { [name](...args) { return wrapper.call(this, original, args); } }[name],
+            writable: true,
+            configurable: true,
+        });
+    };
+    const checked = (check) => function (original, args) {
+        const result = original.apply(this, args);
+        check(result.length);
+        return result;
+    };
+
+    if (maxString !== Infinity) {
+        patch(String.prototype, "repeat", function (repeat, args) {
+            checkString(String(this).length * Math.trunc(args[0]));
+            return repeat.apply(this, args);
+        });
+        for (const name of ["padStart", "padEnd"]) {
+            patch(String.prototype, name, function (pad, args) {
+                checkString(Number(args[0]));
+                return pad.apply(this, args);
+            });
+        }
+        patch(String.prototype, "concat", checked(checkString));
+        patch(Array.prototype, "join", checked(checkString));
+    }
+
+    if (maxArray !== Infinity) {
+        patch(Array, "from", function (from, args) {
+            if (args[0] != null && typeof args[0].length === "number") {
+                checkArray(args[0].length);
+            }
+            return checked(checkArray).call(this, from, args);
+        });
+        for (const name of ["push", "unshift"]) {
+            patch(Array.prototype, name, function (add, args) {
+                checkArray(this.length + args.length);
+                return add.apply(this, args);
+            });
+        }
+        patch(Array.prototype, "fill", function (fill, args) {
+            checkArray(this.length);
+            return fill.apply(this, args);
+        });
+        patch(Array.prototype, "concat", checked(checkArray));
+    }
+})();
+"#;
+
+static TRACK_GLOBALS_SCRIPT_NAME: &str = "track-globals.js";
+
+/// snapshots the enumerable globals and returns a function listing, as JSON, those defined, reassigned or deleted since.
+static TRACK_GLOBALS: &str = r#"
+(() => {
+    const snapshot = new Map(Object.entries(globalThis));
+    return () => {
+        const changed = Object.keys(globalThis)
+            .filter((name) => !snapshot.has(name) || !Object.is(snapshot.get(name), globalThis[name]));
+        const deleted = [...snapshot.keys()].filter((name) => !Object.prototype.hasOwnProperty.call(globalThis, name));
+        return JSON.stringify([...changed, ...deleted]);
+    };
+})()
+"#;
+
+static ABORT_SCRIPT_NAME: &str = "abort.js";
+
+/// `AbortController` and `AbortSignal` for scripts which stop cooperatively. every signal is also aborted once the host
+/// cancels the execution, which is read from the `__isCancelled` callback and removed from the global object.
+static ABORT: &str = r#"
+(() => {
+    const isCancelled = globalThis.__isCancelled;
+    delete globalThis.__isCancelled;
+
+    const abort = (signal, reason) => {
+        if (signal._aborted) {
+            return;
+        }
+        signal._aborted = true;
+        signal._reason = reason;
+        if (typeof signal.onabort === "function") {
+            signal.onabort({ type: "abort", target: signal });
+        }
+    };
+
+    class AbortSignal {
+        constructor() {
+            this._aborted = false;
+            this._reason = undefined;
+            this.onabort = null;
+        }
+        get aborted() {
+            if (!this._aborted && isCancelled()) {
+                abort(this, new Error("execution cancelled"));
+            }
+            return this._aborted;
+        }
+        get reason() {
+            return this.aborted ? this._reason : undefined;
+        }
+        throwIfAborted() {
+            if (this.aborted) {
+                throw this.reason;
+            }
+        }
+        static abort(reason = new Error("signal is aborted without reason")) {
+            const signal = new AbortSignal();
+            abort(signal, reason);
+            return signal;
+        }
+    }
+
+    class AbortController {
+        constructor() {
+            this.signal = new AbortSignal();
+        }
+        abort(reason = new Error("signal is aborted without reason")) {
+            abort(this.signal, reason);
+        }
+    }
+
+    globalThis.AbortSignal = AbortSignal;
+    globalThis.AbortController = AbortController;
+})();
+"#;
+
+thread_local! {
+    /// xorshift64* state backing `Math.random`. captured by the wizer snapshot and reseeded on every execution.
+    static RANDOM_STATE: Cell<u64> = const { Cell::new(0) };
+}
+
 /// set quickjs globals
 pub fn set_quickjs_globals(context: &JSContextRef) -> anyhow::Result<()> {
-    let console_log_callback = context.wrap_callback(console_log_to(std::io::stdout()))?;
-    let console_error_callback = context.wrap_callback(console_log_to(std::io::stderr()))?;
+    let global = context.global_object()?;
+
+    #[cfg(feature = "console")]
+    {
+        let console_log_callback = context.wrap_callback(console_log_to(std::io::stdout(), 0))?;
+        let console_error_callback = context.wrap_callback(console_log_to(std::io::stderr(), 1))?;
+
+        let console_object = context.object_value()?;
+        console_object.set_property("log", console_log_callback)?;
+        console_object.set_property("error", console_error_callback)?;
+
+        global.set_property("console", console_object)?;
+    }
 
-    let console_object = context.object_value()?;
-    console_object.set_property("log", console_log_callback)?;
-    console_object.set_property("error", console_error_callback)?;
+    let emit_callback = context.wrap_callback(emit)?;
+    global.set_property("emit", emit_callback)?;
 
+    let set_binary_output_callback = context.wrap_callback(set_binary_output)?;
+    global.set_property("setBinaryOutput", set_binary_output_callback)?;
+
+    let read_line_callback = context.wrap_callback(read_line)?;
+    global.set_property("readLine", read_line_callback)?;
+
+    let gc_callback = context.wrap_callback(gc)?;
+    global.set_property("gc", gc_callback)?;
+
+    let exit_callback = context.wrap_callback(exit)?;
+    global.set_property("exit", exit_callback)?;
+
+    let math_random_callback = context.wrap_callback(math_random)?;
+    global
+        .get_property("Math")?
+        .set_property("random", math_random_callback)?;
+    context.eval_global(CRYPTO_SCRIPT_NAME, CRYPTO)?;
+    context.eval_global(BASE64_SCRIPT_NAME, BASE64)?;
+
+    let is_cancelled_callback = context.wrap_callback(is_cancelled)?;
+    global.set_property("__isCancelled", is_cancelled_callback)?;
+    context.eval_global(ABORT_SCRIPT_NAME, ABORT)?;
+
+    #[cfg(feature = "structured-clone")]
+    context.eval_global(STRUCTURED_CLONE_SCRIPT_NAME, STRUCTURED_CLONE)?;
+
+    Ok(())
+}
+
+/// seed_random seeds the generator behind `Math.random` and `crypto.getRandomValues`.
+///
+/// if no seed is provided the generator is seeded from the WASI entropy source so that every execution
+/// produces a different sequence.
+pub fn seed_random(seed: Option<u64>) -> Result<()> {
+    let seed = match seed {
+        Some(seed) => seed,
+        None => {
+            let mut bytes = [0u8; 8];
+            getrandom::getrandom(&mut bytes).map_err(|err| anyhow!("{err}"))?;
+            u64::from_le_bytes(bytes)
+        }
+    };
+
+    // splitmix64 so that adjacent seeds produce unrelated sequences and the state is never zero
+    let mut state = seed.wrapping_add(0x9E3779B97F4A7C15);
+    state = (state ^ (state >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    state = (state ^ (state >> 27)).wrapping_mul(0x94D049BB133111EB);
+    state ^= state >> 31;
+
+    RANDOM_STATE.with(|random_state| random_state.set(state.max(1)));
+    Ok(())
+}
+
+/// global_names returns the names of the enumerable own properties of the global object.
+pub fn global_names(context: &JSContextRef) -> Result<Vec<String>> {
+    let mut properties = context.global_object()?.properties()?;
+    let mut names = Vec::new();
+    while let Some(key) = properties.next_key()? {
+        names.push(key.as_str()?.to_string());
+    }
+
+    Ok(names)
+}
+
+/// define_host_functions defines a global for each host function the current execution may call.
+///
+/// host functions the execution may not call are left undefined so calling them throws a `ReferenceError`.
+pub fn define_host_functions(context: &JSContextRef, names: &[String]) -> Result<()> {
+    let global = context.global_object()?;
+    for name in names {
+        let function_name = name.clone();
+        let callback = context.wrap_callback(
+            move |ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+                from_qjs_value(io::invoke_host_function(ctx, &function_name, args)?)
+            },
+        )?;
+        global.set_property(name.as_str(), callback)?;
+    }
+
+    Ok(())
+}
+
+/// module_default_export returns the default export of the evaluated module `name`, or `undefined` if it has none.
+///
+/// a promise is awaited by running pending jobs until none remain, so a module can export the result of async work.
+pub fn module_default_export<'a>(context: &'a JSContextRef, name: &str) -> Result<JSValueRef<'a>> {
+    context.eval_module(MODULE_RESULT_NAME, &MODULE_RESULT.replace("{name}", name))?;
+    runtime::drain_jobs(context)?;
+    context.eval_global(MODULE_RESULT_VALUE_NAME, MODULE_RESULT_VALUE)
+}
+
+/// invoke calls the function `name` defined by the script with `params` as its only argument and returns its result.
+///
+/// the function is looked up by evaluating its name in the global scope so functions declared with `const` or `let`
+/// are found as well as function declarations.
+pub fn invoke<'a>(
+    context: &'a JSContextRef,
+    name: &str,
+    params: JSValueRef<'a>,
+) -> Result<JSValueRef<'a>> {
+    let function = context.eval_global(INVOKE_SCRIPT_NAME, name)?;
+    if !function.is_function() {
+        bail!("TypeError: {name} is not a function");
+    }
+    function.call(&context.undefined_value()?, &[params])
+}
+
+/// define_load_data defines the `loadData(name)` global which synchronously requests a dataset from the host.
+pub fn define_load_data(context: &JSContextRef) -> Result<()> {
+    let load_data_callback = context.wrap_callback(load_data)?;
+    context
+        .global_object()?
+        .set_property("loadData", load_data_callback)?;
+
+    Ok(())
+}
+
+/// define_shared_cache defines the `cacheGet(key)` global which reads the cache the host shares across executions,
+/// and `cacheSet(key, value)` if scripts may write to it.
+pub fn define_shared_cache(context: &JSContextRef, writable: bool) -> Result<()> {
     let global = context.global_object()?;
-    global.set_property("console", console_object)?;
+    global.set_property("cacheGet", context.wrap_callback(cache_get)?)?;
+    if writable {
+        global.set_property("cacheSet", context.wrap_callback(cache_set)?)?;
+    }
+
+    Ok(())
+}
+
+/// track_globals snapshots the globals and returns a function which lists the globals changed since as JSON.
+pub fn track_globals(context: &JSContextRef) -> Result<JSValueRef> {
+    context.eval_global(TRACK_GLOBALS_SCRIPT_NAME, TRACK_GLOBALS)
+}
+
+/// changed_globals calls the function returned by `track_globals`, returning the JSON array of changed globals.
+pub fn changed_globals(context: &JSContextRef, tracker: JSValueRef) -> Result<String> {
+    Ok(tracker
+        .call(&context.undefined_value()?, &[])?
+        .as_str()?
+        .to_string())
+}
+
+/// limit_lengths limits the length of the strings and arrays created by the builtins which can allocate enormous
+/// values, e.g. `'x'.repeat(n)` or `new Array(n).fill(0)`, throwing a catchable `RangeError` rather than exhausting
+/// memory. this is best-effort: see `LIMIT_LENGTHS` for what is not limited.
+pub fn limit_lengths(
+    context: &JSContextRef,
+    max_string_length: Option<u32>,
+    max_array_length: Option<u32>,
+) -> Result<()> {
+    let limit = |max: Option<u32>| max.map_or("Infinity".to_string(), |max| max.to_string());
+    context.eval_global(
+        LIMIT_LENGTHS_SCRIPT_NAME,
+        &LIMIT_LENGTHS
+            .replace("{max_string_length}", &limit(max_string_length))
+            .replace("{max_array_length}", &limit(max_array_length)),
+    )?;
+    Ok(())
+}
+
+/// guard evaluates the guard script in the global scope so the functions it defines remain available to the script.
+///
+/// returns why the input data was rejected: the exception the guard threw, or its result if it was not `true`. an
+/// error is returned rather than a rejection if the execution failed while the guard ran, e.g. it was interrupted.
+pub fn guard(context: &JSContextRef, guard: &str) -> Result<Option<String>> {
+    let check = context.eval_global(GUARD_SCRIPT_NAME, GUARD)?;
+    let reason = check.call(
+        &context.undefined_value()?,
+        &[context.value_from_str(guard)?],
+    )?;
+    Ok(match reason.is_null() {
+        true => None,
+        false => Some(reason.as_str()?.to_string()),
+    })
+}
 
+/// freeze_data makes the data global `name` read-only so scripts cannot corrupt input shared across a pipeline.
+///
+/// the name is passed to the script as a value rather than spliced into its source so it cannot inject code.
+pub fn freeze_data(context: &JSContextRef, name: &str) -> Result<()> {
+    let freeze = context.eval_global(FREEZE_DATA_SCRIPT_NAME, FREEZE_DATA)?;
+    freeze.call(
+        &context.undefined_value()?,
+        &[context.value_from_str(name)?],
+    )?;
     Ok(())
 }
 
+/// emit sends a value to the host as one of many outputs. used by scripts which produce a stream of records.
+///
+/// the guest does not know whether the host collects emitted values so always sends them; hosts which do not collect
+/// them discard them.
+fn emit(ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]) -> Result<JSValue> {
+    match args {
+        [value] => io::set_emitted_value(io::prepare_output(ctx, *value)?)?,
+        _ => bail!("emit expects 1 argument but received {}", args.len()),
+    }
+
+    Ok(JSValue::Undefined)
+}
+
+/// set_binary_output sends raw bytes, e.g. a generated file, to the host with an optional content type.
+///
+/// accepts an `ArrayBuffer`, a typed array or `DataView` (only its view of the buffer is sent) or a string which is
+/// sent as UTF-8.
+fn set_binary_output(
+    _ctx: &JSContextRef,
+    _this: JSValueRef,
+    args: &[JSValueRef],
+) -> Result<JSValue> {
+    let (value, content_type) = match args {
+        [value] => (value, None),
+        [value, content_type] if content_type.is_null_or_undefined() => (value, None),
+        [value, content_type] => (value, Some(content_type.as_str()?)),
+        _ => bail!(
+            "setBinaryOutput expects 1 or 2 arguments but received {}",
+            args.len()
+        ),
+    };
+
+    if value.is_str() {
+        io::set_binary_value(value.as_str()?.as_bytes(), content_type);
+    } else if value.is_array_buffer() {
+        io::set_binary_value(value.as_bytes()?, content_type);
+    } else {
+        let buffer = value.get_property("buffer")?;
+        if !buffer.is_array_buffer() {
+            bail!("setBinaryOutput expects an ArrayBuffer, a typed array or a string");
+        }
+        // any object with a `buffer` is accepted so its view of the buffer is checked rather than trusted
+        let view = |name: &str| -> Result<usize> {
+            let property = value.get_property(name)?;
+            match property.is_repr_as_i32() {
+                true => Ok(usize::try_from(property.as_i32_unchecked())?),
+                false => bail!("setBinaryOutput expects {name} to be a non-negative integer"),
+            }
+        };
+        let offset = view("byteOffset")?;
+        let length = view("byteLength")?;
+        let bytes = buffer.as_bytes()?;
+        let Some(bytes) = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+        else {
+            bail!("setBinaryOutput view is outside its buffer");
+        };
+        io::set_binary_value(bytes, content_type);
+    }
+
+    Ok(JSValue::Undefined)
+}
+
+/// load_data returns the dataset named by its argument, resolved by the host.
+fn load_data(ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]) -> Result<JSValue> {
+    match args {
+        [name] if name.is_str() => from_qjs_value(io::load_dataset(ctx, name.as_str()?)?),
+        _ => bail!("loadData expects a dataset name"),
+    }
+}
+
+/// cache_get returns the value stored under its argument in the shared cache, or `undefined` if there is none.
+fn cache_get(ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]) -> Result<JSValue> {
+    match args {
+        [key] if key.is_str() => match io::get_cached(ctx, key.as_str()?)? {
+            Some(value) => from_qjs_value(value),
+            None => Ok(JSValue::Undefined),
+        },
+        _ => bail!("cacheGet expects a key"),
+    }
+}
+
+/// cache_set stores its second argument under the key given as its first in the shared cache.
+fn cache_set(_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]) -> Result<JSValue> {
+    match args {
+        [key, value] if key.is_str() && !value.is_undefined() => {
+            io::set_cached(key.as_str()?, *value)?
+        }
+        _ => bail!("cacheSet expects a key and a value"),
+    }
+
+    Ok(JSValue::Undefined)
+}
+
+/// gc runs the garbage collector on demand so scripts processing large batches can free memory between chunks.
+fn gc(ctx: &JSContextRef, _this: JSValueRef, _args: &[JSValueRef]) -> Result<JSValue> {
+    runtime::run_gc(ctx);
+
+    Ok(JSValue::Undefined)
+}
+
+/// exit ends the execution immediately with the exit code passed to it (default `0`) by calling `proc_exit`. the host
+/// treats `0` as success, keeping anything already emitted, and any other code as an error.
+fn exit(_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]) -> Result<JSValue> {
+    let code = match args {
+        [] => 0,
+        [code, ..] if code.is_undefined() => 0,
+        [code, ..] if code.is_repr_as_i32() => code.as_i32_unchecked(),
+        _ => bail!("exit expects an integer exit code"),
+    };
+
+    std::process::exit(code)
+}
+
+/// read_line reads the next line of standard input without its line ending, or `null` once it is exhausted.
+fn read_line(_ctx: &JSContextRef, _this: JSValueRef, _args: &[JSValueRef]) -> Result<JSValue> {
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+        return Ok(JSValue::Null);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+
+    Ok(JSValue::String(line))
+}
+
+/// is_cancelled reads whether the host has cancelled the execution. backs the `aborted` flag of `AbortSignal`.
+fn is_cancelled(_ctx: &JSContextRef, _this: JSValueRef, _args: &[JSValueRef]) -> Result<JSValue> {
+    Ok(JSValue::Bool(io::execution_cancelled()))
+}
+
+/// math_random replaces `Math.random` with a xorshift64* generator returning a float in `[0, 1)`.
+fn math_random(_ctx: &JSContextRef, _this: JSValueRef, _args: &[JSValueRef]) -> Result<JSValue> {
+    let value = RANDOM_STATE.with(|random_state| {
+        let mut x = random_state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        random_state.set(x);
+        x.wrapping_mul(0x2545F4914F6CDD1D)
+    });
+
+    // use the top 53 bits to fill the f64 mantissa
+    Ok(JSValue::Float((value >> 11) as f64 / (1u64 << 53) as f64))
+}
+
 /// console_log_to is used to allow the javascript functions console.log and console.error to
 /// log to the stdout and stderr respectively.
+///
+/// if live console is enabled the line is sent to the host instead, identified by `stream_id`.
+#[cfg(feature = "console")]
 fn console_log_to<T>(
     mut stream: T,
+    stream_id: i32,
 ) -> impl FnMut(&JSContextRef, JSValueRef, &[JSValueRef]) -> Result<JSValue>
 where
     T: Write + 'static,
@@ -37,7 +817,11 @@ where
             log_line.push_str(&line);
         }
 
-        writeln!(stream, "{log_line}")?;
+        if io::live_console() {
+            io::send_console_line(&log_line, stream_id);
+        } else {
+            writeln!(stream, "{log_line}")?;
+        }
 
         Ok(JSValue::Undefined)
     }
@@ -0,0 +1,43 @@
+use serde::Serialize;
+
+/// a problem found in a script without running it. serialized to the host's `Diagnostic`.
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub kind: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub severity: &'static str,
+}
+
+/// diagnose converts an error from compiling a script into a diagnostic.
+///
+/// the quickjs parser stops at the first error so at most one diagnostic is returned. the error is reported as
+/// `Uncaught <kind>: <message>` followed by a stack containing `<script name>:<line>[:<column>]`.
+pub fn diagnose(error: &anyhow::Error, script_name: &str) -> Diagnostic {
+    let error = error.to_string();
+    let mut lines = error.lines();
+
+    let summary = lines.next().unwrap_or_default();
+    let summary = summary.strip_prefix("Uncaught ").unwrap_or(summary);
+    let (kind, message) = summary.split_once(": ").unwrap_or(("Error", summary));
+
+    let mut position = lines
+        .find_map(|line| line.split_once(&format!("{script_name}:")))
+        .map(|(_, position)| {
+            position
+                .split(|c: char| !c.is_ascii_digit())
+                .map_while(|number| number.parse::<u32>().ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default()
+        .into_iter();
+
+    Diagnostic {
+        message: message.to_string(),
+        kind: kind.to_string(),
+        line: position.next(),
+        column: position.next(),
+        severity: "error",
+    }
+}
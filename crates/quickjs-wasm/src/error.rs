@@ -0,0 +1,51 @@
+use std::fmt::{self, Display};
+
+/// error code for an exception thrown by the script
+pub const ERROR_SCRIPT: i32 = 1;
+/// error code for input data which is not valid JSON. the message is a JSON object with the parse position.
+pub const ERROR_INVALID_INPUT_DATA: i32 = 3;
+/// error code for input data nested deeper than the configured maximum input depth
+pub const ERROR_INPUT_TOO_DEEP: i32 = 4;
+/// error code for output containing a circular reference. the message is the path of the reference.
+pub const ERROR_CIRCULAR_REFERENCE: i32 = 5;
+/// error code for a script interrupted by the host's time limit. the message is the stack at the interruption.
+pub const ERROR_TIME_LIMIT_EXCEEDED: i32 = 6;
+/// error code for input data rejected by the guard script. the message is the reason it was rejected.
+pub const ERROR_GUARD_REJECTED: i32 = 7;
+
+/// GuestError is an error reported to the host with a specific error code so it can be mapped to a
+/// typed error rather than being treated as an exception thrown by the script.
+#[derive(Debug)]
+pub struct GuestError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl GuestError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for GuestError {
+    /// converts a JSON parse error of the input data into an `ERROR_INVALID_INPUT_DATA` error
+    fn from(err: serde_json::Error) -> Self {
+        let message = serde_json::json!({
+            "line": err.line(),
+            "column": err.column(),
+            "message": err.to_string(),
+        });
+        Self::new(ERROR_INVALID_INPUT_DATA, message.to_string())
+    }
+}
+
+impl Display for GuestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for GuestError {}
@@ -1,13 +1,43 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use quickjs_wasm_rs::{Deserializer, JSContextRef, JSValueRef, Serializer};
+use serde::{
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Serialize,
+};
+use std::{
+    cell::{Cell, RefCell},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{GuestError, ERROR_CIRCULAR_REFERENCE, ERROR_INPUT_TOO_DEEP, ERROR_SCRIPT},
+    options::Options,
+};
 
 #[link(wasm_import_module = "host")]
 extern "C" {
     fn get_script(ptr: i32);
     fn get_script_size() -> i32;
+    fn read_script_chunk(ptr: i32, capacity: i32) -> i32;
     fn get_data(ptr: i32);
     fn get_data_size() -> i32;
+    fn get_options(ptr: i32);
+    fn get_options_size() -> i32;
     fn set_output(ptr: i32, size: i32, error: i32);
+    fn set_abort_message(ptr: i32, size: i32);
+    fn next_job() -> i32;
+    fn set_binary_output(ptr: i32, size: i32, content_type_ptr: i32, content_type_size: i32);
+    fn is_cancelled() -> i32;
+    fn is_timed_out() -> i32;
+    fn console_line(ptr: i32, size: i32, stream: i32);
+    fn call_host_function(name_ptr: i32, name_size: i32, args_ptr: i32, args_size: i32) -> i32;
+    fn get_host_function_result(ptr: i32);
+    fn load_data(name_ptr: i32, name_size: i32) -> i32;
+    fn cache_get(key_ptr: i32, key_size: i32) -> i32;
+    fn cache_set(key_ptr: i32, key_size: i32, value_ptr: i32, value_size: i32);
+    fn set_changed_globals(ptr: i32, size: i32);
+    fn set_timings(input_transcode: i64, evaluation: i64, output_transcode: i64);
+    fn set_max_pending_jobs(jobs: i32);
 }
 
 /// Transcodes a byte slice containing a JSON encoded payload into a [`JSValueRef`].
@@ -16,24 +46,490 @@ extern "C" {
 /// * `context` - A reference to the [`JSContextRef`] that will contain the
 ///   returned [`JSValueRef`].
 /// * `bytes` - A byte slice containing a JSON encoded payload.
+///
+/// Malformed JSON returns a [`GuestError`] with the position at which parsing failed.
+///
+/// The parser's recursion limit is disabled so the caller must check the depth with `exceeds_depth` first.
 pub fn transcode_input<'a>(context: &'a JSContextRef, bytes: &[u8]) -> Result<JSValueRef<'a>> {
+    timed(Phase::InputTranscode, || {
+        transcode_input_untimed(context, bytes)
+    })
+}
+
+fn transcode_input_untimed<'a>(context: &'a JSContextRef, bytes: &[u8]) -> Result<JSValueRef<'a>> {
     let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    deserializer.disable_recursion_limit();
     let mut serializer = Serializer::from_context(context)?;
-    serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+    if let Err(err) = serde_transcode::transcode(&mut deserializer, &mut serializer) {
+        // the transcoded error loses the parse position so validate the input again to recover it
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        deserializer.disable_recursion_limit();
+        return match serde::de::IgnoredAny::deserialize(&mut deserializer) {
+            Err(err) => Err(GuestError::from(err).into()),
+            Ok(_) => Err(err.into()),
+        };
+    }
+    // reject trailing characters after the value
+    deserializer.end().map_err(GuestError::from)?;
     Ok(serializer.value)
 }
 
-/// Transcodes a [`JSValueRef`] into a JSON encoded byte vector.
-pub fn transcode_output(val: JSValueRef) -> Result<Vec<u8>> {
+/// Transcodes a [`JSValueRef`] into a JSON encoded byte vector. indented if `set_pretty_output` is enabled.
+///
+/// arrays and objects nested deeper than `MAX_OUTPUT_DEPTH` are rejected, which is how a circular reference is
+/// detected without tracking every object visited. a circular reference returns an `ERROR_CIRCULAR_REFERENCE` error.
+pub fn transcode_output<'a>(context: &'a JSContextRef, value: JSValueRef<'a>) -> Result<Vec<u8>> {
+    timed(Phase::OutputTranscode, || {
+        let too_deep = Cell::new(false);
+        let output = OutputValue {
+            context,
+            value,
+            depth: 0,
+            too_deep: &too_deep,
+        };
+        match transcode_output_untimed(&output) {
+            Err(_) if too_deep.get() => Err(too_deep_error(context, value)),
+            output => output,
+        }
+    })
+}
+
+fn transcode_output_untimed(value: &OutputValue) -> Result<Vec<u8>> {
     let mut output = Vec::new();
-    let mut deserializer = Deserializer::from(val);
-    let mut serializer = serde_json::Serializer::new(&mut output);
-    serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+    if PRETTY_OUTPUT.get() {
+        value.serialize(&mut serde_json::Serializer::pretty(&mut output))?;
+    } else {
+        value.serialize(&mut serde_json::Serializer::new(&mut output))?;
+    }
     Ok(output)
 }
 
-/// gets the script from the host as a string
-pub fn get_input_script() -> Result<Option<String>> {
+/// the deepest nesting of arrays and objects in an output value. serializing recurses once per level so deeper
+/// values, including every value with a circular reference, are rejected rather than overflowing the stack.
+const MAX_OUTPUT_DEPTH: u32 = 1000;
+
+/// an output value serialized to JSON by `transcode_output`.
+///
+/// arrays and objects are walked here rather than by `Deserializer` so their depth can be bounded, and `BigInt`s are
+/// marked here if big integers are enabled. other values are transcoded by `Deserializer`.
+struct OutputValue<'a, 'b> {
+    context: &'a JSContextRef,
+    value: JSValueRef<'a>,
+    /// the number of arrays and objects `value` is nested in
+    depth: u32,
+    /// set once a value nested deeper than `MAX_OUTPUT_DEPTH` is reached
+    too_deep: &'b Cell<bool>,
+}
+
+impl<'a, 'b> OutputValue<'a, 'b> {
+    fn nested(&self, value: JSValueRef<'a>) -> Self {
+        Self {
+            context: self.context,
+            value,
+            depth: self.depth + 1,
+            too_deep: self.too_deep,
+        }
+    }
+}
+
+impl Serialize for OutputValue<'_, '_> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::Error;
+
+        let value = self.value;
+        if (value.is_array() || value.is_object()) && self.depth >= MAX_OUTPUT_DEPTH {
+            self.too_deep.set(true);
+            return Err(S::Error::custom("output is nested too deeply"));
+        }
+
+        if value.is_array() {
+            let length = value
+                .get_property("length")
+                .map_err(S::Error::custom)?
+                .as_u32_unchecked();
+            let mut seq = serializer.serialize_seq(Some(length as usize))?;
+            for index in 0..length {
+                let element = value
+                    .get_indexed_property(index)
+                    .map_err(S::Error::custom)?;
+                seq.serialize_element(&self.nested(element))?;
+            }
+            seq.end()
+        } else if value.is_object() {
+            let mut map = serializer.serialize_map(None)?;
+            let mut properties = value.properties().map_err(S::Error::custom)?;
+            while let Some(key) = properties.next_key().map_err(S::Error::custom)? {
+                let name = property_name(key).map_err(S::Error::custom)?;
+                let property = value
+                    .get_property(name.as_str())
+                    .map_err(S::Error::custom)?;
+                map.serialize_entry(&name, &self.nested(property))?;
+            }
+            map.end()
+        } else if value.is_big_int() && BIG_INTEGERS.get() {
+            // the host writes the marked string as an integer with all of its digits
+            let digits = self
+                .context
+                .global_object()
+                .and_then(|global| global.get_property("String"))
+                .and_then(|string| string.call(&self.context.undefined_value()?, &[value]))
+                .map_err(S::Error::custom)?;
+            let digits = digits.as_str().map_err(S::Error::custom)?;
+            serializer.serialize_str(&format!("{BIG_INTEGER_MARKER}{digits}"))
+        } else {
+            let mut deserializer = Deserializer::from(value);
+            serde_transcode::Transcoder::new(&mut deserializer).serialize(serializer)
+        }
+    }
+}
+
+/// returns the name of an object property, which quickjs reports as a number for integer keys.
+fn property_name(key: JSValueRef) -> Result<String> {
+    match key.is_repr_as_i32() {
+        true => Ok(key.as_i32_unchecked().to_string()),
+        false => Ok(key.as_str()?.to_string()),
+    }
+}
+
+/// the error of an output value nested deeper than `MAX_OUTPUT_DEPTH`, found while filtering or serializing it
+#[derive(Debug)]
+struct OutputTooDeep;
+
+impl std::fmt::Display for OutputTooDeep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "output exceeds maximum depth of {MAX_OUTPUT_DEPTH}")
+    }
+}
+
+impl std::error::Error for OutputTooDeep {}
+
+/// returns the error of an output value nested deeper than `MAX_OUTPUT_DEPTH`: an `ERROR_CIRCULAR_REFERENCE` error
+/// with the path of the reference if it has one, otherwise `OutputTooDeep`.
+///
+/// finding the path walks the value in javascript so is only done once the value is known to be too deep.
+fn too_deep_error(context: &JSContextRef, value: JSValueRef) -> anyhow::Error {
+    let path = context
+        .eval_global(FIND_CYCLE_SCRIPT_NAME, FIND_CYCLE)
+        .and_then(|find_cycle| find_cycle.call(&context.undefined_value()?, &[value]));
+    match path {
+        Ok(path) if path.is_undefined() => OutputTooDeep.into(),
+        Ok(path) => match path.as_str() {
+            Ok(path) => GuestError::new(ERROR_CIRCULAR_REFERENCE, path).into(),
+            Err(err) => err,
+        },
+        // the walk recurses in javascript so may overflow the stack for a value this deep without a cycle
+        Err(_) => OutputTooDeep.into(),
+    }
+}
+
+/// the phases of an execution which are timed and reported to the host with `send_timings`
+#[derive(Clone, Copy)]
+pub enum Phase {
+    InputTranscode,
+    Evaluation,
+    OutputTranscode,
+}
+
+/// runs `f`, adding the time it takes to the total for `phase`
+pub fn timed<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let output = f();
+    let mut timings = TIMINGS.get();
+    timings[phase as usize] += start.elapsed();
+    TIMINGS.set(timings);
+    output
+}
+
+/// sends the total time spent in each phase to the host
+pub fn send_timings() {
+    let [input_transcode, evaluation, output_transcode] =
+        TIMINGS.get().map(|timing| timing.as_nanos() as i64);
+    unsafe { set_timings(input_transcode, evaluation, output_transcode) };
+}
+
+/// sends the most jobs run by a single drain of the job queue to the host
+pub fn send_max_pending_jobs(jobs: u32) {
+    unsafe { set_max_pending_jobs(jobs as i32) };
+}
+
+/// sends the JSON array of the globals changed by the script to the host
+pub fn send_changed_globals(names: &str) {
+    unsafe { set_changed_globals(names.as_ptr() as i32, names.len() as i32) };
+}
+
+/// sets whether `transcode_output` produces indented JSON for the current execution
+pub fn set_pretty_output(pretty_output: bool) {
+    PRETTY_OUTPUT.set(pretty_output);
+}
+
+/// sets whether console lines are sent to the host as they are written for the current execution
+pub fn set_live_console(live_console: bool) {
+    LIVE_CONSOLE.set(live_console);
+}
+
+/// returns whether console lines are sent to the host with `send_console_line`
+#[cfg(feature = "console")]
+pub fn live_console() -> bool {
+    LIVE_CONSOLE.get()
+}
+
+/// sends a console line to the host. `stream` is 0 for stdout and 1 for stderr.
+#[cfg(feature = "console")]
+pub fn send_console_line(line: &str, stream: i32) {
+    unsafe { console_line(line.as_ptr() as i32, line.len() as i32, stream) };
+}
+
+thread_local! {
+    /// the time spent in each `Phase` of the execution
+    static TIMINGS: Cell<[Duration; 3]> = const { Cell::new([Duration::ZERO; 3]) };
+    /// whether output is indented. set from the options on every execution as `emit` has no access to them.
+    static PRETTY_OUTPUT: Cell<bool> = const { Cell::new(false) };
+    /// whether console lines are sent to the host as they are written rather than to stdout or stderr.
+    static LIVE_CONSOLE: Cell<bool> = const { Cell::new(false) };
+    /// the properties filtered from output. set from the options on every execution as `emit` has no access to them.
+    static OUTPUT_FILTER: RefCell<Option<OutputFilter>> = const { RefCell::new(None) };
+    /// the placeholder for values which cannot be serialized to JSON, or `None` if they are an error.
+    static OUTPUT_PLACEHOLDER: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// whether integers the host marks as too large for a number are bound as `BigInt`s, and `BigInt`s in the output
+    /// are marked for the host to write as integers.
+    static BIG_INTEGERS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// the prefix of the strings standing in for integers too large for a number, as exchanged with the host
+const BIG_INTEGER_MARKER: &str = "\u{0}bigint:";
+
+static REVIVE_BIG_INTEGERS_SCRIPT_NAME: &str = "revive-big-integers.js";
+
+/// replaces the strings the host marked as integers too large for a number in `value` with `BigInt`s, in place.
+static REVIVE_BIG_INTEGERS: &str = r#"
+((value) => {
+    const marker = "\u0000bigint:";
+    const revive = (value) => {
+        if (typeof value === "string" && value.startsWith(marker)) {
+            return BigInt(value.slice(marker.length));
+        }
+        if (value !== null && typeof value === "object") {
+            for (const key of Object.keys(value)) {
+                value[key] = revive(value[key]);
+            }
+        }
+        return value;
+    };
+    return revive(value);
+})
+"#;
+
+static FIND_CYCLE_SCRIPT_NAME: &str = "find-cycle.js";
+
+/// returns the path of the first circular reference in `value`, e.g. `$.node.parent`, or `undefined` if there is
+/// none. an object referenced more than once without being its own ancestor is not a cycle. only evaluated for
+/// output which is too deep to serialize, see `too_deep_error`.
+static FIND_CYCLE: &str = r#"
+((value) => {
+    const ancestors = [];
+    const find = (value, path) => {
+        if (value === null || typeof value !== "object") {
+            return undefined;
+        }
+        if (ancestors.includes(value)) {
+            return path;
+        }
+        ancestors.push(value);
+        const entries = Array.isArray(value)
+            ? value.map((item, index) => [`${path}[${index}]`, item])
+            : Object.keys(value).map((key) => [`${path}.${key}`, value[key]]);
+        for (const [path, item] of entries) {
+            const cycle = find(item, path);
+            if (cycle !== undefined) {
+                return cycle;
+            }
+        }
+        ancestors.pop();
+        return undefined;
+    };
+    return find(value, "$");
+})
+"#;
+
+static SANITIZE_OUTPUT_SCRIPT_NAME: &str = "sanitize-output.js";
+
+/// returns a copy of `value` with the values which cannot be serialized to JSON (`BigInt`, unless `bigIntegers` is
+/// set, symbols, functions and circular references) replaced by `null` or, if `placeholder` is `"description"`, an
+/// object describing the value. objects and arrays are only copied if something inside them is replaced so other
+/// values serialize as before.
+static SANITIZE_OUTPUT: &str = r#"
+((value, placeholder, bigIntegers) => {
+    const ancestors = [];
+    const replace = (reason) => placeholder === "null" ? null : { $unserializable: reason };
+    const sanitize = (value, path) => {
+        switch (typeof value) {
+            case "bigint":
+                return bigIntegers ? value : replace("BigInt");
+            case "symbol":
+                return replace("Symbol");
+            case "function":
+                return replace("Function");
+            case "object":
+                break;
+            default:
+                return value;
+        }
+        if (value === null) {
+            return value;
+        }
+        if (ancestors.includes(value)) {
+            return replace(`circular reference at ${path}`);
+        }
+        ancestors.push(value);
+        let copy;
+        if (Array.isArray(value)) {
+            value.forEach((item, index) => {
+                const sanitized = sanitize(item, `${path}[${index}]`);
+                if (!Object.is(sanitized, item)) {
+                    copy ??= value.slice();
+                    copy[index] = sanitized;
+                }
+            });
+        } else {
+            for (const key of Object.keys(value)) {
+                const item = value[key];
+                const sanitized = sanitize(item, `${path}.${key}`);
+                if (!Object.is(sanitized, item)) {
+                    copy ??= { ...value };
+                    copy[key] = sanitized;
+                }
+            }
+        }
+        ancestors.pop();
+        return copy ?? value;
+    };
+    return sanitize(value, "$");
+})
+"#;
+
+/// the names of object properties kept in or removed from the output
+struct OutputFilter {
+    allowlist: Option<Vec<String>>,
+    denylist: Vec<String>,
+}
+
+impl OutputFilter {
+    fn includes(&self, name: &str) -> bool {
+        self.allowlist.as_ref().map_or(true, |allowlist| {
+            allowlist.iter().any(|allowed| allowed == name)
+        }) && !self.denylist.iter().any(|denied| denied == name)
+    }
+}
+
+/// sets whether big integers are exchanged with the host as `BigInt`s for the current execution
+pub fn set_big_integers(big_integers: bool) {
+    BIG_INTEGERS.set(big_integers);
+}
+
+/// sets the placeholder for values of the current execution's output which cannot be serialized to JSON
+pub fn set_output_placeholder(placeholder: Option<String>) {
+    OUTPUT_PLACEHOLDER.set(placeholder);
+}
+
+/// prepares an output value to be sent to the host, replacing values which cannot be serialized with the placeholder
+/// if one is set, applying the output filter and then transcoding it to JSON, marking `BigInt`s if big integers are
+/// enabled.
+///
+/// without a placeholder a circular reference is reported as an `ERROR_CIRCULAR_REFERENCE` error, as serializing it
+/// would never terminate.
+pub fn prepare_output<'a>(context: &'a JSContextRef, value: JSValueRef<'a>) -> Result<Vec<u8>> {
+    let value = match OUTPUT_PLACEHOLDER.with_borrow(Clone::clone) {
+        Some(placeholder) => {
+            let sanitize = context.eval_global(SANITIZE_OUTPUT_SCRIPT_NAME, SANITIZE_OUTPUT)?;
+            sanitize.call(
+                &context.undefined_value()?,
+                &[
+                    value,
+                    context.value_from_str(&placeholder)?,
+                    context.value_from_bool(BIG_INTEGERS.get())?,
+                ],
+            )?
+        }
+        None => value,
+    };
+    let value = filter_output(context, value)?;
+    transcode_output(context, value)
+}
+
+/// sets the properties filtered from the output of the current execution
+pub fn set_output_filter(allowlist: Option<Vec<String>>, denylist: Option<Vec<String>>) {
+    let filter = (allowlist.is_some() || denylist.is_some()).then(|| OutputFilter {
+        allowlist,
+        denylist: denylist.unwrap_or_default(),
+    });
+    OUTPUT_FILTER.set(filter);
+}
+
+/// filters the properties of objects in an output value with the allowlist and denylist, if set.
+///
+/// like the replacer array of `JSON.stringify` the filter applies to the properties of objects at every depth.
+/// array elements are always kept but objects inside arrays are filtered. the value is copied rather than modified
+/// so the script's objects are unchanged.
+fn filter_output<'a>(context: &'a JSContextRef, value: JSValueRef<'a>) -> Result<JSValueRef<'a>> {
+    OUTPUT_FILTER.with_borrow(|filter| match filter {
+        Some(filter) => {
+            filter_value(context, value, filter, 0).map_err(|err| match err.is::<OutputTooDeep>() {
+                true => too_deep_error(context, value),
+                false => err,
+            })
+        }
+        None => Ok(value),
+    })
+}
+
+fn filter_value<'a>(
+    context: &'a JSContextRef,
+    value: JSValueRef<'a>,
+    filter: &OutputFilter,
+    depth: u32,
+) -> Result<JSValueRef<'a>> {
+    if (value.is_array() || value.is_object()) && depth >= MAX_OUTPUT_DEPTH {
+        return Err(OutputTooDeep.into());
+    }
+
+    if value.is_array() {
+        let array = context.array_value()?;
+        let length = value.get_property("length")?.as_u32_unchecked();
+        for index in 0..length {
+            let element = filter_value(
+                context,
+                value.get_indexed_property(index)?,
+                filter,
+                depth + 1,
+            )?;
+            array.append_property(element)?;
+        }
+        Ok(array)
+    } else if value.is_object() && !value.is_function() {
+        let object = context.object_value()?;
+        let mut properties = value.properties()?;
+        while let Some(key) = properties.next_key()? {
+            let name = property_name(key)?;
+            if filter.includes(&name) {
+                object.set_property(
+                    name.as_str(),
+                    filter_value(
+                        context,
+                        value.get_property(name.as_str())?,
+                        filter,
+                        depth + 1,
+                    )?,
+                )?;
+            }
+        }
+        Ok(object)
+    } else {
+        Ok(value)
+    }
+}
+
+/// gets the script from the host as bytes. this is either utf-8 source or bytecode.
+pub fn get_input_script() -> Result<Option<Vec<u8>>> {
     let input_size = unsafe { get_script_size() } as usize;
 
     if input_size == 0 {
@@ -45,36 +541,269 @@ pub fn get_input_script() -> Result<Option<String>> {
 
         let input_buf = unsafe { Vec::from_raw_parts(ptr, input_size, input_size) };
 
-        Ok(Some(String::from_utf8(input_buf.to_vec())?))
+        Ok(Some(input_buf.to_vec()))
+    }
+}
+
+/// the number of bytes of a streamed script requested from the host at a time
+const SCRIPT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// gets a script streamed by the host in chunks as bytes, for scripts whose size is not known up front.
+pub fn get_streamed_script() -> Result<Option<Vec<u8>>> {
+    let mut buf: Vec<u8> = Vec::new();
+    loop {
+        buf.reserve(SCRIPT_CHUNK_SIZE);
+        let spare = buf.spare_capacity_mut();
+        let read = unsafe { read_script_chunk(spare.as_mut_ptr() as i32, spare.len() as i32) };
+        if read <= 0 {
+            break;
+        }
+        unsafe { buf.set_len(buf.len() + read as usize) };
+    }
+
+    Ok((!buf.is_empty()).then_some(buf))
+}
+
+/// the maximum nesting depth of the input data if the host does not set one
+const DEFAULT_MAX_INPUT_DEPTH: u32 = 128;
+
+/// returns whether arrays and objects in a JSON payload are nested deeper than `max_depth`.
+///
+/// this scans the bytes without parsing them so it cannot overflow the stack however deep the payload is.
+fn exceeds_depth(bytes: &[u8], max_depth: u32) -> bool {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for byte in bytes {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match byte {
+                b'"' => in_string = true,
+                b'[' | b'{' => {
+                    depth += 1;
+                    if depth > max_depth {
+                        return true;
+                    }
+                }
+                b']' | b'}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    false
+}
+
+/// advances the host to the next job of a batch. returns false once every job has been executed.
+pub fn advance_job() -> bool {
+    unsafe { next_job() != 0 }
+}
+
+/// sends raw bytes to the host verbatim rather than transcoding them to JSON
+pub fn set_binary_value(bytes: &[u8], content_type: Option<&str>) {
+    let (content_type_ptr, content_type_size) = content_type.map_or((0, -1), |content_type| {
+        (content_type.as_ptr() as i32, content_type.len() as i32)
+    });
+
+    unsafe {
+        set_binary_output(
+            bytes.as_ptr() as i32,
+            bytes.len() as i32,
+            content_type_ptr,
+            content_type_size,
+        )
+    };
+}
+
+/// returns whether the host's time limit has been exceeded and the execution should be interrupted
+pub fn time_limit_exceeded() -> bool {
+    unsafe { is_timed_out() != 0 }
+}
+
+/// returns whether the host has cancelled the execution
+pub fn execution_cancelled() -> bool {
+    unsafe { is_cancelled() != 0 }
+}
+
+/// calls the host function `name` with the arguments as a JSON array and returns its JSON result as a JSValueRef.
+///
+/// a function failing with a `HostFunctionError` returns its message as an error, which is thrown in the script as
+/// a catchable exception. the host aborts the execution if the function fails otherwise or is not available to the
+/// current execution.
+pub fn invoke_host_function<'a>(
+    context: &'a JSContextRef,
+    name: &str,
+    args: &[JSValueRef],
+) -> Result<JSValueRef<'a>> {
+    let array = context.array_value()?;
+    for arg in args {
+        array.append_property(*arg)?;
+    }
+    let mut args = Vec::new();
+    let mut serializer = serde_json::Serializer::new(&mut args);
+    serde_transcode::transcode(&mut Deserializer::from(array), &mut serializer)?;
+
+    // a negative size of `-size - 1` is the size of the message of a thrown error
+    let result_size = unsafe {
+        call_host_function(
+            name.as_ptr() as i32,
+            name.len() as i32,
+            args.as_ptr() as i32,
+            args.len() as i32,
+        )
+    };
+    let thrown = result_size < 0;
+    let result_size = if thrown {
+        -result_size - 1
+    } else {
+        result_size
+    } as usize;
+    let mut result: Vec<u8> = vec![0; result_size];
+    unsafe { get_host_function_result(result.as_mut_ptr() as i32) };
+
+    if thrown {
+        bail!("{}", String::from_utf8_lossy(&result));
+    }
+    transcode_input(context, &result)
+}
+
+/// requests the dataset `name` from the host's data resolver and returns it as a JSValueRef.
+///
+/// the host aborts the execution if the dataset cannot be resolved.
+pub fn load_dataset<'a>(context: &'a JSContextRef, name: &str) -> Result<JSValueRef<'a>> {
+    let size = unsafe { load_data(name.as_ptr() as i32, name.len() as i32) } as usize;
+    let mut data: Vec<u8> = vec![0; size];
+    unsafe { get_host_function_result(data.as_mut_ptr() as i32) };
+
+    transcode_input(context, &data)
+}
+
+/// reads the value stored under `key` in the host's shared cache. returns `None` if the key is missing.
+pub fn get_cached<'a>(context: &'a JSContextRef, key: &str) -> Result<Option<JSValueRef<'a>>> {
+    let size = unsafe { cache_get(key.as_ptr() as i32, key.len() as i32) };
+    if size < 0 {
+        return Ok(None);
     }
+    let mut value: Vec<u8> = vec![0; size as usize];
+    unsafe { get_host_function_result(value.as_mut_ptr() as i32) };
+
+    transcode_input(context, &value).map(Some)
 }
 
-/// gets the data from the host as a JSValueRef
-pub fn get_input_data(context: &JSContextRef) -> Result<Option<JSValueRef>> {
+/// stores `value` as JSON under `key` in the host's shared cache.
+///
+/// the host aborts the execution if the cache is read-only.
+pub fn set_cached(key: &str, value: JSValueRef) -> Result<()> {
+    let mut bytes = Vec::new();
+    let mut serializer = serde_json::Serializer::new(&mut bytes);
+    serde_transcode::transcode(&mut Deserializer::from(value), &mut serializer)?;
+
+    unsafe {
+        cache_set(
+            key.as_ptr() as i32,
+            key.len() as i32,
+            bytes.as_ptr() as i32,
+            bytes.len() as i32,
+        )
+    };
+
+    Ok(())
+}
+
+/// gets the input data from the host as bytes
+fn get_input_bytes() -> Vec<u8> {
     let input_size = unsafe { get_data_size() } as usize;
+    let mut buf: Vec<u8> = vec![0; input_size];
+    if input_size > 0 {
+        unsafe { get_data(buf.as_mut_ptr() as i32) };
+    }
+    buf
+}
 
-    if input_size == 0 {
+/// gets the input data from the host as a string without parsing it. invalid utf-8 is replaced.
+pub fn get_input_text(context: &JSContextRef) -> Result<JSValueRef> {
+    context.value_from_str(&String::from_utf8_lossy(&get_input_bytes()))
+}
+
+/// gets the data from the host as a JSValueRef. data nested deeper than `max_depth` is rejected before it is parsed.
+pub fn get_input_data(
+    context: &JSContextRef,
+    max_depth: Option<u32>,
+) -> Result<Option<JSValueRef>> {
+    let input_buf = get_input_bytes();
+
+    if input_buf.is_empty() {
         Ok(None)
     } else {
-        let mut buf: Vec<u8> = Vec::with_capacity(input_size);
-        let ptr = buf.as_mut_ptr();
-        unsafe { get_data(ptr as i32) };
+        let max_depth = max_depth.unwrap_or(DEFAULT_MAX_INPUT_DEPTH);
+        if exceeds_depth(&input_buf, max_depth) {
+            return Err(GuestError::new(
+                ERROR_INPUT_TOO_DEEP,
+                format!("input data exceeds maximum depth of {max_depth}"),
+            )
+            .into());
+        }
 
-        let input_buf = unsafe { Vec::from_raw_parts(ptr, input_size, input_size) };
+        let value = transcode_input(context, &input_buf)?;
+        if BIG_INTEGERS.get() {
+            let revive =
+                context.eval_global(REVIVE_BIG_INTEGERS_SCRIPT_NAME, REVIVE_BIG_INTEGERS)?;
+            return Ok(Some(revive.call(&context.undefined_value()?, &[value])?));
+        }
+        Ok(Some(value))
+    }
+}
+
+/// gets the execution options from the host
+pub fn get_input_options() -> Result<Options> {
+    let input_size = unsafe { get_options_size() } as usize;
 
-        Ok(Some(transcode_input(context, &input_buf)?))
+    if input_size == 0 {
+        Ok(Options::default())
+    } else {
+        let mut buf: Vec<u8> = vec![0; input_size];
+        unsafe { get_options(buf.as_mut_ptr() as i32) };
+
+        Ok(serde_json::from_slice(&buf)?)
     }
 }
 
-/// sets the output value on the host
-pub fn set_output_value(output: Result<Option<JSValueRef>>) -> Result<()> {
+/// the `set_output` code for a value passed to `emit` rather than the final result
+const OUTPUT_EMITTED: i32 = -1;
+
+/// sends a value passed to `emit`, prepared with `prepare_output`, to the host. the host collects these in order
+/// ahead of the final result.
+pub fn set_emitted_value(output: Vec<u8>) -> Result<()> {
+    let size = output.len() as i32;
+    let ptr = output.as_ptr();
+
+    unsafe {
+        set_output(ptr as i32, size, OUTPUT_EMITTED);
+    }
+    Ok(())
+}
+
+/// sets the output value, prepared with `prepare_output`, on the host
+pub fn set_output_value(output: Result<Option<Vec<u8>>>) -> Result<()> {
     match output {
         Ok(None) => unsafe {
             set_output(0, 0, 0);
         },
-        Ok(Some(output)) => {
-            let output = transcode_output(output)?;
+        Ok(Some(output)) => set_output_bytes(Ok(output))?,
+        Err(err) => set_output_bytes(Err(err))?,
+    }
+    Ok(())
+}
 
+/// sets raw output bytes on the host without transcoding them to JSON
+pub fn set_output_bytes(output: Result<Vec<u8>>) -> Result<()> {
+    match output {
+        Ok(output) => {
             let size = output.len() as i32;
             let ptr = output.as_ptr();
 
@@ -83,6 +812,9 @@ pub fn set_output_value(output: Result<Option<JSValueRef>>) -> Result<()> {
             }
         }
         Err(err) => {
+            let code = err
+                .downcast_ref::<GuestError>()
+                .map_or(ERROR_SCRIPT, |err| err.code);
             let err = err.to_string();
 
             let output = err.as_bytes();
@@ -90,9 +822,19 @@ pub fn set_output_value(output: Result<Option<JSValueRef>>) -> Result<()> {
             let ptr = output.as_ptr();
 
             unsafe {
-                set_output(ptr as i32, size, 1);
+                set_output(ptr as i32, size, code);
             };
         }
     }
     Ok(())
 }
+
+/// reports to the host why the guest is about to abort so it can be surfaced distinctly from a script error
+pub fn set_abort(message: &str) {
+    let size = message.len() as i32;
+    let ptr = message.as_ptr();
+
+    unsafe {
+        set_abort_message(ptr as i32, size);
+    }
+}
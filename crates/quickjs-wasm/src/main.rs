@@ -1,14 +1,23 @@
-#[cfg(feature = "console")]
 mod context;
+mod diagnostics;
+mod error;
 mod io;
+mod options;
+mod runtime;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use error::{GuestError, ERROR_GUARD_REJECTED, ERROR_TIME_LIMIT_EXCEEDED};
 use once_cell::sync::OnceCell;
-use quickjs_wasm_rs::JSContextRef;
+use options::Options;
+use quickjs_wasm_rs::{JSContextRef, JSValueRef};
 
 static mut JS_CONTEXT: OnceCell<JSContextRef> = OnceCell::new();
 static SCRIPT_NAME: &str = "script.js";
 static DEPENDENCIES: &str = include_str!("../dependencies/index.js");
+/// the release of the quickjs engine bundled by `quickjs-wasm-sys`. the host's
+/// `quickjs_version_matches_pinned_sys_crate` test fails if `quickjs-wasm-sys` is bumped in `Cargo.lock` without
+/// updating this.
+static QUICKJS_VERSION: &str = "2021-03-27";
 
 /// init() is executed by wizer to create a snapshot after the quickjs context has been initialized.
 ///
@@ -18,29 +27,296 @@ static DEPENDENCIES: &str = include_str!("../dependencies/index.js");
 pub extern "C" fn init() {
     unsafe {
         let context = JSContextRef::default();
+        initialize(&context).unwrap();
+        JS_CONTEXT.set(context).unwrap();
+    }
+}
+
+/// quickjs_version returns the version of the quickjs engine so the host can read it without evaluating a script.
+///
+/// the version is returned as its pointer in the upper 32 bits and its length in the lower 32 bits.
+#[export_name = "quickjs_version"]
+pub extern "C" fn quickjs_version() -> u64 {
+    (QUICKJS_VERSION.as_ptr() as u64) << 32 | QUICKJS_VERSION.len() as u64
+}
 
-        // add any init code
-        context.eval_global(SCRIPT_NAME, DEPENDENCIES).unwrap();
+/// initialize evaluates the dependencies and adds the globals to a new context.
+fn initialize(context: &JSContextRef) -> Result<()> {
+    // add any init code
+    context.eval_global(SCRIPT_NAME, DEPENDENCIES)?;
 
-        // add globals to the quickjs instance if enabled
-        #[cfg(feature = "console")]
-        context::set_quickjs_globals(&context).unwrap();
+    // add globals to the quickjs instance
+    context::set_quickjs_globals(context)?;
 
-        JS_CONTEXT.set(context).unwrap();
-    }
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    match io::get_input_script()? {
+    // report panics to the host before the guest traps so they are not mistaken for script errors
+    std::panic::set_hook(Box::new(|info| io::set_abort(&info.to_string())));
+
+    run().inspect_err(|err| io::set_abort(&err.to_string()))
+}
+
+fn run() -> Result<()> {
+    let options = io::get_input_options()?;
+    let context = unsafe { JS_CONTEXT.get_or_init(JSContextRef::default) };
+
+    if !options.batch {
+        execute(context, &options)?;
+        io::send_timings();
+        io::send_max_pending_jobs(runtime::max_pending_jobs());
+        return Ok(());
+    }
+
+    // the first job uses the snapshot context. every later job gets a fresh context so that jobs cannot observe
+    // each other's globals, which is freed once the job completes.
+    let mut first = true;
+    while io::advance_job() {
+        if std::mem::take(&mut first) {
+            execute(context, &options)?;
+            continue;
+        }
+        let job_context = runtime::JobContext::new(context)?;
+        initialize(&job_context)?;
+        execute(&job_context, &options)?;
+    }
+    io::send_timings();
+    io::send_max_pending_jobs(runtime::max_pending_jobs());
+
+    Ok(())
+}
+
+/// execute evaluates the current script and sends its result to the host.
+fn execute(context: &JSContextRef, options: &Options) -> Result<()> {
+    let script = if options.streamed_script {
+        io::get_streamed_script()?
+    } else {
+        io::get_input_script()?
+    };
+    match script {
         Some(input) => {
-            let context = unsafe { JS_CONTEXT.get_or_init(JSContextRef::default) };
+            if options.diagnose {
+                let input = source(input, options)?;
+                let diagnostics = match compile(context, &input, options) {
+                    Ok(_) => Vec::new(),
+                    Err(err) => vec![diagnostics::diagnose(&err, SCRIPT_NAME)],
+                };
+                return io::set_output_bytes(Ok(serde_json::to_vec(&diagnostics)?));
+            }
+
+            if options.compile {
+                let input = source(input, options)?;
+                return io::set_output_bytes(compile(context, &input, options));
+            }
+
+            // the generator state is part of the snapshot so must be reseeded for every execution
+            context::seed_random(options.random_seed)?;
+
+            runtime::set_interrupt_handler(context, options.stack_on_timeout);
+
+            if let Some(gc_threshold) = options.gc_threshold {
+                runtime::set_gc_threshold(context, gc_threshold);
+            }
+
+            if let Some(js_heap_limit) = options.js_heap_limit {
+                runtime::set_memory_limit(context, js_heap_limit);
+            }
+
+            if options.unhandled_rejection_is_error {
+                runtime::track_rejections(context);
+            }
+
+            io::set_pretty_output(options.pretty_output);
+            io::set_live_console(options.live_console);
+            io::set_output_filter(
+                options.output_allowlist.clone(),
+                options.output_denylist.clone(),
+            );
+            io::set_output_placeholder(options.output_placeholder.clone());
+            io::set_big_integers(options.big_integers);
+
+            if options.max_string_length.is_some() || options.max_array_length.is_some() {
+                context::limit_lengths(
+                    context,
+                    options.max_string_length,
+                    options.max_array_length,
+                )?;
+            }
 
-            if let Some(value) = io::get_input_data(context)? {
-                context.global_object()?.set_property("data", value)?;
+            context::define_host_functions(context, &options.host_functions)?;
+            if options.data_resolver {
+                context::define_load_data(context)?;
+            }
+            if options.shared_cache {
+                context::define_shared_cache(context, options.shared_cache_writable)?;
+            }
+            for (name, value) in &options.secrets {
+                context
+                    .global_object()?
+                    .set_property(name.as_str(), context.value_from_str(value)?)?;
+            }
+            for (name, value) in &options.constants {
+                let value = io::transcode_input(context, &serde_json::to_vec(value)?)?;
+                context
+                    .global_object()?
+                    .set_property(name.as_str(), value)?;
+                context::freeze_data(context, name)?;
             }
 
-            io::set_output_value(context.eval_global(SCRIPT_NAME, &input).map(Some))
+            if options.list_globals {
+                let defined = context::global_names(context)?;
+                let output = evaluate(context, input, options)
+                    .and_then(|_| context::global_names(context))
+                    .and_then(|names| {
+                        let names = names
+                            .into_iter()
+                            .filter(|name| !defined.contains(name))
+                            .collect::<Vec<_>>();
+                        Ok(serde_json::to_vec(&names)?)
+                    });
+                return io::set_output_bytes(output);
+            }
+
+            // the parameters of an invoked function are passed to it rather than bound as a global
+            let mut params = None;
+            if options.text_input {
+                // text is bound as a string without being parsed so cannot be malformed
+                let text = io::get_input_text(context)?;
+                context.global_object()?.set_property("text", text)?;
+            } else {
+                // report malformed data as an error rather than aborting so it can be distinguished from a script error
+                match io::get_input_data(context, options.max_input_depth) {
+                    Ok(Some(value)) if options.invoke.is_some() => params = Some(value),
+                    Ok(Some(value)) => {
+                        let name = options.data_global.as_deref().unwrap_or("data");
+                        context.global_object()?.set_property(name, value)?;
+                        if options.read_only_data {
+                            context::freeze_data(context, name)?;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => return io::set_output_value(Err(err)),
+                }
+            }
+
+            // the snapshot is taken once the data is bound so only the script's own changes are reported
+            let tracker = options
+                .track_globals
+                .then(|| context::track_globals(context))
+                .transpose()?;
+
+            let output = io::timed(io::Phase::Evaluation, || {
+                if let Some(guard) = &options.guard {
+                    if let Some(reason) = context::guard(context, guard)? {
+                        return Err(GuestError::new(ERROR_GUARD_REJECTED, reason).into());
+                    }
+                }
+                let output = evaluate(context, input, options)?;
+                let output = match &options.invoke {
+                    Some(name) => {
+                        let params = match params {
+                            Some(params) => params,
+                            None => context.object_value()?,
+                        };
+                        context::invoke(context, name, params)?
+                    }
+                    None => output,
+                };
+                if options.unhandled_rejection_is_error {
+                    // pending jobs may still attach handlers to promises which were rejected without one
+                    runtime::drain_jobs(context)?;
+                    if let Some(reason) = runtime::unhandled_rejection() {
+                        bail!("unhandled promise rejection: {reason}");
+                    }
+                }
+                if let Some(tracker) = tracker {
+                    io::send_changed_globals(&context::changed_globals(context, tracker)?);
+                }
+                Ok(output)
+            });
+
+            // `undefined` has no JSON representation so is reported as no output, whereas `null` is a value
+            let output = output
+                .map(|value| (!value.is_undefined()).then_some(value))
+                .map_err(|err| {
+                    // an interrupted script reports the stack it was interrupted at rather than the interruption.
+                    // the error is `Uncaught InternalError: interrupted` followed by that stack.
+                    if runtime::timed_out() {
+                        let message = err.to_string();
+                        let stack = message.lines().skip(1).collect::<Vec<_>>().join("\n");
+                        return GuestError::new(ERROR_TIME_LIMIT_EXCEEDED, stack).into();
+                    }
+                    err
+                })
+                .and_then(|value| {
+                    value
+                        .map(|value| io::prepare_output(context, value))
+                        .transpose()
+                });
+
+            io::set_output_value(output)
         }
         None => io::set_output_value(Ok(None)),
     }
 }
+
+/// evaluate evaluates the script source or bytecode and returns its result.
+///
+/// if `module_eval` is set the script is an ES module and its result is its default export, awaited if it is a
+/// promise. otherwise the script is a classic script and its result is the value of its last statement.
+///
+/// bytecode is not validated by quickjs so loading untrusted bytecode is unsafe: it can corrupt the guest's memory.
+/// the host only accepts bytecode for `try_execute_bytecode`, which documents that it must be trusted.
+fn evaluate<'a>(
+    context: &'a JSContextRef,
+    input: Vec<u8>,
+    options: &Options,
+) -> Result<JSValueRef<'a>> {
+    let output = if options.bytecode {
+        context.eval_binary(&input)?
+    } else if options.module_eval {
+        context.eval_module(SCRIPT_NAME, &source(input, options)?)?
+    } else {
+        context.eval_global(SCRIPT_NAME, &source(input, options)?)?
+    };
+
+    if options.module_eval {
+        context::module_default_export(context, SCRIPT_NAME)
+    } else {
+        Ok(output)
+    }
+}
+
+/// compile compiles the script source to bytecode as a classic script or, if `module_eval` is set, an ES module.
+fn compile(context: &JSContextRef, input: &str, options: &Options) -> Result<Vec<u8>> {
+    if options.module_eval {
+        context.compile_module(SCRIPT_NAME, input)
+    } else {
+        context.compile_global(SCRIPT_NAME, input)
+    }
+}
+
+/// source decodes the script source, prepending a `"use strict"` directive if strict mode is enabled and wrapping
+/// it in a function if statement mode is enabled.
+///
+/// the directive and wrapper are added on the first line so that line numbers in errors still match the script.
+fn source(input: Vec<u8>, options: &Options) -> Result<String> {
+    let input = String::from_utf8(input)?;
+    // modules are always strict and their result is their default export so neither applies
+    if options.module_eval {
+        return Ok(input);
+    }
+
+    // the closing brace is on its own line so that a trailing line comment does not swallow it
+    let input = if options.statement_mode {
+        format!("(function () {{{input}\n}}).call(globalThis);")
+    } else {
+        input
+    };
+    if options.strict_mode {
+        Ok(format!("\"use strict\";{input}"))
+    } else {
+        Ok(input)
+    }
+}
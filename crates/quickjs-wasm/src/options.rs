@@ -0,0 +1,85 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Options supplied by the host for a single execution.
+///
+/// Every field is optional so that a host which does not provide options (or an older host which
+/// only knows a subset of them) results in the default behaviour.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// Seed for the generator backing `Math.random` and `crypto.getRandomValues`. Real entropy is used if unset.
+    pub random_seed: Option<u64>,
+    /// Bytes allocated between automatic garbage collections. The quickjs default is used if unset.
+    pub gc_threshold: Option<u32>,
+    /// Bytes the quickjs runtime may allocate before allocations fail with a catchable error. Unlimited if unset.
+    pub js_heap_limit: Option<usize>,
+    /// Characters of the strings builtins such as `repeat` may create before throwing a `RangeError`. Unlimited if unset.
+    pub max_string_length: Option<u32>,
+    /// Elements of the arrays builtins such as `Array.from` may create before throwing a `RangeError`. Unlimited if
+    /// unset.
+    pub max_array_length: Option<u32>,
+    /// Produce indented JSON rather than compact JSON.
+    pub pretty_output: bool,
+    /// Deeply freeze the `data` global so the script cannot mutate it.
+    pub read_only_data: bool,
+    /// Evaluate the script as an ES module whose default export is the result rather than as a classic script.
+    pub module_eval: bool,
+    /// Evaluate the script as the body of a function so only an explicit `return` is its result.
+    pub statement_mode: bool,
+    /// Run pending jobs after the script and report a promise rejection which was never handled as an error.
+    pub unhandled_rejection_is_error: bool,
+    /// Evaluate the script in strict mode.
+    pub strict_mode: bool,
+    /// Maximum nesting depth of the input data. `DEFAULT_MAX_INPUT_DEPTH` is used if unset.
+    pub max_input_depth: Option<u32>,
+    /// Execute a batch of jobs, fetching each with `next_job`, rather than a single script.
+    pub batch: bool,
+    /// Names of the only object properties included in the output.
+    pub output_allowlist: Option<Vec<String>>,
+    /// Names of object properties excluded from the output.
+    pub output_denylist: Option<Vec<String>>,
+    /// Replace values in the output which cannot be serialized to JSON with `null` (`"null"`) or an object
+    /// describing them (`"description"`) rather than failing.
+    pub output_placeholder: Option<String>,
+    /// Send console lines to the host with `console_line` rather than writing them to stdout or stderr.
+    pub live_console: bool,
+    /// Return the problems found by compiling the script rather than evaluating it.
+    pub diagnose: bool,
+    /// Names of the host functions the script can call, each defined as a global of the same name.
+    pub host_functions: Vec<String>,
+    /// Strings bound as globals of the same name. the host redacts their values from the output.
+    pub secrets: BTreeMap<String, String>,
+    /// Values bound as deeply frozen, non-writable globals of the same name.
+    pub constants: BTreeMap<String, serde_json::Value>,
+    /// Define the `loadData` global which requests datasets from the host by name.
+    pub data_resolver: bool,
+    /// Define the `cacheGet` global which reads the cache the host shares across executions.
+    pub shared_cache: bool,
+    /// Define the `cacheSet` global as well so scripts can write to the shared cache.
+    pub shared_cache_writable: bool,
+    /// Name of the global the input data is bound to. `data` is used if unset.
+    pub data_global: Option<String>,
+    /// Report the names of the globals the script defined, reassigned or deleted to the host.
+    pub track_globals: bool,
+    /// Interrupt the script once the host's time limit is exceeded, reporting the stack at the interruption.
+    pub stack_on_timeout: bool,
+    /// Bind integers the host marked as too large for a number as `BigInt`s, and mark `BigInt`s in the output.
+    pub big_integers: bool,
+    /// Name of a function defined by the script which is called with the input data as its only argument. Its return
+    /// value is the result rather than the script's.
+    pub invoke: Option<String>,
+    /// Bind the input data as the string global `text` rather than parsing it as JSON.
+    pub text_input: bool,
+    /// A script evaluated before the script, after the input data is bound, which must evaluate to `true` for the
+    /// script to run.
+    pub guard: Option<String>,
+    /// Read the script from the host in chunks with `read_script_chunk` rather than all at once.
+    pub streamed_script: bool,
+    /// Compile the script to bytecode and return the bytecode rather than evaluating it.
+    pub compile: bool,
+    /// The script is bytecode produced by `compile` rather than source.
+    pub bytecode: bool,
+    /// Return the names of the globals defined by the script rather than its result.
+    pub list_globals: bool,
+}
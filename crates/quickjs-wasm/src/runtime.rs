@@ -0,0 +1,217 @@
+use anyhow::{bail, Result};
+use quickjs_wasm_rs::JSContextRef;
+use quickjs_wasm_sys::{
+    JSContext, JSRuntime, JSValue, JS_ExecutePendingJob, JS_FreeCString, JS_FreeContext,
+    JS_GetException, JS_GetRuntime, JS_NewContext, JS_RunGC, JS_SetGCThreshold,
+    JS_SetHostPromiseRejectionTracker, JS_SetInterruptHandler, JS_SetMemoryLimit, JS_ToCStringLen2,
+};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::{c_void, CStr},
+    ops::Deref,
+    os::raw::c_int,
+};
+
+use crate::io;
+
+thread_local! {
+    /// whether the current execution was interrupted by the host's time limit
+    static TIMED_OUT: Cell<bool> = const { Cell::new(false) };
+    /// the promises of the current execution which were rejected without a handler, with their reasons
+    static UNHANDLED_REJECTIONS: RefCell<Vec<(JSValue, String)>> = const { RefCell::new(Vec::new()) };
+    /// the most jobs run by a single call to `drain_jobs`
+    static MAX_PENDING_JOBS: Cell<u32> = const { Cell::new(0) };
+}
+
+/// raw_context returns the raw quickjs context wrapped by `context`.
+///
+/// `JSContextRef` does not expose its inner `*mut JSContext` but it is a single pointer field so can be
+/// read directly. the size assertion guards against the wrapper changing shape in a future release.
+fn raw_context(context: &JSContextRef) -> *mut JSContext {
+    const _: () =
+        assert!(std::mem::size_of::<JSContextRef>() == std::mem::size_of::<*mut JSContext>());
+    unsafe { *(context as *const JSContextRef).cast::<*mut JSContext>() }
+}
+
+/// JobContext is a fresh context for a job of a batch, created in the runtime of the snapshot context so the job cannot
+/// observe the globals of earlier jobs. the context is freed once dropped and its objects collected, so the memory of a
+/// batch does not grow with the number of jobs.
+pub struct JobContext(JSContextRef);
+
+impl JobContext {
+    /// new creates a context in the runtime which owns `context`.
+    pub fn new(context: &JSContextRef) -> Result<Self> {
+        let inner = unsafe { JS_NewContext(raw_runtime(context)) };
+        if inner.is_null() {
+            bail!("failed to create a context for the job");
+        }
+        // `JSContextRef` is a single pointer field, as asserted by `raw_context`.
+        Ok(Self(unsafe {
+            std::mem::transmute::<*mut JSContext, JSContextRef>(inner)
+        }))
+    }
+}
+
+impl Deref for JobContext {
+    type Target = JSContextRef;
+
+    fn deref(&self) -> &JSContextRef {
+        &self.0
+    }
+}
+
+impl Drop for JobContext {
+    fn drop(&mut self) {
+        // every value borrows the context so none outlive it.
+        let runtime = raw_runtime(&self.0);
+        unsafe {
+            JS_FreeContext(raw_context(&self.0));
+            JS_RunGC(runtime);
+        }
+    }
+}
+
+/// raw_runtime returns the raw quickjs runtime that owns `context`.
+pub fn raw_runtime(context: &JSContextRef) -> *mut JSRuntime {
+    unsafe { JS_GetRuntime(raw_context(context)) }
+}
+
+/// run_gc runs the quickjs garbage collector, freeing unreachable objects (including cycles) immediately.
+pub fn run_gc(context: &JSContextRef) {
+    unsafe { JS_RunGC(raw_runtime(context)) };
+}
+
+/// set_gc_threshold sets the number of bytes allocated since the last collection which triggers an automatic
+/// garbage collection. quickjs defaults to 256KiB.
+pub fn set_gc_threshold(context: &JSContextRef, gc_threshold: u32) {
+    unsafe { JS_SetGCThreshold(raw_runtime(context), gc_threshold as _) };
+}
+
+/// set_memory_limit sets the number of bytes the quickjs runtime may allocate. allocations beyond the limit throw
+/// a catchable `InternalError: out of memory` rather than growing the wasm memory.
+pub fn set_memory_limit(context: &JSContextRef, limit: usize) {
+    unsafe { JS_SetMemoryLimit(raw_runtime(context), limit as _) };
+}
+
+/// set_interrupt_handler installs an interrupt handler which, if `stack_on_timeout` is set, interrupts the script once
+/// the host's time limit is exceeded.
+///
+/// quickjs only invokes the interrupt handler periodically (every 10000 function calls or loop iterations) so the
+/// script runs briefly past the limit before it is interrupted.
+pub fn set_interrupt_handler(context: &JSContextRef, stack_on_timeout: bool) {
+    TIMED_OUT.set(false);
+
+    let handler = stack_on_timeout.then_some(interrupt_handler as _);
+    unsafe { JS_SetInterruptHandler(raw_runtime(context), handler, std::ptr::null_mut()) };
+}
+
+/// timed_out returns whether the last execution was interrupted by the host's time limit.
+pub fn timed_out() -> bool {
+    TIMED_OUT.get()
+}
+
+/// drain_jobs runs pending jobs, such as promise reactions, until none remain, recording the number of jobs run for
+/// `max_pending_jobs`.
+///
+/// quickjs does not expose the length of its job queue so the jobs are run one at a time and counted instead. every
+/// job queued while draining is counted, so the count is an upper bound of the depth the queue reached.
+pub fn drain_jobs(context: &JSContextRef) -> Result<()> {
+    let runtime = raw_runtime(context);
+    let mut jobs = 0u32;
+    let result = loop {
+        let mut job_context = std::ptr::null_mut();
+        match unsafe { JS_ExecutePendingJob(runtime, &mut job_context) } {
+            0 => break Ok(()),
+            // promise reactions capture exceptions in their promise so only an interruption or running out of
+            // memory fails a job. the exception is not freed as the instance is discarded after the failure.
+            result if result < 0 => break Err(unsafe { exception_message(job_context) }),
+            _ => jobs += 1,
+        }
+    };
+    MAX_PENDING_JOBS.set(MAX_PENDING_JOBS.get().max(jobs));
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(message) => bail!(message),
+    }
+}
+
+/// max_pending_jobs returns the most jobs run by a single call to `drain_jobs` in this execution.
+pub fn max_pending_jobs() -> u32 {
+    MAX_PENDING_JOBS.get()
+}
+
+/// exception_message takes the pending exception of `ctx` and returns it as a string, e.g. `InternalError: interrupted`.
+unsafe fn exception_message(ctx: *mut JSContext) -> String {
+    let exception = JS_GetException(ctx);
+    let mut length = 0;
+    let pointer = JS_ToCStringLen2(ctx, &mut length, exception, 0);
+    if pointer.is_null() {
+        return "unknown exception".to_string();
+    }
+    let message = CStr::from_ptr(pointer).to_string_lossy().into_owned();
+    JS_FreeCString(ctx, pointer);
+    message
+}
+
+/// track_rejections installs a promise rejection tracker which records promises rejected without a handler until a
+/// handler is attached, so that rejections which are never handled can be reported by `unhandled_rejection`.
+pub fn track_rejections(context: &JSContextRef) {
+    UNHANDLED_REJECTIONS.with_borrow_mut(Vec::clear);
+    unsafe {
+        JS_SetHostPromiseRejectionTracker(
+            raw_runtime(context),
+            Some(rejection_tracker),
+            std::ptr::null_mut(),
+        )
+    };
+}
+
+/// unhandled_rejection returns the reason of the first promise rejection which has not been handled, if any.
+pub fn unhandled_rejection() -> Option<String> {
+    UNHANDLED_REJECTIONS
+        .with_borrow(|rejections| rejections.first().map(|(_, reason)| reason.clone()))
+}
+
+/// rejection_tracker is called by quickjs when a promise is rejected without a handler, and again if a handler is
+/// later attached to it.
+///
+/// promises are identified by their value, which is only valid while the promise is alive. a promise which is freed
+/// while unhandled cannot be handled afterwards so it remains recorded.
+unsafe extern "C" fn rejection_tracker(
+    ctx: *mut JSContext,
+    promise: JSValue,
+    reason: JSValue,
+    is_handled: c_int,
+    _opaque: *mut c_void,
+) {
+    if is_handled != 0 {
+        UNHANDLED_REJECTIONS
+            .with_borrow_mut(|rejections| rejections.retain(|(rejected, _)| *rejected != promise));
+        return;
+    }
+
+    let mut length = 0;
+    let pointer = JS_ToCStringLen2(ctx, &mut length, reason, 0);
+    let reason = if pointer.is_null() {
+        "unknown reason".to_string()
+    } else {
+        let reason = CStr::from_ptr(pointer).to_string_lossy().into_owned();
+        JS_FreeCString(ctx, pointer);
+        reason
+    };
+    UNHANDLED_REJECTIONS.with_borrow_mut(|rejections| rejections.push((promise, reason)));
+}
+
+/// interrupt_handler returns non-zero to interrupt the script once the host's time limit is exceeded.
+///
+/// it only reads flags: evaluating javascript here would re-enter the interpreter in the middle of an instruction. the
+/// exception quickjs throws for the interruption already carries the stack it was interrupted at.
+unsafe extern "C" fn interrupt_handler(_rt: *mut JSRuntime, _opaque: *mut c_void) -> c_int {
+    if io::time_limit_exceeded() {
+        TIMED_OUT.set(true);
+        1
+    } else {
+        0
+    }
+}
@@ -1,13 +1,13 @@
 use std::time::Duration;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use quickjs::{QuickJSBuilder, TimeLimit};
+use quickjs::{QuickJS, QuickJSBuilder, TimeLimit};
 
 pub fn bench(c: &mut Criterion) {
     let script = include_str!("../../../track_points.js");
     let data = include_str!("../../../track_points.json");
 
-    let quickjs = QuickJSBuilder::new().build().unwrap();
+    let quickjs = QuickJS::new().unwrap();
     c.bench_function("try_execute", |b| {
         b.iter(|| black_box(quickjs.try_execute(script, Some(data)).unwrap()))
     });
@@ -20,6 +20,14 @@ pub fn bench(c: &mut Criterion) {
         b.iter(|| black_box(quickjs.try_execute(script, Some(data)).unwrap()))
     });
 
+    let quickjs = QuickJSBuilder::new()
+        .with_interruptible(true)
+        .build()
+        .unwrap();
+    c.bench_function("try_execute_interruptible", |b| {
+        b.iter(|| black_box(quickjs.try_execute(script, Some(data)).unwrap()))
+    });
+
     let quickjs = QuickJSBuilder::new()
         .with_time_limit(
             TimeLimit::new(Duration::from_millis(10000))
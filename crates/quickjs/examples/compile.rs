@@ -0,0 +1,63 @@
+extern crate quickjs;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use quickjs::QuickJSBuilder;
+use std::path::PathBuf;
+
+/// Compile a script to QuickJS bytecode so it can be executed with `try_execute_bytecode`
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the wasm module. bytecode can only be executed by the module that compiled it
+    #[arg(long)]
+    module: Option<PathBuf>,
+
+    /// Path to the input script
+    #[arg(long, default_value = "track_points.js")]
+    script: PathBuf,
+
+    /// Path to write the bytecode to. defaults to the script path with a `.qjsc` extension
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Verify the bytecode executes to the same result as the script
+    #[arg(long)]
+    verify: bool,
+
+    /// Path to the data json object used to verify the bytecode
+    #[arg(long)]
+    data: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut builder = QuickJSBuilder::new();
+    if let Some(module) = args.module {
+        builder = builder.with_module(module);
+    }
+    let quickjs = builder.build()?;
+
+    let script = std::fs::read_to_string(&args.script)?;
+    let output = args
+        .output
+        .unwrap_or_else(|| args.script.with_extension("qjsc"));
+
+    let bytecode = quickjs.compile(&script)?;
+    std::fs::write(&output, &bytecode)?;
+    println!("wrote {} bytes to {}", bytecode.len(), output.display());
+
+    if args.verify {
+        let data = args.data.map(std::fs::read_to_string).transpose()?;
+
+        let expected = quickjs.try_execute(&script, data.as_deref())?;
+        let actual = quickjs.try_execute_bytecode(&std::fs::read(&output)?, data.as_deref())?;
+        if expected != actual {
+            bail!("bytecode returned {actual:?} but script returned {expected:?}");
+        }
+        println!("verified {}", actual.unwrap_or_else(|| "None".to_string()));
+    }
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ extern crate quickjs;
 
 use anyhow::Result;
 use clap::Parser;
-use quickjs::{QuickJS, TimeLimit};
+use quickjs::{QuickJSBuilder, TimeLimit};
 use std::{
     path::PathBuf,
     time::{Duration, Instant},
@@ -47,31 +47,43 @@ struct Args {
     /// Set time limit evaluation interval. only used if `time_limit_micros` is set.
     #[arg(long)]
     time_limit_evaluation_interval_micros: Option<u64>,
+
+    /// Print the output as indented JSON
+    #[arg(long)]
+    pretty_output: bool,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let quickjs = QuickJS::try_new(
-        args.module,
-        args.inherit_stdout,
-        args.inherit_stderr,
-        args.memory_limit_bytes,
-        args.time_limit_micros.map(|limit| {
-            let mut limit = TimeLimit::new(Duration::from_micros(limit));
-            if let Some(evaluation_interval) = args.time_limit_evaluation_interval_micros {
-                limit.evaluation_interval = Duration::from_micros(evaluation_interval);
-            }
-            limit
-        }),
-    )?;
+    let mut builder = QuickJSBuilder::new()
+        .with_inherit_stdout(args.inherit_stdout)
+        .with_inherit_stderr(args.inherit_stderr)
+        .with_pretty_output(args.pretty_output);
+    if let Some(module) = args.module {
+        builder = builder.with_module(module);
+    }
+    if let Some(memory_limit) = args.memory_limit_bytes {
+        builder = builder.with_memory_limit(memory_limit);
+    }
+    if let Some(limit) = args.time_limit_micros {
+        let mut limit = TimeLimit::new(Duration::from_micros(limit));
+        if let Some(evaluation_interval) = args.time_limit_evaluation_interval_micros {
+            limit.evaluation_interval = Duration::from_micros(evaluation_interval);
+        }
+        builder = builder.with_time_limit(limit);
+    }
+    let quickjs = builder.build()?;
 
     let script = std::fs::read_to_string(args.script)?;
     let data = std::fs::read_to_string(args.data)?;
 
+    // The script is compiled once so each iteration only binds the data
+    let prepared = quickjs.prepare(&script)?;
+
     let start = Instant::now();
     for i in 0..args.iterations {
-        let output = quickjs.try_execute(&script, Some(&data))?;
+        let output = prepared.apply(Some(&data))?;
         println!("{i} {}", output.unwrap_or_else(|| "None".to_string()));
     }
 
@@ -0,0 +1,78 @@
+use anyhow::{bail, Context, Result};
+
+/// The first line of a bundle, identifying the format and its version.
+const MAGIC: &[u8] = b"quickjs-bundle 1\n";
+
+/// A script packaged with its default input data in a single file, executed with `QuickJS::try_execute_bundle`.
+///
+/// A bundle is the line `quickjs-bundle 1`, a header line with the byte lengths of the script and the data separated by
+/// a space, then the script and the data themselves:
+///
+/// ```text
+/// quickjs-bundle 1
+/// 10 7
+/// data.a + 1{"a":1}
+/// ```
+///
+/// A data length of `0` means the bundle has no data. Anything after the data is rejected so a truncated or
+/// concatenated bundle is not silently accepted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bundle {
+    /// The JavaScript code to execute.
+    pub script: String,
+    /// The JSON input data, if any.
+    pub data: Option<String>,
+}
+
+impl Bundle {
+    /// Creates a bundle from a script and optional JSON input data.
+    pub fn new(script: impl Into<String>, data: Option<impl Into<String>>) -> Self {
+        Self {
+            script: script.into(),
+            data: data.map(Into::into),
+        }
+    }
+
+    /// Parses a bundle from its bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+            bail!("not a quickjs bundle");
+        };
+        let Some(end) = bytes.iter().position(|byte| *byte == b'\n') else {
+            bail!("bundle header is missing");
+        };
+        let header = std::str::from_utf8(&bytes[..end]).context("bundle header is not utf-8")?;
+        let Some((script_len, data_len)) = header.split_once(' ') else {
+            bail!("bundle header `{header}` is not `<script length> <data length>`");
+        };
+        let script_len: usize = script_len.parse().context("invalid bundle script length")?;
+        let data_len: usize = data_len.parse().context("invalid bundle data length")?;
+
+        let body = &bytes[end + 1..];
+        if script_len.checked_add(data_len) != Some(body.len()) {
+            bail!(
+                "bundle body is {} bytes but the header declares {script_len} + {data_len}",
+                body.len()
+            );
+        }
+        let (script, data) = body.split_at(script_len);
+
+        Ok(Self {
+            script: String::from_utf8(script.to_vec()).context("bundle script is not utf-8")?,
+            data: (!data.is_empty())
+                .then(|| String::from_utf8(data.to_vec()))
+                .transpose()
+                .context("bundle data is not utf-8")?,
+        })
+    }
+
+    /// Serializes the bundle to the bytes parsed by `Bundle::from_bytes`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let data = self.data.as_deref().unwrap_or_default();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(format!("{} {}\n", self.script.len(), data.len()).as_bytes());
+        bytes.extend_from_slice(self.script.as_bytes());
+        bytes.extend_from_slice(data.as_bytes());
+        bytes
+    }
+}
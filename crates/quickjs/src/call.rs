@@ -0,0 +1,84 @@
+use std::fmt::Debug;
+
+use crate::{CancellationToken, ConsoleCallback, TimeLimit};
+
+/// Options for a single execution passed to `QuickJS::try_execute_with_options`, e.g. to run a guarded script for a
+/// request with its own time limit.
+///
+/// The options compose, unlike the `try_execute_*` shorthands such as `QuickJS::try_execute_guarded` which each set
+/// one of them. Options which are not set behave as with `QuickJS::try_execute`.
+#[derive(Clone, Default)]
+pub struct ExecuteOptions {
+    /// Optional script which must evaluate to `true` for the script to run.
+    pub(crate) guard: Option<String>,
+    /// Optional identifier of the call attached to any error it returns and passed with its console lines.
+    pub(crate) request_id: Option<String>,
+    /// Optional time limit which overrides the instance's time limit.
+    pub(crate) time_limit: Option<TimeLimit>,
+    /// Optional token which interrupts the execution when cancelled.
+    pub(crate) cancellation: Option<CancellationToken>,
+    /// Optional names of the only host functions the script can call.
+    pub(crate) capabilities: Option<Vec<String>>,
+    /// Optional function receiving console lines, which replaces the instance's `with_live_console` callback.
+    pub(crate) on_console: Option<ConsoleCallback>,
+}
+
+impl Debug for ExecuteOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExecuteOptions")
+            .field("guard", &self.guard)
+            .field("request_id", &self.request_id)
+            .field("time_limit", &self.time_limit)
+            .field("cancellation", &self.cancellation)
+            .field("capabilities", &self.capabilities)
+            .field("on_console", &self.on_console.is_some())
+            .finish()
+    }
+}
+
+impl ExecuteOptions {
+    /// Creates options which execute the script as `QuickJS::try_execute` would.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a guard script which is evaluated first and must evaluate to `true` for the script to run, as with
+    /// `QuickJS::try_execute_guarded`.
+    pub fn with_guard(mut self, guard: impl Into<String>) -> Self {
+        self.guard = Some(guard.into());
+        self
+    }
+
+    /// Sets an identifier of the call which is attached to any error it returns and passed with its console lines, as
+    /// with `QuickJS::try_execute_with_request_id`.
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Sets a time limit for this call which overrides the instance's time limit, as with
+    /// `QuickJS::try_execute_with_timeout`.
+    pub fn with_time_limit(mut self, time_limit: TimeLimit) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    /// Sets a token which stops the execution when cancelled, as with `QuickJS::try_execute_cancellable`.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Exposes only the named host functions to the script, as with `QuickJS::try_execute_with_capabilities`.
+    pub fn with_capabilities(mut self, capabilities: &[&str]) -> Self {
+        self.capabilities = Some(capabilities.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    /// Sets a function receiving each console line of this call instead of the instance's `with_live_console`
+    /// callback, as with `QuickJS::try_execute_transcript`.
+    pub fn with_console(mut self, callback: ConsoleCallback) -> Self {
+        self.on_console = Some(callback);
+        self
+    }
+}
@@ -0,0 +1,73 @@
+use cap_std::time::{Instant, SystemTime};
+use std::{
+    sync::{Arc, Mutex},
+    time::{self, Duration},
+};
+use wasi_common::{WasiClocks, WasiMonotonicClock, WasiSystemClock};
+
+/// A clock controlled by the host rather than the wall clock, for simulation workloads.
+///
+/// The guest's realtime clock (e.g. `Date.now()`) and monotonic clock read this clock, which only advances when
+/// `advance` is called. Clones share the same time, so the host can keep a clone and advance it while a script runs
+/// or between executions.
+#[derive(Clone, Debug)]
+pub struct VirtualClock {
+    /// The time elapsed since the clock was created.
+    elapsed: Arc<Mutex<Duration>>,
+    /// The realtime at which the clock was created.
+    start: time::SystemTime,
+    /// A fixed instant from which the monotonic time is measured.
+    origin: time::Instant,
+}
+
+impl VirtualClock {
+    /// Creates a new clock starting at `start`.
+    pub fn new(start: time::SystemTime) -> Self {
+        Self {
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+            start,
+            origin: time::Instant::now(),
+        }
+    }
+
+    /// Advances the clock by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// Returns the current time of the clock.
+    pub fn now(&self) -> time::SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Returns WASI clocks which read this clock.
+    pub(crate) fn clocks(&self) -> WasiClocks {
+        WasiClocks::new()
+            .with_system(self.clone())
+            .with_monotonic(self.clone())
+    }
+}
+
+impl WasiSystemClock for VirtualClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self, _precision: Duration) -> SystemTime {
+        SystemTime::from_std(VirtualClock::now(self))
+    }
+}
+
+impl WasiMonotonicClock for VirtualClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self, _precision: Duration) -> Instant {
+        Instant::from_std(self.origin + self.elapsed())
+    }
+}
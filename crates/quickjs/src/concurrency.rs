@@ -0,0 +1,57 @@
+use std::sync::{Condvar, Mutex};
+
+use crate::QuickJSError;
+
+/// A counting semaphore limiting the number of executions which run at once.
+///
+/// One semaphore is shared by a `QuickJS` instance and its clones. Each execution holds a `Permit` while its store
+/// and memory are allocated and returns it when it completes.
+#[derive(Debug)]
+pub(crate) struct Semaphore {
+    /// The maximum number of executions which run at once.
+    max: usize,
+    /// The number of executions currently running.
+    running: Mutex<usize>,
+    /// Notified when a permit is returned.
+    released: Condvar,
+    /// Fail with `QuickJSError::AtCapacity` rather than waiting for a permit when all are taken.
+    fail_at_capacity: bool,
+}
+
+impl Semaphore {
+    /// Creates a semaphore allowing `max` executions to run at once.
+    pub(crate) fn new(max: usize, fail_at_capacity: bool) -> Self {
+        Self {
+            max,
+            running: Mutex::new(0),
+            released: Condvar::new(),
+            fail_at_capacity,
+        }
+    }
+
+    /// Takes a permit, waiting until one is returned if all are taken, or failing if `fail_at_capacity` is set.
+    pub(crate) fn acquire(&self) -> Result<Permit<'_>, QuickJSError> {
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.max {
+            if self.fail_at_capacity {
+                return Err(QuickJSError::AtCapacity(self.max));
+            }
+            running = self.released.wait(running).unwrap();
+        }
+        *running += 1;
+
+        Ok(Permit { semaphore: self })
+    }
+}
+
+/// A permit to run an execution, returned to its semaphore when dropped.
+pub(crate) struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.running.lock().unwrap() -= 1;
+        self.semaphore.released.notify_one();
+    }
+}
@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+/// Returns the CPU time consumed by the calling thread, or `None` if it is not available on this platform.
+///
+/// Wasmtime runs the guest on the thread which calls it so the difference across a call is the CPU time of the guest.
+#[cfg(unix)]
+pub(crate) fn thread_cpu_time() -> Option<Duration> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let result = unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) };
+    (result == 0).then(|| Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+/// Returns the CPU time consumed by the calling thread, or `None` if it is not available on this platform.
+#[cfg(not(unix))]
+pub(crate) fn thread_cpu_time() -> Option<Duration> {
+    None
+}
@@ -0,0 +1,30 @@
+use serde::Deserialize;
+
+/// A problem found in a script by `QuickJS::diagnose` without running it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The error message, e.g. `unexpected token in expression: ''`.
+    pub message: String,
+    /// The kind of error raised by the parser, e.g. `SyntaxError` or `ReferenceError` for early errors such as an
+    /// invalid assignment target.
+    pub kind: String,
+    /// The line of the script at which the problem was found (1-based), if known.
+    pub line: Option<u32>,
+    /// The column of the script at which the problem was found (1-based), if reported by QuickJS.
+    pub column: Option<u32>,
+    /// How severe the problem is.
+    pub severity: Severity,
+}
+
+/// The severity of a `Diagnostic`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The script cannot be executed.
+    Error,
+    /// The script can be executed but may not behave as intended.
+    ///
+    /// The QuickJS parser has no warnings: patterns such as unreachable code or duplicate declarations in sloppy mode
+    /// are accepted silently, so `QuickJS::diagnose` currently only reports errors.
+    Warning,
+}
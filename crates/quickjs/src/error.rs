@@ -0,0 +1,180 @@
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Error code reported by the guest when the input data is not valid JSON.
+pub(crate) const ERROR_INVALID_INPUT_DATA: i32 = 3;
+/// Error code reported by the guest when the input data is nested deeper than the maximum input depth.
+pub(crate) const ERROR_INPUT_TOO_DEEP: i32 = 4;
+/// Error code reported by the guest when the output contains a circular reference.
+pub(crate) const ERROR_CIRCULAR_REFERENCE: i32 = 5;
+/// Error code reported by the guest when it interrupted the script for exceeding the time limit.
+pub(crate) const ERROR_TIME_LIMIT_EXCEEDED: i32 = 6;
+/// Error code reported by the guest when the guard script rejected the input data.
+pub(crate) const ERROR_GUARD_REJECTED: i32 = 7;
+
+/// Errors raised by `QuickJS` that callers may want to handle distinctly from a generic failure.
+///
+/// These are returned wrapped in an `anyhow::Error` and can be recovered with `downcast_ref::<QuickJSError>()`.
+#[derive(Debug, Error)]
+pub enum QuickJSError {
+    /// The guest runtime itself panicked or exited, as opposed to the script throwing an exception.
+    #[error("guest aborted{}", describe_abort(.exit_code, .message))]
+    GuestAborted {
+        /// The exit code passed to `proc_exit`, if the guest reported a failure before exiting rather than trapped.
+        exit_code: Option<i32>,
+        /// The panic or error message reported by the guest before aborting, if any.
+        message: Option<String>,
+    },
+    /// The guest exited with a non-zero exit code passed to `proc_exit`, e.g. by a script calling `exit(code)`. An
+    /// exit code of `0` is not an error.
+    #[error("guest exited with exit code {0}")]
+    GuestExit(i32),
+    /// The execution did not complete within the timeout passed to `QuickJS::try_execute_with_hard_timeout`.
+    #[error("exceeds hard timeout of {0:?}")]
+    HardTimeout(std::time::Duration),
+    /// The execution did not complete within the time limit set with `QuickJSBuilder::with_time_limit`.
+    #[error("exceeds time limit")]
+    TimeLimitExceeded {
+        /// The JavaScript stack at the moment the script was interrupted, innermost frame first. Only captured with
+        /// `QuickJSBuilder::with_stack_on_timeout` and if the guest could interrupt the script itself.
+        stack: Option<String>,
+    },
+    /// The execution was cancelled with a `CancellationToken`.
+    #[error("execution cancelled")]
+    Cancelled,
+    /// The execution consumed all of the fuel set with `QuickJSBuilder::with_fuel_limit`.
+    #[error("exceeds fuel limit of {0}")]
+    FuelExhausted(u64),
+    /// The instance was shut down with `QuickJS::shutdown` before or during the execution.
+    #[error("instance shut down")]
+    Shutdown,
+    /// The maximum number of concurrent executions set with `QuickJSBuilder::with_max_concurrency` were already running
+    /// and `QuickJSBuilder::with_fail_at_capacity` is set.
+    #[error("at capacity of {0} concurrent executions")]
+    AtCapacity(usize),
+    /// The result is larger than the limit set with `QuickJSBuilder::with_max_output_bytes` and could not be truncated.
+    #[error("output of {size} bytes exceeds limit of {limit} bytes")]
+    OutputTooLarge {
+        /// The size of the result in bytes.
+        size: usize,
+        /// The limit in bytes.
+        limit: usize,
+    },
+    /// The input data is nested deeper than the maximum set with `QuickJSBuilder::with_max_input_depth`. Raised before
+    /// the input data is parsed so the script never runs.
+    #[error("{0}")]
+    InputTooDeep(String),
+    /// The result or an emitted value refers to itself so cannot be serialized to JSON. Use
+    /// `QuickJSBuilder::with_output_placeholder` to replace the reference instead.
+    #[error("circular reference at {path}")]
+    CircularReference {
+        /// The path of the reference which closes the cycle, e.g. `$.node.parent`.
+        path: String,
+    },
+    /// A custom module set with `QuickJSBuilder::with_module` does not match the interface provided by the host.
+    #[error("incompatible module: missing exports {missing_exports:?}, unexpected imports {unexpected_imports:?}")]
+    IncompatibleModule {
+        /// The exports required by the host which the module does not provide.
+        missing_exports: Vec<String>,
+        /// The imports required by the module which the host does not provide, as `module::name`.
+        unexpected_imports: Vec<String>,
+    },
+    /// The input data is not valid JSON. Raised before the `data` global is bound so the script never runs.
+    #[error("invalid input data: {message}")]
+    InvalidInputData {
+        /// The line of the input data at which parsing failed (1-based).
+        line: usize,
+        /// The column of the input data at which parsing failed (1-based).
+        column: usize,
+        /// The parse error, including its position.
+        message: String,
+    },
+    /// Jobs of a batch executed with `QuickJS::try_execute_batch_all` failed. Holds the index and error of every job
+    /// which failed, in order. Each error can be downcast to `QuickJSError` as for a single execution.
+    #[error("{}", describe_batch(.0))]
+    Batch(Vec<(usize, anyhow::Error)>),
+    /// The guard script passed to `QuickJS::try_execute_guarded` did not return `true`, so the script never ran.
+    /// Holds the exception the guard threw, or why its result was rejected.
+    #[error("guard rejected: {0}")]
+    GuardRejected(String),
+}
+
+/// An error a host function returns to throw a catchable exception in the script rather than abort the execution.
+///
+/// Return it from a function registered with `QuickJSBuilder::with_host_function`, e.g.
+/// `return Err(HostFunctionError::new("cache miss").into())`, for expected failures the script can handle with
+/// `try`/`catch`. The script receives an `InternalError`, a subclass of `Error`, whose message is `message`. Any
+/// other error returned by a host function still aborts the execution.
+#[derive(Debug, Error)]
+#[error("{message}")]
+pub struct HostFunctionError {
+    /// The message of the exception thrown in the script.
+    pub message: String,
+}
+
+impl HostFunctionError {
+    /// Creates an error which throws an exception with `message` in the script.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+/// The payload of an `ERROR_INVALID_INPUT_DATA` error reported by the guest.
+#[derive(Deserialize)]
+struct InvalidInputData {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl QuickJSError {
+    /// Converts an error reported by the guest through `set_output` into an error based on its error code.
+    ///
+    /// Any unrecognised code (including `1` for an exception thrown by the script) is returned as the message.
+    pub(crate) fn from_guest(code: i32, message: String) -> anyhow::Error {
+        match code {
+            ERROR_INPUT_TOO_DEEP => QuickJSError::InputTooDeep(message).into(),
+            ERROR_CIRCULAR_REFERENCE => QuickJSError::CircularReference { path: message }.into(),
+            ERROR_GUARD_REJECTED => QuickJSError::GuardRejected(message).into(),
+            ERROR_TIME_LIMIT_EXCEEDED => QuickJSError::TimeLimitExceeded {
+                stack: Some(message),
+            }
+            .into(),
+            ERROR_INVALID_INPUT_DATA => match serde_json::from_str::<InvalidInputData>(&message) {
+                Ok(InvalidInputData {
+                    line,
+                    column,
+                    message,
+                }) => QuickJSError::InvalidInputData {
+                    line,
+                    column,
+                    message,
+                }
+                .into(),
+                Err(_) => anyhow::anyhow!(message),
+            },
+            _ => anyhow::anyhow!(message),
+        }
+    }
+}
+
+fn describe_batch(failures: &[(usize, anyhow::Error)]) -> String {
+    let failures = failures
+        .iter()
+        .map(|(index, err)| format!("job {index}: {err:#}"))
+        .collect::<Vec<_>>();
+    format!("{} jobs failed: {}", failures.len(), failures.join("; "))
+}
+
+fn describe_abort(exit_code: &Option<i32>, message: &Option<String>) -> String {
+    let mut description = String::new();
+    if let Some(exit_code) = exit_code {
+        description.push_str(&format!(" with exit code {exit_code}"));
+    }
+    if let Some(message) = message {
+        description.push_str(&format!(": {message}"));
+    }
+    description
+}
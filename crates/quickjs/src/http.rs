@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// An HTTP request bound as the `request` global by `QuickJS::try_execute_http`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct HttpRequest {
+    /// The request method, e.g. `GET`.
+    pub method: String,
+    /// The request path including any query string, e.g. `/users?page=2`.
+    pub path: String,
+    /// The request headers by name.
+    pub headers: BTreeMap<String, String>,
+    /// The request body, if any.
+    pub body: Option<String>,
+}
+
+/// The HTTP response returned by a script executed with `QuickJS::try_execute_http`.
+///
+/// Scripts return it as an object of the form `{ statusCode, headers, body }`, where `headers` and `body` are
+/// optional.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct HttpResponse {
+    /// The response status code, between 100 and 599.
+    pub status_code: u16,
+    /// The response headers by name.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// The response body, if any.
+    #[serde(default)]
+    pub body: Option<String>,
+}
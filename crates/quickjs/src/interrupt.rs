@@ -0,0 +1,105 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread,
+    time::Duration,
+};
+use wasmtime::Engine;
+
+use crate::QuickJSError;
+
+/// A token used to cancel an in-flight execution from another thread.
+///
+/// Cancellation is observed at the next epoch tick or fuel checkpoint so the instance must be built with a time limit,
+/// `QuickJSBuilder::with_interruptible` or `QuickJSBuilder::with_interrupt_fuel_interval`. A cancelled execution
+/// returns `QuickJSError::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a new token which has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every execution using this token (or a clone of it).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether `cancel` has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// A background thread which increments the engine epoch every `interval`.
+///
+/// One ticker is shared by all executions of a `QuickJS` instance. Each execution sets its epoch deadline
+/// relative to the current epoch so concurrent executions do not interfere with each other. The thread
+/// stops when the ticker is dropped.
+pub(crate) struct Ticker {
+    stopped: Arc<AtomicBool>,
+}
+
+impl Ticker {
+    /// Starts a thread incrementing the epoch of `engine` every `interval`.
+    pub(crate) fn start(engine: Engine, interval: Duration) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        thread::spawn(move || {
+            while !stopped_clone.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+                engine.increment_epoch();
+            }
+        });
+        Self { stopped }
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A waker which does nothing as `block_on` polls its future again as soon as it yields.
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Drives `future` to completion on the current thread, checking `cancellation` and `shutdown` every time it yields.
+///
+/// Wasmtime futures only yield at fuel checkpoints so are always ready to be polled again. Dropping the future on
+/// cancellation unwinds the guest.
+pub(crate) fn block_on<F: Future>(
+    future: F,
+    cancellation: Option<&CancellationToken>,
+    shutdown: &CancellationToken,
+) -> Result<F::Output, QuickJSError> {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return Ok(output),
+            Poll::Pending => {
+                if shutdown.is_cancelled() {
+                    return Err(QuickJSError::Shutdown);
+                }
+                if cancellation.is_some_and(|cancellation| cancellation.is_cancelled()) {
+                    return Err(QuickJSError::Cancelled);
+                }
+            }
+        }
+    }
+}
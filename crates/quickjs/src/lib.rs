@@ -1,26 +1,142 @@
-use anyhow::{anyhow, bail, Result};
+mod bundle;
+mod call;
+mod clock;
+mod concurrency;
+mod cpu;
+mod diagnostic;
+mod error;
+mod http;
+mod interrupt;
+mod limiter;
+mod numbers;
+mod output;
+mod prepared;
+mod registry;
+mod secret;
+mod stream;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod transcoder;
+mod wasi;
+
+pub use bundle::Bundle;
+pub use call::ExecuteOptions;
+pub use clock::VirtualClock;
+pub use diagnostic::{Diagnostic, Severity};
+pub use error::{HostFunctionError, QuickJSError};
+pub use http::{HttpRequest, HttpResponse};
+pub use interrupt::CancellationToken;
+pub use output::{
+    AfterExecuteCallback, BeforeExecuteCallback, ConsoleCallback, ConsoleStream, ExecuteOutput,
+    ExecuteReport, ExecuteStats, LimitStatus, MemoryThresholdCallback, OutputEvent,
+    OutputPlaceholder,
+};
+pub use prepared::PreparedScript;
+pub use registry::QuickJSRegistry;
+pub use stream::ExecuteStream;
+pub use transcoder::Transcoder;
+pub use wasmtime::{Caller, ExternType, Linker};
+
+use anyhow::{anyhow, bail, Context, Result};
+use concurrency::Semaphore;
+use interrupt::Ticker;
+use limiter::Limiter;
+use numbers::{mark_big_integers, unmark_big_integers};
+use output::truncate_output;
+use secret::Secrets;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
     fmt::Debug,
-    path::PathBuf,
-    sync::mpsc::sync_channel,
-    thread::{self},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{channel, RecvTimeoutError},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    thread,
     time::Duration,
 };
-use wasi_common::sync::WasiCtxBuilder;
-use wasi_common::WasiCtx;
+use wasi::{WasiInstance, WasiTemplate};
+use wasi_common::{I32Exit, WasiCtx};
 use wasmtime::*;
 
+/// A function defining additional host functions in the linker, set with `QuickJSBuilder::with_linker_extension`.
+pub type LinkerExtension = Arc<dyn Fn(&mut Linker<State>) -> Result<()> + Send + Sync>;
+
+/// A function receiving each value passed to `emit` as soon as the guest emits it, rather than once it returns.
+type EmitCallback = Arc<dyn Fn(Vec<u8>) + Send + Sync>;
+
+/// A function applied to the input data on the host before it is bound as `data` in the guest.
+pub type InputTransform = Arc<dyn Fn(serde_json::Value) -> serde_json::Value + Send + Sync>;
+
+/// A function resolving a dataset requested by a script with `loadData(name)` to its JSON value.
+pub type DataResolver = Arc<dyn Fn(&str) -> Result<serde_json::Value> + Send + Sync>;
+
+/// A cache of JSON values shared by every execution of an instance, read by scripts with `cacheGet(key)`.
+pub type SharedCache = Arc<RwLock<HashMap<String, serde_json::Value>>>;
+
+/// A function implemented by the host which scripts can call. Receives the arguments of the call as JSON values.
+pub type HostFunction =
+    Arc<dyn Fn(&[serde_json::Value]) -> Result<serde_json::Value> + Send + Sync>;
+
 static PAGE_SIZE: u32 = 65536;
+/// The default namespace under which the module imports the host functions.
+static HOST_NAMESPACE: &str = "host";
+/// The exports the host requires of the module.
+static MODULE_EXPORTS: [&str; 2] = ["memory", "_start"];
 static EPOCH_INTERVAL: u64 = 100;
+/// The globals the guest defines for scripts, which constants must not replace.
+static RESERVED_GLOBALS: [&str; 18] = [
+    "emit",
+    "exit",
+    "gc",
+    "console",
+    "readLine",
+    "setBinaryOutput",
+    "loadData",
+    "cacheGet",
+    "cacheSet",
+    "crypto",
+    "btoa",
+    "atob",
+    "structuredClone",
+    "deepEqual",
+    "AbortController",
+    "AbortSignal",
+    "Math",
+    "globalThis",
+];
+/// The default maximum number of entries scripts may grow a writable shared cache to.
+static DEFAULT_SHARED_CACHE_ENTRIES: usize = 10_000;
+/// The default maximum size in bytes of the key and JSON value of an entry scripts write to a shared cache.
+static DEFAULT_SHARED_CACHE_ENTRY_SIZE: usize = 1024 * 1024;
+/// The `set_output` code the guest uses for a value passed to `emit`, rather than the final result.
+const OUTPUT_EMITTED: i32 = -1;
 
 /// A Rust wrapper around the QuickJS JavaScript engine.
 ///
 /// This struct represents a running instance of the QuickJS engine, along with its module and configuration options.
+/// Clones share the same engine, module and epoch ticker.
+///
+/// Every execution instantiates the compiled module afresh so starts from the state captured by the Wizer snapshot:
+/// globals, prototypes and the heap modified by one execution are never observed by the next, and there is no
+/// state to reset between executions for different tenants. Only the host-side configuration, such as a
+/// `VirtualClock`, is shared. Within `try_execute_batch` each job gets a fresh JavaScript context but the jobs share
+/// one instance's memory.
+#[derive(Clone)]
 pub struct QuickJS {
     /// The underlying QuickJS engine instance.
     engine: Engine,
-    /// The module loaded into the engine.
-    module: Module,
+    /// The module loaded into the engine. It is empty until the first execution if compilation is lazy, and is shared
+    /// by clones so the module is only compiled once.
+    module: Arc<OnceLock<Module>>,
+    /// The path to a custom module file, or `None` for the embedded default module.
+    module_path: Option<PathBuf>,
+    /// A linker defining the WASI functions. It is cloned for every execution so the WASI functions are only
+    /// defined once, and the host functions of the execution are added to the clone.
+    linker: Linker<State>,
     /// Whether to inherit standard output from the parent process.
     inherit_stdout: bool,
     /// Whether to inherit standard error from the parent process.
@@ -29,6 +145,97 @@ pub struct QuickJS {
     memory_limit: Option<u32>,
     /// Optional time limit for the engine. If set, will be used to interrupt long-running scripts and prevent them from consuming excessive CPU time.
     time_limit: Option<TimeLimit>,
+    /// Optional seed for `Math.random` and `crypto.getRandomValues`. If unset, real entropy is used.
+    random_seed: Option<u64>,
+    /// Optional number of bytes allocated between automatic garbage collections. If unset, the QuickJS default is used.
+    gc_threshold: Option<u32>,
+    /// Optional clock controlled by the host. If set, the guest reads this instead of the host clocks.
+    virtual_clock: Option<VirtualClock>,
+    /// Whether the output is pretty-printed JSON rather than compact JSON.
+    pretty_output: bool,
+    /// Optional function applied to the input data before it is bound as `data`.
+    input_transform: Option<InputTransform>,
+    /// Whether the `data` global is deeply frozen so scripts cannot mutate it.
+    read_only_data: bool,
+    /// Whether scripts are evaluated in strict mode.
+    strict_mode: bool,
+    /// Optional maximum nesting depth of the input data. If unset, the guest default of 128 is used.
+    max_input_depth: Option<u32>,
+    /// Optional names of the only properties included in the output.
+    output_allowlist: Option<Vec<String>>,
+    /// Optional names of properties excluded from the output.
+    output_denylist: Option<Vec<String>>,
+    /// The namespace under which the host functions are imported by the module.
+    import_namespace: String,
+    /// Optional function receiving each line written to the console as it is written.
+    console_callback: Option<ConsoleCallback>,
+    /// How long a cancelled execution may keep running before it is interrupted.
+    cancellation_grace: Duration,
+    /// Functions implemented by the host which scripts can call as globals, by name.
+    host_functions: BTreeMap<String, HostFunction>,
+    /// Optional amount of fuel an execution may consume before it is interrupted.
+    fuel_limit: Option<u64>,
+    /// Optional amount of fuel consumed between checks of the cancellation token.
+    interrupt_fuel_interval: Option<u64>,
+    /// Optional limit in bytes of the memory allocated by the QuickJS runtime.
+    js_heap_limit: Option<usize>,
+    /// Values bound as globals of the same name which are redacted from everything the guest returns to the host.
+    secrets: BTreeMap<String, String>,
+    /// Whether scripts are evaluated as ES modules whose default export is the result.
+    module_eval: bool,
+    /// Optional function resolving the datasets scripts load by name with `loadData`.
+    data_resolver: Option<DataResolver>,
+    /// Optional fractions of the memory limit at which the callback is notified as the guest's memory grows.
+    memory_thresholds: Option<(Vec<f64>, MemoryThresholdCallback)>,
+    /// How the result of a script is determined.
+    eval_mode: EvalMode,
+    /// The name of the global the input data is bound to, if not `data`.
+    data_global: Option<String>,
+    /// Bytes the guest reads from standard input. Empty if unset.
+    stdin: Option<Arc<[u8]>>,
+    /// Whether a promise rejection which is never handled fails the execution.
+    unhandled_rejection_is_error: bool,
+    /// The placeholder for values in the output which cannot be serialized to JSON, if they are not an error.
+    output_placeholder: Option<OutputPlaceholder>,
+    /// A function called before every execution which can reject it.
+    before_execute: Option<BeforeExecuteCallback>,
+    /// A function called after every execution with its outcome.
+    after_execute: Option<AfterExecuteCallback>,
+    /// Optional cache shared by every execution and whether scripts can write to it.
+    shared_cache: Option<(SharedCache, bool)>,
+    /// The maximum number of entries scripts may grow the shared cache to and the maximum size of each entry.
+    shared_cache_limits: (usize, usize),
+    /// Optional semaphore limiting the executions of this instance and its clones which run at once.
+    max_concurrency: Option<Arc<Semaphore>>,
+    /// Whether the guest captures its stack when interrupted by the time limit.
+    stack_on_timeout: bool,
+    /// Optional limit in bytes of the JSON result of an execution.
+    max_output_bytes: Option<usize>,
+    /// Whether a result larger than `max_output_bytes` is truncated rather than failing the execution.
+    truncate_output: bool,
+    /// Whether integers outside the safe integer range are exchanged with the guest exactly as `BigInt`s.
+    big_integers: bool,
+    /// Optional transcoder between another format and the JSON values exchanged with scripts.
+    transcoder: Option<Arc<dyn Transcoder>>,
+    /// The encoding of scripts passed as bytes to `try_execute_encoded`.
+    source_encoding: SourceEncoding,
+    /// Optional maximum length of the strings builtins such as `repeat` may create.
+    max_string_length: Option<u32>,
+    /// Optional maximum length of the arrays builtins such as `new Array(n)` may create.
+    max_array_length: Option<u32>,
+    /// Values bound as deeply frozen globals of the same name.
+    constants: BTreeMap<String, serde_json::Value>,
+    /// The interval at which the epoch is incremented if epoch interruption is enabled.
+    epoch_interval: Duration,
+    /// The thread incrementing the epoch. Set if the instance has a time limit or is interruptible.
+    ticker: Option<Arc<Ticker>>,
+    /// Cancelled by `shutdown` to stop every execution of this instance and its clones.
+    shutdown: CancellationToken,
+    /// The patterns redacted from everything the guest sends to the host, derived from `secrets`.
+    redacted: Secrets,
+    /// The WASI configuration of every execution, resolved once from `stdin`, `inherit_stdout`, `inherit_stderr`,
+    /// `virtual_clock` and the secrets.
+    wasi: WasiTemplate,
 }
 
 impl Debug for QuickJS {
@@ -38,6 +245,63 @@ impl Debug for QuickJS {
             .field("inherit_stderr", &self.inherit_stderr)
             .field("memory_limit", &self.memory_limit)
             .field("time_limit", &self.time_limit)
+            .field("random_seed", &self.random_seed)
+            .field("gc_threshold", &self.gc_threshold)
+            .field("virtual_clock", &self.virtual_clock)
+            .field("pretty_output", &self.pretty_output)
+            .field("input_transform", &self.input_transform.is_some())
+            .field("read_only_data", &self.read_only_data)
+            .field("strict_mode", &self.strict_mode)
+            .field("max_input_depth", &self.max_input_depth)
+            .field("output_allowlist", &self.output_allowlist)
+            .field("output_denylist", &self.output_denylist)
+            .field("import_namespace", &self.import_namespace)
+            .field("live_console", &self.console_callback.is_some())
+            .field("cancellation_grace", &self.cancellation_grace)
+            .field("secrets", &self.secrets.keys().collect::<Vec<_>>())
+            .field("constants", &self.constants.keys().collect::<Vec<_>>())
+            .field(
+                "host_functions",
+                &self.host_functions.keys().collect::<Vec<_>>(),
+            )
+            .field("fuel_limit", &self.fuel_limit)
+            .field("interrupt_fuel_interval", &self.interrupt_fuel_interval)
+            .field("js_heap_limit", &self.js_heap_limit)
+            .field("module_eval", &self.module_eval)
+            .field("data_resolver", &self.data_resolver.is_some())
+            .field(
+                "memory_thresholds",
+                &self
+                    .memory_thresholds
+                    .as_ref()
+                    .map(|(thresholds, _)| thresholds),
+            )
+            .field("eval_mode", &self.eval_mode)
+            .field("data_global", &self.data_global)
+            .field("stdin", &self.stdin.as_ref().map(|stdin| stdin.len()))
+            .field(
+                "unhandled_rejection_is_error",
+                &self.unhandled_rejection_is_error,
+            )
+            .field("output_placeholder", &self.output_placeholder)
+            .field("before_execute", &self.before_execute.is_some())
+            .field("after_execute", &self.after_execute.is_some())
+            .field(
+                "shared_cache",
+                &self.shared_cache.as_ref().map(|(_, writable)| writable),
+            )
+            .field("shared_cache_limits", &self.shared_cache_limits)
+            .field("max_concurrency", &self.max_concurrency)
+            .field("stack_on_timeout", &self.stack_on_timeout)
+            .field("max_output_bytes", &self.max_output_bytes)
+            .field("truncate_output", &self.truncate_output)
+            .field("big_integers", &self.big_integers)
+            .field("transcoder", &self.transcoder.is_some())
+            .field("source_encoding", &self.source_encoding)
+            .field("max_string_length", &self.max_string_length)
+            .field("max_array_length", &self.max_array_length)
+            .field("interruptible", &self.ticker.is_some())
+            .field("shutdown", &self.shutdown.is_cancelled())
             .finish()
     }
 }
@@ -63,21 +327,32 @@ impl QuickJS {
         memory_limit: Option<u32>,
         time_limit: Option<TimeLimit>,
     ) -> Result<Self> {
-        let engine = Engine::new(Config::default().epoch_interruption(time_limit.is_some()))?;
-
-        let module = match path {
-            Some(path) => Module::from_file(&engine, path)?,
-            None => Module::from_binary(&engine, include_bytes!("../../../quickjs.wasm"))?,
-        };
-        Ok(Self {
-            engine,
-            module,
-            inherit_stdout,
-            inherit_stderr,
+        QuickJSBuilder {
+            module: path,
+            inherit_stdout: Some(inherit_stdout),
+            inherit_stderr: Some(inherit_stderr),
             memory_limit,
             time_limit,
-        })
+            ..Default::default()
+        }
+        .build()
     }
+
+    /// Creates a new instance of `QuickJS` with the default configuration.
+    ///
+    /// Use `QuickJSBuilder` to configure the instance.
+    pub fn new() -> Result<Self> {
+        QuickJSBuilder::new().build()
+    }
+}
+
+/// Builds a `QuickJS` instance with the default configuration and executes the given JavaScript code with optional
+/// input data.
+///
+/// This is convenient for one-off scripts. Building an instance compiles the module so reuse a `QuickJS` instance to
+/// execute many scripts.
+pub fn eval(script: &str, data: Option<&str>) -> Result<Option<String>> {
+    QuickJS::new()?.try_execute(script, data)
 }
 
 /// A builder for creating a `QuickJS` instance.
@@ -87,6 +362,10 @@ impl QuickJS {
 pub struct QuickJSBuilder {
     /// The path to a custom module file (optional).
     module: Option<PathBuf>,
+    /// Functions defining additional host functions for a custom module (default: none).
+    linker_extensions: Vec<LinkerExtension>,
+    /// Whether the module is compiled by the first execution rather than by `build` (default: false).
+    lazy_compilation: Option<bool>,
     /// Whether to inherit standard output from the parent process (default: false).
     inherit_stdout: Option<bool>,
     /// Whether to inherit standard error from the parent process (default: false).
@@ -95,6 +374,90 @@ pub struct QuickJSBuilder {
     memory_limit: Option<u32>,
     /// Optional time limit for the engine. If set, will be used to interrupt long-running scripts and prevent them from consuming excessive CPU time.
     time_limit: Option<TimeLimit>,
+    /// Optional seed for `Math.random` and `crypto.getRandomValues` (default: real entropy).
+    random_seed: Option<u64>,
+    /// Optional number of bytes allocated between automatic garbage collections (default: QuickJS default of 256KiB).
+    gc_threshold: Option<u32>,
+    /// Optional clock controlled by the host (default: host clocks).
+    virtual_clock: Option<VirtualClock>,
+    /// Whether the output is pretty-printed JSON (default: false).
+    pretty_output: Option<bool>,
+    /// Optional function applied to the input data (default: none).
+    input_transform: Option<InputTransform>,
+    /// Whether the `data` global is deeply frozen (default: false).
+    read_only_data: Option<bool>,
+    /// Whether scripts are evaluated in strict mode (default: false).
+    strict_mode: Option<bool>,
+    /// Optional maximum nesting depth of the input data (default: 128).
+    max_input_depth: Option<u32>,
+    /// Optional names of the only properties included in the output (default: all).
+    output_allowlist: Option<Vec<String>>,
+    /// Optional names of properties excluded from the output (default: none).
+    output_denylist: Option<Vec<String>>,
+    /// The namespace under which the host functions are imported (default: `host`).
+    import_namespace: Option<String>,
+    /// Optional function receiving each console line as it is written (default: none).
+    console_callback: Option<ConsoleCallback>,
+    /// How long a cancelled execution may keep running (default: zero).
+    cancellation_grace: Option<Duration>,
+    /// Functions implemented by the host which scripts can call (default: none).
+    host_functions: BTreeMap<String, HostFunction>,
+    /// Optional amount of fuel an execution may consume (default: unlimited).
+    fuel_limit: Option<u64>,
+    /// Optional amount of fuel consumed between checks of the cancellation token (default: none).
+    interrupt_fuel_interval: Option<u64>,
+    /// Optional limit in bytes of the memory allocated by the QuickJS runtime (default: unlimited).
+    js_heap_limit: Option<usize>,
+    /// Values bound as globals and redacted from the output (default: none).
+    secrets: BTreeMap<String, String>,
+    /// Whether scripts are evaluated as ES modules (default: false).
+    module_eval: Option<bool>,
+    /// Optional function resolving datasets loaded with `loadData` (default: none).
+    data_resolver: Option<DataResolver>,
+    /// Optional fractions of the memory limit at which the callback is notified (default: none).
+    memory_thresholds: Option<(Vec<f64>, MemoryThresholdCallback)>,
+    /// How the result of a script is determined (default: `EvalMode::Expression`).
+    eval_mode: Option<EvalMode>,
+    /// The name of the global the input data is bound to (default: `data`).
+    data_global: Option<String>,
+    /// Bytes the guest reads from standard input (default: empty).
+    stdin: Option<Arc<[u8]>>,
+    /// Whether a promise rejection which is never handled fails the execution (default: false).
+    unhandled_rejection_is_error: Option<bool>,
+    /// The placeholder for values in the output which cannot be serialized to JSON (default: none, they are an error).
+    output_placeholder: Option<OutputPlaceholder>,
+    /// A function called before every execution which can reject it (optional).
+    before_execute: Option<BeforeExecuteCallback>,
+    /// A function called after every execution with its outcome (optional).
+    after_execute: Option<AfterExecuteCallback>,
+    /// Optional cache shared by every execution (default: none).
+    shared_cache: Option<(SharedCache, bool)>,
+    /// Optional maximum entries and entry size scripts may write to the shared cache (default: 10000 and 1MiB).
+    shared_cache_limits: Option<(usize, usize)>,
+    /// Optional maximum number of executions which run at once (default: unlimited).
+    max_concurrency: Option<usize>,
+    /// Whether executions fail rather than wait when the maximum are running (default: false).
+    fail_at_capacity: Option<bool>,
+    /// Whether the guest captures its stack when interrupted by the time limit (default: false).
+    stack_on_timeout: Option<bool>,
+    /// Optional limit in bytes of the JSON result of an execution (default: unlimited).
+    max_output_bytes: Option<usize>,
+    /// Whether a result larger than the output limit is truncated (default: false).
+    truncate_output: Option<bool>,
+    /// Whether large integers are exchanged exactly as `BigInt`s (default: false).
+    big_integers: Option<bool>,
+    /// Optional transcoder used by `try_execute_transcoded` (default: none).
+    transcoder: Option<Arc<dyn Transcoder>>,
+    /// The encoding of scripts passed as bytes (default: UTF-8).
+    source_encoding: Option<SourceEncoding>,
+    /// Optional maximum length of strings created by builtins (default: unlimited).
+    max_string_length: Option<u32>,
+    /// Optional maximum length of arrays created by builtins (default: unlimited).
+    max_array_length: Option<u32>,
+    /// Values bound as deeply frozen globals of the same name (default: none).
+    constants: BTreeMap<String, serde_json::Value>,
+    /// Whether executions can be interrupted without a time limit (default: false).
+    interruptible: Option<bool>,
 }
 
 impl QuickJSBuilder {
@@ -103,6 +466,25 @@ impl QuickJSBuilder {
         Self::default()
     }
 
+    /// Creates a new `QuickJSBuilder` whose executions are reproducible: the same script and input data always
+    /// produce the same output.
+    ///
+    /// This combines the determinism settings into one preset:
+    ///
+    /// * The realtime and monotonic clocks (e.g. `Date.now()` and `new Date()`) read a `VirtualClock` fixed at the
+    ///   Unix epoch. Use `with_virtual_clock` to start it elsewhere.
+    /// * `Math.random` and `crypto.getRandomValues` are seeded with `0` so return the same sequence on every
+    ///   execution. Use `with_random_seed` to choose another sequence.
+    ///
+    /// The guest has no filesystem, network or environment access and QuickJS runs scripts on a single thread in
+    /// every mode, so these need no configuration. A time limit interrupts a script at a point which depends on the
+    /// load on the host so prefer `with_fuel_limit` to bound the work of a deterministic execution.
+    pub fn deterministic() -> Self {
+        Self::new()
+            .with_virtual_clock(VirtualClock::new(std::time::UNIX_EPOCH))
+            .with_random_seed(0)
+    }
+
     /// Sets the path to a custom module file.
     ///
     /// If no module is provided, the engine will use its built-in module by default.
@@ -111,6 +493,29 @@ impl QuickJSBuilder {
         self
     }
 
+    /// Adds a function which defines additional host functions in the linker, so a custom module set with
+    /// `with_module` can import more than the standard host functions.
+    ///
+    /// The function is called once by `build`, after the WASI functions are defined, and its definitions are shared by
+    /// every execution. The standard host functions are defined for each execution afterwards, so an extension which
+    /// defines one of them makes executions fail rather than replacing it. Extensions are called in the order they
+    /// are added.
+    pub fn with_linker_extension(mut self, extension: LinkerExtension) -> Self {
+        self.linker_extensions.push(extension);
+        self
+    }
+
+    /// Controls whether the module is compiled by the first execution rather than by `build`.
+    ///
+    /// Compiling the module is the most expensive part of building an instance, so a system which builds many
+    /// instances but only executes some of them can defer it. The tradeoff is that the first execution pays the cost
+    /// of compilation, and an invalid module file is reported by that execution rather than by `build`. The compiled
+    /// module is shared by clones of the instance. Compilation is eager by default.
+    pub fn with_lazy_compilation(mut self, lazy_compilation: bool) -> Self {
+        self.lazy_compilation = Some(lazy_compilation);
+        self
+    }
+
     /// Controls whether to inherit standard output from the parent process.
     pub fn with_inherit_stdout(mut self, inherit: bool) -> Self {
         self.inherit_stdout = Some(inherit);
@@ -124,6 +529,9 @@ impl QuickJSBuilder {
     }
 
     /// Sets the memory limit for the engine in bytes.
+    ///
+    /// Wasm memory is allocated in pages of 64KiB so a limit which is not a multiple of the page size is rounded up to
+    /// the next whole page.
     pub fn with_memory_limit(mut self, limit: u32) -> Self {
         self.memory_limit = Some(limit);
         self
@@ -135,17 +543,666 @@ impl QuickJSBuilder {
         self
     }
 
+    /// Sets the seed for `Math.random` and `crypto.getRandomValues`.
+    ///
+    /// Every execution starts from the same seed so scripts that sample produce reproducible results. `Math.random`
+    /// is replaced by a seeded generator when the module's snapshot is created and the generator is reseeded at the
+    /// start of every execution, so the sequence does not depend on the snapshot, the instance or earlier executions.
+    pub fn with_random_seed(mut self, seed: u64) -> Self {
+        self.random_seed = Some(seed);
+        self
+    }
+
+    /// Controls whether executions can be interrupted by a `CancellationToken` without setting a time limit.
+    ///
+    /// This enables epoch interruption with an unlimited time budget so `try_execute_cancellable` can be used.
+    /// Epoch interruption adds a check to every loop and function entry in the compiled module plus a callback
+    /// every evaluation interval (`100µs`), which has a measurable cost (see the `try_execute_interruptible`
+    /// benchmark) so it is disabled by default. An instance with a time limit is always interruptible.
+    pub fn with_interruptible(mut self, interruptible: bool) -> Self {
+        self.interruptible = Some(interruptible);
+        self
+    }
+
+    /// Sets the number of bytes allocated since the last garbage collection which triggers an automatic collection.
+    ///
+    /// A lower threshold collects more often, keeping memory closer to the live set at the cost of more time spent
+    /// collecting. Scripts can also call the `gc()` global to collect on demand, e.g. between chunks of a large batch.
+    /// QuickJS frees most objects immediately by reference counting so garbage collection only reclaims cycles.
+    /// Collecting does not shrink the wasm linear memory, which never shrinks once grown, but freed memory is reused
+    /// by later allocations so it can keep a script under the memory limit that would otherwise exceed it.
+    pub fn with_gc_threshold(mut self, gc_threshold: u32) -> Self {
+        self.gc_threshold = Some(gc_threshold);
+        self
+    }
+
+    /// Sets a clock controlled by the host which the guest reads instead of the host clocks.
+    ///
+    /// Time only advances when `VirtualClock::advance` is called so `Date.now()` is deterministic. Keep a clone of the
+    /// clock to advance it.
+    pub fn with_virtual_clock(mut self, clock: VirtualClock) -> Self {
+        self.virtual_clock = Some(clock);
+        self
+    }
+
+    /// Controls whether the output (including values passed to `emit`) is indented JSON for human-facing output.
+    ///
+    /// Compact JSON is smaller and faster to produce so it is the default.
+    pub fn with_pretty_output(mut self, pretty_output: bool) -> Self {
+        self.pretty_output = Some(pretty_output);
+        self
+    }
+
+    /// Sets a function applied to the parsed input data before the script sees it as `data`.
+    ///
+    /// The function runs on the host for every execution, so it can strip or redact fields to enforce data governance
+    /// without modifying every script. Malformed input data is passed to the guest unchanged so it is still reported
+    /// as `QuickJSError::InvalidInputData`.
+    ///
+    /// The input data is parsed into a `serde_json::Value` for the function and serialized again afterwards, which
+    /// costs a second parse of the input on every execution and does not preserve its exact text: object keys are
+    /// reordered alphabetically and integers beyond the range of `i64`/`u64` become doubles, even with
+    /// `with_big_integers`. Scripts which depend on key order or exact large integers should not use a transform.
+    pub fn with_input_transform(mut self, transform: InputTransform) -> Self {
+        self.input_transform = Some(transform);
+        self
+    }
+
+    /// Controls whether the `data` global is deeply frozen so scripts cannot mutate their input.
+    ///
+    /// Every object and array in `data` is frozen with `Object.freeze` and `data` cannot be reassigned. Mutations
+    /// throw a `TypeError` in strict mode and are silently ignored otherwise.
+    pub fn with_read_only_data(mut self, read_only_data: bool) -> Self {
+        self.read_only_data = Some(read_only_data);
+        self
+    }
+
+    /// Controls whether scripts are evaluated in strict mode, e.g. so assigning an undeclared variable throws rather
+    /// than creating a global.
+    ///
+    /// Scripts are evaluated in sloppy mode by default, as a script would be by a browser. A script can still opt in to
+    /// strict mode itself with a `"use strict";` directive. Strict mode also applies to `compile`, and bytecode keeps
+    /// the mode it was compiled with. Scripts are evaluated as scripts rather than ES modules, which are always strict.
+    pub fn with_strict_mode(mut self, strict_mode: bool) -> Self {
+        self.strict_mode = Some(strict_mode);
+        self
+    }
+
+    /// Sets the maximum nesting depth of arrays and objects in the input data.
+    ///
+    /// Input data nested deeper is rejected with `QuickJSError::InputTooDeep` before it is parsed, so adversarial input
+    /// cannot overflow the guest stack while it is transcoded. The guest stack limits how far this can be raised.
+    pub fn with_max_input_depth(mut self, depth: u32) -> Self {
+        self.max_input_depth = Some(depth);
+        self
+    }
+
+    /// Sets the names of the only object properties included in the output, like the replacer array of
+    /// `JSON.stringify(value, replacer)`.
+    ///
+    /// The allowlist applies to objects at every depth: a nested object is only included if its property name is
+    /// allowed, and then only its allowed properties are included. Array elements are always included but objects
+    /// inside arrays are filtered. Applies to the result and to values passed to `emit`.
+    pub fn with_output_allowlist<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.output_allowlist = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the names of object properties excluded from the output, e.g. to prevent sensitive fields leaking
+    /// without modifying each script.
+    ///
+    /// The denylist applies to objects at every depth, including objects inside arrays, and is applied after the
+    /// allowlist so a property named in both is excluded. Applies to the result and to values passed to `emit`.
+    pub fn with_output_denylist<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.output_denylist = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the namespace under which the module imports the host functions (`get_script`, `set_output` etc.).
+    ///
+    /// The module built by `quickjs-wasm` imports them from `host`. This allows a custom module set with `with_module`
+    /// which imports them from another namespace, such as `env`, to be linked.
+    pub fn with_import_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.import_namespace = Some(namespace.into());
+        self
+    }
+
+    /// Sets a function which receives each line written with `console.log` or `console.error` as it is written,
+    /// e.g. to report the progress of a long-running script.
+    ///
+    /// Console lines are delivered to the function instead of stdout or stderr, regardless of `with_inherit_stdout`
+    /// and `with_inherit_stderr`. Each line is a separate call from the guest to the host which is slower than
+    /// writing to stdout. Requires building `quickjs-wasm` with the `console` feature (default).
+    pub fn with_live_console(mut self, callback: ConsoleCallback) -> Self {
+        self.console_callback = Some(callback);
+        self
+    }
+
+    /// Sets how long a cancelled execution may keep running before it is interrupted with
+    /// `QuickJSError::Cancelled`.
+    ///
+    /// Scripts observe cancellation through the `aborted` flag of an `AbortSignal`, so a grace period lets a
+    /// well-behaved script which checks `signal.aborted` clean up and return a result rather than being interrupted.
+    pub fn with_cancellation_grace(mut self, grace: Duration) -> Self {
+        self.cancellation_grace = Some(grace);
+        self
+    }
+
+    /// Registers a function implemented by the host which scripts can call as the global `name`.
+    ///
+    /// The arguments are passed to the function as JSON values and its result is returned to the script as a JSON
+    /// value. An error returned by the function aborts the execution unless it is a `HostFunctionError`, which throws
+    /// a catchable exception in the script instead. Every registered function is available to
+    /// `try_execute`; use `try_execute_with_capabilities` to expose only some of them to an execution.
+    pub fn with_host_function(mut self, name: impl Into<String>, function: HostFunction) -> Self {
+        self.host_functions.insert(name.into(), function);
+        self
+    }
+
+    /// Sets the amount of fuel an execution may consume before it is interrupted with `QuickJSError::FuelExhausted`.
+    ///
+    /// Fuel is consumed by wasmtime for every wasm instruction executed by the guest, so unlike a time limit the
+    /// limit is reached at the same point of a script on every execution regardless of the load on the host.
+    /// Consuming fuel slows the execution down.
+    pub fn with_fuel_limit(mut self, limit: u64) -> Self {
+        self.fuel_limit = Some(limit);
+        self
+    }
+
+    /// Sets the amount of fuel consumed between checks of the cancellation token passed to
+    /// `QuickJS::try_execute_cancellable`.
+    ///
+    /// Cancellation is then observed at deterministic points of the script rather than at the next epoch tick.
+    /// This enables fuel consumption (see `with_fuel_limit`) and runs every execution on wasmtime's async support,
+    /// driven on the calling thread, so the guest can yield at each checkpoint. Smaller intervals react to
+    /// cancellation sooner at the cost of more frequent yields.
+    ///
+    /// This composes with the epoch-based path: a time limit or `with_interruptible` still interrupts the execution
+    /// at the epoch interval, and whichever check observes the cancellation first stops it. Cancellation observed at
+    /// a fuel checkpoint is immediate so does not wait for `with_cancellation_grace`.
+    pub fn with_interrupt_fuel_interval(mut self, interval: u64) -> Self {
+        self.interrupt_fuel_interval = Some(interval);
+        self
+    }
+
+    /// Sets the limit in bytes of the memory allocated by the QuickJS runtime itself.
+    ///
+    /// Unlike `with_memory_limit`, which traps once the wasm linear memory cannot grow, exceeding this limit throws
+    /// an `InternalError: out of memory` which the script can catch. Set it below the memory limit so that QuickJS
+    /// fails the allocation before the wasm memory is exhausted. The limit includes the memory already allocated by
+    /// the snapshot.
+    pub fn with_js_heap_limit(mut self, limit: usize) -> Self {
+        self.js_heap_limit = Some(limit);
+        self
+    }
+
+    /// Binds `value` as the global string `name`, e.g. an API token, and redacts it from everything the guest returns.
+    ///
+    /// Every occurrence of the value in the output, emitted values, binary output, error messages, stack traces and
+    /// console lines (whether inherited or delivered with `with_live_console`) is replaced with `[REDACTED]`. A script
+    /// can still leak a transformed secret, e.g. by reversing it, so this guards against accidental disclosure rather
+    /// than a malicious script.
+    pub fn with_secret(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.secrets.insert(name.into(), value.into());
+        self
+    }
+
+    /// Sets whether scripts are evaluated as ES modules rather than classic scripts.
+    ///
+    /// A module may use `export` and its result is its `export default` value rather than the value of its last
+    /// statement, or `undefined` if it has no default export. A default export which is a promise is awaited by
+    /// running pending jobs until it settles, so `export default (async () => ...)()` returns the resolved value.
+    /// Modules are always strict.
+    ///
+    /// Top-level `await` is not supported: the bundled QuickJS release (2021-03-27) predates it, so `await` outside
+    /// an async function is a `SyntaxError`. Wrap the async work in an async function and export its promise instead,
+    /// as above. A script also cannot import other modules as the host provides none to import; the module is
+    /// registered as `script.js` only so that the host can read its default export.
+    pub fn with_module_eval(mut self, module_eval: bool) -> Self {
+        self.module_eval = Some(module_eval);
+        self
+    }
+
+    /// Sets a function which resolves the datasets scripts load on demand with the `loadData(name)` global.
+    ///
+    /// Rather than passing every dataset a script may need as input data, the script requests only the datasets it
+    /// uses and the resolver fetches each one, e.g. from a database, when it is requested. `loadData` is synchronous
+    /// and calls the resolver every time it is called. An error returned by the resolver aborts the execution.
+    pub fn with_data_resolver(mut self, resolver: DataResolver) -> Self {
+        self.data_resolver = Some(resolver);
+        self
+    }
+
+    /// Sets a function which is notified when the guest's memory grows past each of `thresholds`, given as fractions
+    /// of the memory limit (e.g. `[0.5, 0.9]`), so operators are warned before a script runs out of memory.
+    ///
+    /// Each threshold is reported at most once per execution, in ascending order, with the threshold and the size of
+    /// the memory in bytes. The function is called on the thread running the script while the memory grows so
+    /// should return quickly. Requires `with_memory_limit`.
+    pub fn with_memory_threshold_callback(
+        mut self,
+        thresholds: impl IntoIterator<Item = f64>,
+        callback: MemoryThresholdCallback,
+    ) -> Self {
+        let mut thresholds = thresholds.into_iter().collect::<Vec<_>>();
+        thresholds.sort_by(f64::total_cmp);
+        self.memory_thresholds = Some((thresholds, callback));
+        self
+    }
+
+    /// Sets how the result of a script is determined.
+    ///
+    /// By default (`EvalMode::Expression`) the result is the completion value of the last statement, as with `eval`, so
+    /// a script ending in `'quickjs' + 'wasm'` returns `"quickjswasm"` and one ending in a loop returns the value of
+    /// the loop's last statement. With `EvalMode::Statement` the script is the body of a function so only an explicit
+    /// `return` (or `emit`) produces output. It does not apply to ES modules, whose result is their default export.
+    pub fn with_eval_mode(mut self, eval_mode: EvalMode) -> Self {
+        self.eval_mode = Some(eval_mode);
+        self
+    }
+
+    /// Sets the name of the global the input data is bound to, e.g. when a script already uses `data` for something
+    /// else.
+    ///
+    /// The input data is bound as `data` by default. The name also applies to `with_read_only_data`. It must be a
+    /// JavaScript identifier, e.g. `input` or `$payload`, otherwise `build` fails.
+    pub fn with_data_global(mut self, name: impl Into<String>) -> Self {
+        self.data_global = Some(name.into());
+        self
+    }
+
+    /// Sets the bytes the guest reads from standard input, which scripts read line by line with `readLine()`.
+    ///
+    /// This complements the `data` global for scripts written to consume a stream, e.g. line-by-line processing of a
+    /// log. Every execution reads the same bytes from the start. Standard input is never inherited from the parent
+    /// process, independently of `with_inherit_stdout` and `with_inherit_stderr`, and is empty by default.
+    pub fn with_stdin(mut self, stdin: Vec<u8>) -> Self {
+        self.stdin = Some(stdin.into());
+        self
+    }
+
+    /// Controls whether a promise rejection which is never handled fails the execution, like Node's
+    /// `--unhandled-rejections=strict`.
+    ///
+    /// Pending jobs are run once the script has been evaluated, and then a promise which was rejected without a
+    /// handler being attached is reported as an error with its reason. This catches async bugs which are otherwise
+    /// silently swallowed. Unhandled rejections are ignored by default.
+    pub fn with_unhandled_rejection_is_error(mut self, unhandled_rejection_is_error: bool) -> Self {
+        self.unhandled_rejection_is_error = Some(unhandled_rejection_is_error);
+        self
+    }
+
+    /// Sets a placeholder substituted for values in the output which cannot be serialized to JSON, rather than the
+    /// execution failing.
+    ///
+    /// `BigInt`s, symbols, functions and circular references are replaced wherever they occur in the result or an
+    /// emitted value, so a mostly good result is not lost. This walks the output before it is serialized, which adds
+    /// to the output transcode time. By default such values fail the execution.
+    pub fn with_output_placeholder(mut self, placeholder: OutputPlaceholder) -> Self {
+        self.output_placeholder = Some(placeholder);
+        self
+    }
+
+    /// Sets a function called before every execution, e.g. for metrics, logging or rate limiting.
+    ///
+    /// It is called on the thread executing the script before the module is instantiated so it is outside the time
+    /// limit and fuel budget of the execution. Returning an error rejects the execution, which returns the error
+    /// without the module being instantiated. Every execution of the module is included, e.g. those made by `compile`
+    /// and `diagnose`.
+    pub fn with_before_execute(mut self, callback: BeforeExecuteCallback) -> Self {
+        self.before_execute = Some(callback);
+        self
+    }
+
+    /// Sets a function called after every execution with the statistics of the execution, or the error it failed
+    /// with, e.g. for metrics or logging.
+    ///
+    /// It is called on the thread executing the script once the execution has finished, so it is outside the time
+    /// limit and fuel budget of the execution. It is also called for an execution rejected by the function set with
+    /// `with_before_execute`.
+    pub fn with_after_execute(mut self, callback: AfterExecuteCallback) -> Self {
+        self.after_execute = Some(callback);
+        self
+    }
+
+    /// Shares `cache` with every execution of the instance and its clones through the `cacheGet(key)` global, e.g. to
+    /// look up reference data the host loads once rather than passing it as input data to every execution.
+    ///
+    /// The host populates the cache through its own handle at any time. `cacheGet` returns `undefined` for a missing
+    /// key. If `writable` is true the `cacheSet(key, value)` global is defined as well so scripts can store JSON
+    /// values for later executions; otherwise the cache is read-only to scripts.
+    ///
+    /// The cache lives on the host so it is outside the memory limit of the executions. Writes by scripts are bounded
+    /// by `with_shared_cache_limits`.
+    pub fn with_shared_cache(mut self, cache: SharedCache, writable: bool) -> Self {
+        self.shared_cache = Some((cache, writable));
+        self
+    }
+
+    /// Sets the maximum number of entries scripts may grow the shared cache to, and the maximum size in bytes of the
+    /// key and JSON value of an entry they write. Defaults to 10000 entries of 1MiB.
+    ///
+    /// A `cacheSet` which would exceed either fails the execution. Replacing an existing key does not count as a new
+    /// entry, and the host can still insert entries through its own handle beyond the limit.
+    pub fn with_shared_cache_limits(mut self, max_entries: usize, max_entry_size: usize) -> Self {
+        self.shared_cache_limits = Some((max_entries, max_entry_size));
+        self
+    }
+
+    /// Sets the maximum number of executions of the instance and its clones which run at once.
+    ///
+    /// Each execution allocates its own store and memory, so unbounded concurrency, e.g. with `par_iter`, can
+    /// exhaust the host's memory. Once `max_concurrency` executions are running further calls wait until one
+    /// completes, or fail immediately with `QuickJSError::AtCapacity` if `with_fail_at_capacity` is set.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// Sets whether an execution fails with `QuickJSError::AtCapacity` rather than waiting when the maximum number of
+    /// executions set with `with_max_concurrency` are already running.
+    pub fn with_fail_at_capacity(mut self, fail_at_capacity: bool) -> Self {
+        self.fail_at_capacity = Some(fail_at_capacity);
+        self
+    }
+
+    /// Sets whether an execution interrupted by the time limit reports the JavaScript stack it was interrupted at, to
+    /// debug scripts which hang.
+    ///
+    /// Once the time limit is exceeded the guest is given one more check interval to interrupt the script itself and
+    /// capture its stack, which is returned as `QuickJSError::TimeLimitExceeded { stack: Some(..) }`. A script stuck
+    /// outside JavaScript, e.g. in a host function, cannot be interrupted by the guest so is stopped without a stack
+    /// after the extra interval.
+    ///
+    /// QuickJS polls for interrupts every 10000 function calls or loop iterations and each poll calls into the host
+    /// to check the time limit, which slows tight loops by a few percent. Executions without a time limit are
+    /// unaffected.
+    pub fn with_stack_on_timeout(mut self, stack_on_timeout: bool) -> Self {
+        self.stack_on_timeout = Some(stack_on_timeout);
+        self
+    }
+
+    /// Sets the maximum size in bytes of the JSON result of an execution, so a script cannot return an unbounded
+    /// amount of data to the host.
+    ///
+    /// A larger result fails with `QuickJSError::OutputTooLarge` unless `with_truncate_output` is set. Values passed
+    /// to `emit` and the output of `compile` are not limited.
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = Some(max_output_bytes);
+        self
+    }
+
+    /// Sets whether a result larger than `with_max_output_bytes` is truncated to a valid JSON value which fits rather
+    /// than failing the execution.
+    ///
+    /// The result is truncated at the value level so it remains valid JSON:
+    ///
+    /// * an array keeps its longest prefix of elements which fits, e.g. `[1,2,3]` may become `[1,2]` or `[]`.
+    /// * a string keeps its longest prefix of characters which fits once encoded, e.g. `"quickjs"` may become `"quick"`.
+    ///
+    /// Any other result, such as an object, cannot be truncated meaningfully so still fails with
+    /// `QuickJSError::OutputTooLarge`, as does an array or string whose empty prefix does not fit. Elements of an
+    /// array are never truncated themselves. `ExecuteOutput::truncated` reports whether the result of
+    /// `QuickJS::try_execute_detailed` was truncated; the other methods return the truncated result without saying so.
+    pub fn with_truncate_output(mut self, truncate_output: bool) -> Self {
+        self.truncate_output = Some(truncate_output);
+        self
+    }
+
+    /// Sets whether integers outside the range JavaScript numbers represent exactly are exchanged with scripts as
+    /// `BigInt`s, so large integers such as ids or amounts in minor units round-trip exactly between host and guest.
+    ///
+    /// JavaScript numbers are doubles, so by default an integer in the input data beyond `Number.MAX_SAFE_INTEGER`
+    /// (2^53 - 1) is rounded, and a `BigInt` cannot be returned at all. With big integers enabled such integers are
+    /// bound as `BigInt`s, e.g. `data.id === 12345678901234567890n`, and any `BigInt` in the result or emitted values is
+    /// written as a JSON integer with all of its digits. Integers within the safe range are still numbers, so scripts
+    /// must handle both types, and mixing them in arithmetic throws a `TypeError`, e.g. `data.id + 1` must be written
+    /// as `data.id + 1n`.
+    ///
+    /// This is not arbitrary precision for decimals: a number with a fraction or exponent is still a double, so exact
+    /// decimal amounts should be passed as strings and handled with a decimal library in the script. Integers are not
+    /// preserved through `with_input_transform`, which sees the input data as `serde_json::Value`s. A string value in the
+    /// output consisting of the `"\u0000bigint:"` marker used internally followed by an integer is written as that
+    /// integer.
+    pub fn with_big_integers(mut self, big_integers: bool) -> Self {
+        self.big_integers = Some(big_integers);
+        self
+    }
+
+    /// Sets the transcoder `QuickJS::try_execute_transcoded` uses to decode input data from, and encode results to, a
+    /// format other than JSON, e.g. a proprietary binary format.
+    pub fn with_transcoder(mut self, transcoder: Arc<dyn Transcoder>) -> Self {
+        self.transcoder = Some(transcoder);
+        self
+    }
+
+    /// Sets the encoding of scripts passed as bytes to `QuickJS::try_execute_encoded`, e.g. `SourceEncoding::Latin1`
+    /// for legacy scripts which are not UTF-8.
+    ///
+    /// By default (`SourceEncoding::Utf8`) the bytes are validated as UTF-8. Scripts passed as `&str` are always UTF-8
+    /// so are unaffected.
+    pub fn with_source_encoding(mut self, source_encoding: SourceEncoding) -> Self {
+        self.source_encoding = Some(source_encoding);
+        self
+    }
+
+    /// Sets the maximum number of characters of the strings builtins such as `String.prototype.repeat`,
+    /// `padStart`/`padEnd`, `concat` and `Array.prototype.join` may create. Exceeding it throws a catchable
+    /// `RangeError: string length exceeds the maximum of {max}` rather than consuming memory up to the memory limit,
+    /// which is easier to diagnose. The length is checked before allocating where it is known up front (`repeat` and
+    /// padding).
+    ///
+    /// This is best-effort and not a security boundary: only the builtins listed are checked, so concatenation with
+    /// `+` (e.g. `s += s` in a loop) is not limited. Use `with_memory_limit` to bound what a script can allocate.
+    pub fn with_max_string_length(mut self, max_string_length: u32) -> Self {
+        self.max_string_length = Some(max_string_length);
+        self
+    }
+
+    /// Sets the maximum number of elements of the arrays `Array.from`, `push`, `unshift`, `fill` and `concat` may
+    /// create or populate. Exceeding it throws a catchable `RangeError: array length exceeds the maximum of {max}`
+    /// rather than consuming memory up to the memory limit, so `new Array(1e9).fill(0)` fails before allocating.
+    ///
+    /// This is best-effort and not a security boundary: the builtins are wrapped in place so the global `Array` keeps
+    /// its identity, which leaves the `Array` constructor itself, array literals, assigning `length` or indices and
+    /// typed arrays unlimited. Use `with_memory_limit` to bound what a script can allocate.
+    pub fn with_max_array_length(mut self, max_array_length: u32) -> Self {
+        self.max_array_length = Some(max_array_length);
+        self
+    }
+
+    /// Binds `value` as the deeply frozen global constant `name`, e.g. the deployment `ENVIRONMENT` or feature toggles,
+    /// shared by every execution of the instance.
+    ///
+    /// Unlike `data` the value is the same for every execution and the script cannot modify or reassign it. The Wizer
+    /// snapshot is created when the module is built so cannot include it; instead constants are passed with the other
+    /// options and bound before the script runs. Prefer small values as they are transcoded on every execution.
+    ///
+    /// `build` fails if `name` is not a JavaScript identifier, or would replace the data global, a secret, a host
+    /// function or a global the guest defines, e.g. `emit`, `exit` or `gc`.
+    pub fn with_constant(mut self, name: impl Into<String>, value: serde_json::Value) -> Self {
+        self.constants.insert(name.into(), value);
+        self
+    }
+
     /// Builds a `QuickJS` instance from the current configuration settings.
     ///
     /// This method creates and returns a new `QuickJS` instance based on the settings provided through this builder.
     pub fn build(&self) -> Result<QuickJS> {
-        QuickJS::try_new(
-            self.module.clone(),
+        if self.memory_thresholds.is_some() && self.memory_limit.is_none() {
+            bail!("memory thresholds require a memory limit");
+        }
+        if self.max_concurrency == Some(0) {
+            bail!("max concurrency must be at least 1");
+        }
+        if let Some(name) = &self.data_global {
+            if !is_identifier(name) {
+                bail!("data global `{name}` is not a valid identifier");
+            }
+        }
+        for name in self.constants.keys() {
+            if !is_identifier(name) {
+                bail!("constant `{name}` is not a valid identifier");
+            }
+            if name == self.data_global.as_deref().unwrap_or("data")
+                || self.secrets.contains_key(name)
+                || self.host_functions.contains_key(name)
+                || RESERVED_GLOBALS.contains(&name.as_str())
+            {
+                bail!("constant `{name}` would replace a global defined for scripts");
+            }
+        }
+
+        let interruptible = self.time_limit.is_some() || self.interruptible.unwrap_or(false);
+        // Checking the cancellation token at fuel checkpoints requires the guest to yield so runs it asynchronously.
+        let fuel_interruptible = self.interrupt_fuel_interval.is_some();
+        let engine = Engine::new(
+            Config::default()
+                .epoch_interruption(interruptible)
+                .consume_fuel(self.fuel_limit.is_some() || fuel_interruptible)
+                .async_support(fuel_interruptible),
+        )?;
+
+        // A lazily compiled module is compiled by the first execution instead.
+        let module = Arc::new(OnceLock::new());
+        if !self.lazy_compilation.unwrap_or(false) {
+            let _ = module.set(compile_module(&engine, self.module.as_ref())?);
+        }
+
+        // A single ticker increments the epoch for every execution of this instance.
+        let epoch_interval = self
+            .time_limit
+            .as_ref()
+            .map(|time_limit| time_limit.evaluation_interval)
+            .unwrap_or(Duration::from_micros(EPOCH_INTERVAL));
+        let ticker = interruptible.then(|| Arc::new(Ticker::start(engine.clone(), epoch_interval)));
+
+        // The WASI functions are the same for every execution so are defined once.
+        let mut linker = Linker::new(&engine);
+        wasi_common::sync::add_to_linker(&mut linker, |state: &mut State| &mut state.wasi)?;
+        for extension in &self.linker_extensions {
+            extension(&mut linker).context("linker extension failed")?;
+        }
+
+        // The WASI configuration is the same for every execution so is resolved once.
+        let redacted = Secrets::new(self.secrets.values());
+        let wasi = WasiTemplate::new(
+            self.stdin.clone(),
             self.inherit_stdout.unwrap_or(false),
             self.inherit_stderr.unwrap_or(false),
-            self.memory_limit,
-            self.time_limit.clone(),
-        )
+            &redacted,
+            self.virtual_clock.clone(),
+        );
+
+        Ok(QuickJS {
+            engine,
+            module,
+            module_path: self.module.clone(),
+            linker,
+            inherit_stdout: self.inherit_stdout.unwrap_or(false),
+            inherit_stderr: self.inherit_stderr.unwrap_or(false),
+            memory_limit: self.memory_limit,
+            time_limit: self.time_limit.clone(),
+            random_seed: self.random_seed,
+            gc_threshold: self.gc_threshold,
+            virtual_clock: self.virtual_clock.clone(),
+            pretty_output: self.pretty_output.unwrap_or(false),
+            input_transform: self.input_transform.clone(),
+            read_only_data: self.read_only_data.unwrap_or(false),
+            strict_mode: self.strict_mode.unwrap_or(false),
+            max_input_depth: self.max_input_depth,
+            output_allowlist: self.output_allowlist.clone(),
+            output_denylist: self.output_denylist.clone(),
+            import_namespace: self
+                .import_namespace
+                .clone()
+                .unwrap_or_else(|| HOST_NAMESPACE.to_string()),
+            console_callback: self.console_callback.clone(),
+            cancellation_grace: self.cancellation_grace.unwrap_or_default(),
+            host_functions: self.host_functions.clone(),
+            fuel_limit: self.fuel_limit,
+            interrupt_fuel_interval: self.interrupt_fuel_interval,
+            js_heap_limit: self.js_heap_limit,
+            secrets: self.secrets.clone(),
+            module_eval: self.module_eval.unwrap_or(false),
+            data_resolver: self.data_resolver.clone(),
+            memory_thresholds: self.memory_thresholds.clone(),
+            eval_mode: self.eval_mode.unwrap_or_default(),
+            data_global: self.data_global.clone(),
+            stdin: self.stdin.clone(),
+            unhandled_rejection_is_error: self.unhandled_rejection_is_error.unwrap_or(false),
+            output_placeholder: self.output_placeholder,
+            before_execute: self.before_execute.clone(),
+            after_execute: self.after_execute.clone(),
+            shared_cache: self.shared_cache.clone(),
+            shared_cache_limits: self.shared_cache_limits.unwrap_or((
+                DEFAULT_SHARED_CACHE_ENTRIES,
+                DEFAULT_SHARED_CACHE_ENTRY_SIZE,
+            )),
+            max_concurrency: self.max_concurrency.map(|max_concurrency| {
+                Arc::new(Semaphore::new(
+                    max_concurrency,
+                    self.fail_at_capacity.unwrap_or(false),
+                ))
+            }),
+            stack_on_timeout: self.stack_on_timeout.unwrap_or(false),
+            max_output_bytes: self.max_output_bytes,
+            truncate_output: self.truncate_output.unwrap_or(false),
+            big_integers: self.big_integers.unwrap_or(false),
+            transcoder: self.transcoder.clone(),
+            source_encoding: self.source_encoding.unwrap_or_default(),
+            max_string_length: self.max_string_length,
+            max_array_length: self.max_array_length,
+            constants: self.constants.clone(),
+            epoch_interval,
+            ticker,
+            shutdown: CancellationToken::new(),
+            redacted,
+            wasi,
+        })
+    }
+}
+
+/// Whether `name` is a JavaScript identifier which scripts can refer to as a global, e.g. `data` but not `a-b` or
+/// `class`. Only ASCII identifiers are accepted.
+fn is_identifier(name: &str) -> bool {
+    // The reserved words of strict mode, which cannot be used as the name of a binding.
+    const RESERVED_WORDS: &str = "arguments await break case catch class const continue debugger default delete do \
+        else enum eval export extends false finally for function if implements import in instanceof interface let new \
+        null package private protected public return static super switch this throw true try typeof var void while \
+        with yield";
+
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_' || first == '$')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '$')
+        && !RESERVED_WORDS.split_whitespace().any(|word| word == name)
+}
+
+/// The number of wasm pages needed to hold `memory_limit` bytes, rounded up to a whole page.
+fn memory_pages(memory_limit: u32) -> u32 {
+    memory_limit.div_ceil(PAGE_SIZE)
+}
+
+/// The memory limit in bytes actually enforced for `memory_limit`, which is rounded up to a whole page.
+fn effective_memory_limit(memory_limit: u32) -> usize {
+    memory_pages(memory_limit) as usize * PAGE_SIZE as usize
+}
+
+/// Compiles the module at `path`, or the embedded default module if `None`.
+fn compile_module(engine: &Engine, path: Option<&PathBuf>) -> Result<Module> {
+    match path {
+        Some(path) => Module::from_file(engine, path),
+        None => Module::from_binary(engine, include_bytes!("../../../quickjs.wasm")),
     }
 }
 
@@ -160,6 +1217,10 @@ pub struct TimeLimit {
     pub limit: Duration,
     /// Evaluation interval to check if the execution is still within the allowed time frame.
     pub evaluation_interval: Duration,
+    /// Number of evaluation intervals between checks of the time limit, cancellation and shutdown.
+    continue_epochs: u64,
+    /// Whether the limit is an idle timeout which restarts whenever the guest makes progress.
+    idle: bool,
 }
 
 impl TimeLimit {
@@ -172,6 +1233,8 @@ impl TimeLimit {
         Self {
             limit,
             evaluation_interval: Duration::from_micros(EPOCH_INTERVAL),
+            continue_epochs: 1,
+            idle: false,
         }
     }
 
@@ -185,283 +1248,5580 @@ impl TimeLimit {
         self.evaluation_interval = evaluation_interval;
         self
     }
-}
-
-struct State {
-    pub wasi: WasiCtx,
-    pub limits: StoreLimits,
-}
 
-impl QuickJS {
-    /// Attempts to execute the given JavaScript code with optional input data.
+    /// Sets the number of evaluation intervals between checks (default: 1).
     ///
-    /// This method sets up a WASI context and executes the provided JavaScript code in that context. If `data` is provided, it will be passed to the script as standard input.
+    /// The check is a callback into the host so checking less often reduces the overhead for long-running scripts, at
+    /// the cost of the time limit, cancellation and shutdown being observed up to `continue_epochs` intervals late.
     ///
     /// # Arguments
     ///
-    /// * `script`: The JavaScript code to execute as a string.
-    /// * `data`: Optional input data to pass to the script as standard input.
+    /// * `continue_epochs`: Number of evaluation intervals between checks. Zero is treated as one.
+    pub fn with_continue_epochs(mut self, continue_epochs: u64) -> Self {
+        self.continue_epochs = continue_epochs;
+        self
+    }
+
+    /// Sets whether the limit is an idle timeout rather than a deadline for the whole execution (default: false).
     ///
-    /// # Returns
+    /// An idle timeout restarts whenever the guest makes progress by interacting with the host: emitting a value,
+    /// writing a console line with `QuickJSBuilder::with_live_console` set, setting binary output, calling a host
+    /// function, loading data or accessing the shared cache. Only a script which does none of these for `limit` is
+    /// interrupted, so long-running streaming scripts are not killed while they keep producing output. Progress is
+    /// observed at the next check so the timeout restarts up to `continue_epochs` intervals late.
+    ///
+    /// # Arguments
+    ///
+    /// * `idle`: Whether the limit restarts on progress.
+    pub fn with_idle(mut self, idle: bool) -> Self {
+        self.idle = idle;
+        self
+    }
+}
+
+/// How the result of a script is determined.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvalMode {
+    /// The result is the completion value of the last statement, as with `eval`.
+    #[default]
+    Expression,
+    /// The script is evaluated as the body of a function so the result is the value passed to `return`, or
+    /// `undefined` if the script does not return.
+    ///
+    /// Declarations are local to the function so do not become globals.
+    Statement,
+}
+
+/// The character encoding of a script passed as bytes to `QuickJS::try_execute_encoded`.
+///
+/// The guest evaluates UTF-8, so scripts in another encoding are transcoded on the host first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourceEncoding {
+    /// The script is UTF-8, which is validated so malformed bytes fail the execution rather than being replaced.
+    #[default]
+    Utf8,
+    /// The script is ISO-8859-1, where every byte is the character of the same code point, e.g. `0xE9` is `é`.
+    Latin1,
+}
+
+impl SourceEncoding {
+    /// Converts a script in this encoding to UTF-8.
+    pub fn decode(self, script: &[u8]) -> Result<String> {
+        match self {
+            SourceEncoding::Utf8 => match std::str::from_utf8(script) {
+                Ok(script) => Ok(script.to_string()),
+                Err(err) => bail!("script is not valid UTF-8: {err}"),
+            },
+            SourceEncoding::Latin1 => Ok(script.iter().map(|byte| char::from(*byte)).collect()),
+        }
+    }
+}
+
+/// The state of an execution's store, which host functions defined with `QuickJSBuilder::with_linker_extension`
+/// receive through their `Caller`. Its contents are private.
+pub struct State {
+    pub(crate) wasi: WasiCtx,
+    pub(crate) limiter: Limiter,
+    /// The panic or error message reported by the guest before it aborted.
+    pub(crate) abort_message: Option<String>,
+    /// The bytes and content type passed to `setBinaryOutput`, if called.
+    pub(crate) binary_output: Option<(Vec<u8>, Option<String>)>,
+    /// The JSON result of the last host function call, retrieved by the guest with `get_host_function_result`.
+    pub(crate) host_function_result: Vec<u8>,
+    /// The time spent in each phase of the execution, reported by the guest with `set_timings`.
+    pub(crate) timings: [Duration; 3],
+    /// The names of the globals the script changed, reported by the guest with `set_changed_globals`.
+    pub(crate) changed_globals: Vec<String>,
+    /// Whether the guest has interacted with the host since the idle timeout was last restarted.
+    pub(crate) progressed: bool,
+    /// The epochs counted against the time limit, i.e. since the start or the idle timeout was last restarted.
+    pub(crate) epochs_elapsed: u64,
+    /// Whether the guest has advanced to the next job of a batch since the time limit was last restarted.
+    pub(crate) job_started: bool,
+    /// Whether the time limit has been exceeded and the guest should interrupt the script, reported by `is_timed_out`.
+    pub(crate) timed_out: bool,
+    /// The most jobs run by a single drain of the guest's job queue, reported by the guest with `set_max_pending_jobs`.
+    pub(crate) max_pending_jobs: u32,
+}
+
+/// Options passed to the guest for each execution. Retrieved by the guest via `get_options`.
+#[derive(Debug, Default, Serialize)]
+struct Options {
+    random_seed: Option<u64>,
+    gc_threshold: Option<u32>,
+    pretty_output: bool,
+    read_only_data: bool,
+    strict_mode: bool,
+    max_input_depth: Option<u32>,
+    /// Return the problems found by compiling the script rather than evaluating it.
+    diagnose: bool,
+    /// Execute the jobs of `Invocation::batch` one after another rather than a single script.
+    batch: bool,
+    output_allowlist: Option<Vec<String>>,
+    output_denylist: Option<Vec<String>>,
+    /// Send console lines to the host with `console_line` rather than writing them to stdout or stderr.
+    live_console: bool,
+    /// The names of the host functions the script can call.
+    host_functions: Vec<String>,
+    js_heap_limit: Option<usize>,
+    /// Values bound as globals of the same name.
+    secrets: BTreeMap<String, String>,
+    module_eval: bool,
+    /// Define the `loadData` global which loads datasets with `load_data`.
+    data_resolver: bool,
+    /// The name of the global the input data is bound to, if not `data`.
+    data_global: Option<String>,
+    /// The name of a function defined by the script to call with the input data as its only argument.
+    invoke: Option<String>,
+    /// Bind the input data as the string global `text` rather than parsing it as JSON.
+    text_input: bool,
+    /// A script evaluated after the input data is bound which must return `true` for the script to run.
+    guard: Option<String>,
+    /// Evaluate the script as the body of a function so only an explicit `return` is its result.
+    statement_mode: bool,
+    /// Run pending jobs after the script and report a promise rejection which was never handled as an error.
+    unhandled_rejection_is_error: bool,
+    /// Read the script from the host in chunks with `read_script_chunk` rather than all at once.
+    streamed_script: bool,
+    /// Replace values in the output which cannot be serialized to JSON with the placeholder rather than failing.
+    output_placeholder: Option<OutputPlaceholder>,
+    /// Define the `cacheGet` global which reads the shared cache with `cache_get`.
+    shared_cache: bool,
+    /// Define the `cacheSet` global which writes the shared cache with `cache_set`.
+    shared_cache_writable: bool,
+    /// Report the globals the script changed with `set_changed_globals`.
+    track_globals: bool,
+    /// Interrupt the script once `is_timed_out` returns true, reporting the stack at the interruption.
+    stack_on_timeout: bool,
+    /// Bind integers marked by `mark_big_integers` as `BigInt`s and mark `BigInt`s in the output.
+    big_integers: bool,
+    /// Throw a `RangeError` from the builtins which would create a longer string.
+    max_string_length: Option<u32>,
+    /// Throw a `RangeError` from the builtins which would create a longer array.
+    max_array_length: Option<u32>,
+    /// Values bound as deeply frozen globals of the same name.
+    constants: BTreeMap<String, serde_json::Value>,
+    /// Compile the script to bytecode and return the bytecode rather than evaluating it.
+    compile: bool,
+    /// The script is bytecode produced by `compile` rather than source.
+    bytecode: bool,
+    /// Return the names of the globals defined by the script rather than its result.
+    list_globals: bool,
+}
+
+/// The per-call inputs to `QuickJS::execute`.
+#[derive(Default)]
+struct Invocation {
+    /// The script source, or bytecode if `options.bytecode` is set.
+    script: Vec<u8>,
+    /// The JSON input data. Empty if there is no input data.
+    data: Vec<u8>,
+    /// Options passed to the guest.
+    options: Options,
+    /// Optional token which interrupts the execution when cancelled.
+    cancellation: Option<CancellationToken>,
+    /// Optional time limit which overrides the instance's time limit.
+    time_limit: Option<TimeLimit>,
+    /// The jobs to execute if `options.batch` is set, in which case `script` and `data` are unused.
+    batch: Vec<Job>,
+    /// Stop a batch at the first job which returns an error rather than executing every job.
+    fail_fast: bool,
+    /// The reader the guest reads the script from in chunks if `options.streamed_script` is set, in which case
+    /// `script` is unused.
+    script_reader: Option<Box<dyn Read + Send>>,
+    /// Optional identifier of the call which is attached to any error it returns.
+    request_id: Option<String>,
+    /// Optional function receiving emitted values as they are emitted, in which case they are not collected.
+    on_emit: Option<EmitCallback>,
+    /// Optional function receiving console lines, which replaces the instance's `with_live_console` callback.
+    on_console: Option<ConsoleCallback>,
+    /// Optional cell receiving the resource usage of the execution once the guest returns, even if it failed.
+    stats: Option<Arc<Mutex<ExecuteStats>>>,
+}
+
+/// A script and its input data.
+struct Job {
+    /// The script source, or bytecode if `options.bytecode` is set.
+    script: Vec<u8>,
+    /// The JSON input data. Empty if there is no input data.
+    data: Vec<u8>,
+}
+
+/// A message sent by the guest through `set_output`.
+enum OutputMessage {
+    /// A value passed to `emit`. More messages follow.
+    Emitted(Vec<u8>),
+    /// The final result of the script. This is the last message.
+    Result(Option<Result<Vec<u8>>>),
+}
+
+/// The raw bytes sent by the guest through `set_output` during an execution.
+struct RawOutput {
+    /// The values passed to `emit`, in order.
+    emitted: Vec<Vec<u8>>,
+    /// The final result of the script, or `None` if it evaluated to `undefined`.
+    value: Option<Vec<u8>>,
+    /// Non-fatal problems observed during the execution.
+    warnings: Vec<String>,
+    /// Resource usage of the execution.
+    stats: ExecuteStats,
+    /// The result of every job of a batch which ran, in order. Empty unless `options.batch` is set.
+    results: Vec<Result<Option<Vec<u8>>>>,
+    /// The bytes and content type passed to `setBinaryOutput`, if called.
+    binary: Option<(Vec<u8>, Option<String>)>,
+    /// The names of the globals the script changed. Empty unless `options.track_globals` is set.
+    changed_globals: Vec<String>,
+    /// How close the execution came to its memory limit or time limit.
+    limit_status: LimitStatus,
+    /// Whether `value` was truncated to fit `max_output_bytes`.
+    truncated: bool,
+}
+
+impl RawOutput {
+    /// Returns the final result as a JSON string, or `None` if the script evaluated to `undefined`.
+    fn into_json(self) -> Result<Option<String>> {
+        Ok(self.value.map(String::from_utf8).transpose()?)
+    }
+
+    /// Returns the final result as a JSON string alongside the details of the execution.
+    fn into_output(self) -> Result<ExecuteOutput> {
+        Ok(ExecuteOutput {
+            output: self.value.map(String::from_utf8).transpose()?,
+            warnings: self.warnings,
+            stats: self.stats,
+            limit_status: self.limit_status,
+            truncated: self.truncated,
+        })
+    }
+}
+
+/// Writes `bytes` into the guest memory at the offset `ptr`.
+fn write_memory(caller: &mut Caller<'_, State>, ptr: i32, bytes: &[u8]) -> Result<()> {
+    let memory = guest_memory(caller)?;
+
+    // The offset in bytes at which to write the data.
+    let offset = ptr as u32 as usize;
+
+    Ok(memory.write(caller, offset, bytes)?)
+}
+
+/// Reads `size` bytes at `ptr` from the guest's memory.
+///
+/// The range is checked before anything is allocated, so a negative size or a range outside the memory fails rather
+/// than panicking or allocating on behalf of the guest.
+fn read_guest_bytes(caller: &mut Caller<'_, State>, ptr: i32, size: i32) -> Result<Vec<u8>> {
+    let memory = guest_memory(caller)?;
+
+    let offset = ptr as u32 as usize;
+    let size = usize::try_from(size).map_err(|_| anyhow!("invalid size {size} of guest memory"))?;
+    memory
+        .data(&*caller)
+        .get(offset..offset.saturating_add(size))
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| anyhow!("guest memory at {offset} of size {size} is out of bounds"))
+}
+
+/// Returns the memory exported by the guest.
+fn guest_memory(caller: &mut Caller<'_, State>) -> Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(anyhow!("failed to find host memory")),
+    }
+}
+
+impl QuickJS {
+    /// Attempts to execute the given JavaScript code with optional input data.
+    ///
+    /// This method sets up a WASI context and executes the provided JavaScript code in that context. If `data` is provided, it is bound as the `data` global.
+    ///
+    /// The `emit` global is defined for every execution but only `try_execute_many`, `try_execute_stream` and
+    /// `try_execute_ndjson` collect the values passed to it. Here, as with the other `try_execute_*` methods, emitted
+    /// values are transcoded and sent to the host but discarded, so only the script's result is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
     ///
     /// If execution is successful, it returns `Some(String)` with the output  or None if no output is returned from the JavaScript context.
+    /// A script evaluating to `undefined` returns `None` whereas a script evaluating to `null` returns `Some("null")`.
     pub fn try_execute(&self, script: &str, data: Option<&str>) -> Result<Option<String>> {
         // Convert the script string to a byte vector for later use
         let script = script.as_bytes().to_vec();
 
-        // Get the size of the script as an i32 (for WASI API calls)
-        let script_size = script.len() as i32;
+        // Optionally convert the data string to a byte vector and set its default value if it's not provided
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        self.execute(Invocation {
+            script,
+            data,
+            options: self.options(),
+            ..Default::default()
+        })?
+        .into_json()
+    }
+
+    /// Attempts to execute the script of the bundle file at `bundle_path` with the input data packaged alongside it.
+    ///
+    /// See `Bundle` for the format. Bundles are written with `Bundle::to_bytes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bundle_path`: The path of the bundle file.
+    ///
+    /// # Returns
+    ///
+    /// The result as a JSON string, or `None` if the script evaluated to `undefined`. An error if the file cannot be
+    /// read or is not a valid bundle.
+    pub fn try_execute_bundle(&self, bundle_path: impl AsRef<Path>) -> Result<Option<String>> {
+        let bundle_path = bundle_path.as_ref();
+        let bytes = std::fs::read(bundle_path)
+            .with_context(|| format!("failed to read bundle {}", bundle_path.display()))?;
+        let bundle = Bundle::from_bytes(&bytes)
+            .with_context(|| format!("invalid bundle {}", bundle_path.display()))?;
+
+        self.try_execute(&bundle.script, bundle.data.as_deref())
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, only if a guard script accepts it.
+    ///
+    /// The guard is evaluated first, in the same context and after the input data is bound, so it can validate `data`
+    /// and any functions or globals it defines remain available to the script. The script runs only if the guard
+    /// evaluates to `true`.
+    ///
+    /// # Arguments
+    ///
+    /// * `guard`: The JavaScript code validating the input data, evaluating to a boolean or throwing.
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional input data to pass to the guard and the script.
+    ///
+    /// # Returns
+    ///
+    /// The result as with `try_execute`, or `QuickJSError::GuardRejected` with the exception the guard threw or why its
+    /// result was rejected if it did not evaluate to `true`. A guard which fails the execution itself, e.g. by exceeding
+    /// the time limit or running out of memory or stack, returns that error instead.
+    pub fn try_execute_guarded(
+        &self,
+        guard: &str,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<Option<String>> {
+        let options = ExecuteOptions::new().with_guard(guard);
+        Ok(self
+            .try_execute_with_options(script, data, &options)?
+            .output)
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, tagging any error with `request_id`.
+    ///
+    /// When many executions run concurrently, e.g. with `par_iter`, the identifier correlates an error back to the
+    /// request which caused it. The error is the same as with `try_execute` but with `request {request_id}` added as
+    /// context, so it still downcasts to `QuickJSError` and its message reads e.g.
+    /// `request 42: execution timed out`. The identifier is also passed alongside every line sent to
+    /// `QuickJSBuilder::with_live_console`, leaving the line unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    /// * `request_id`: The identifier of the call, e.g. the id of the HTTP request being served.
+    ///
+    /// # Returns
+    ///
+    /// The result of the script as with `try_execute`.
+    pub fn try_execute_with_request_id(
+        &self,
+        script: &str,
+        data: Option<&str>,
+        request_id: &str,
+    ) -> Result<Option<String>> {
+        let options = ExecuteOptions::new().with_request_id(request_id);
+        Ok(self
+            .try_execute_with_options(script, data, &options)?
+            .output)
+    }
+
+    /// Attempts to execute the given JavaScript code in the encoding set with `QuickJSBuilder::with_source_encoding`,
+    /// with optional input data.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as bytes in the configured encoding.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute`, or an error if the script is not valid in the configured encoding.
+    pub fn try_execute_encoded(&self, script: &[u8], data: Option<&str>) -> Result<Option<String>> {
+        let script = self.source_encoding.decode(script)?;
+        self.try_execute(&script, data)
+    }
+
+    /// Attempts to execute the JavaScript code read from `script` with optional input data.
+    ///
+    /// The guest pulls the script from the reader in chunks written straight into its memory, so a very large
+    /// generated script does not need to be materialized as a string on the host first, and its size need not be
+    /// known up front. The script is still held in full in the guest's memory while it is evaluated.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: A reader of the JavaScript code, e.g. a file.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute`. An error reading the script fails the execution.
+    pub fn try_execute_reader(
+        &self,
+        script: impl Read + Send + 'static,
+        data: Option<&str>,
+    ) -> Result<Option<String>> {
+        let options = Options {
+            streamed_script: true,
+            ..self.options()
+        };
+
+        self.execute(Invocation {
+            data: data
+                .map(|data| data.as_bytes().to_vec())
+                .unwrap_or_default(),
+            options,
+            script_reader: Some(Box::new(script)),
+            ..Default::default()
+        })?
+        .into_json()
+    }
+
+    /// Attempts to execute the given JavaScript code as an HTTP handler, e.g. for a serverless gateway.
+    ///
+    /// The request is bound as the `request` global and the script returns the response as an object of the form
+    /// `{ statusCode, headers, body }`. The response is validated: the status code must be between 100 and 599, the
+    /// header values and body must be strings, and no other properties are allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code handling the request as a string.
+    /// * `request`: The request bound as the `request` global.
+    ///
+    /// # Returns
+    ///
+    /// The response returned by the script, or an error if it did not return a valid response.
+    pub fn try_execute_http(&self, script: &str, request: &HttpRequest) -> Result<HttpResponse> {
+        let options = Options {
+            data_global: Some("request".to_string()),
+            ..self.options()
+        };
+
+        let output = self
+            .execute(Invocation {
+                script: script.as_bytes().to_vec(),
+                data: serde_json::to_vec(request)?,
+                options,
+                ..Default::default()
+            })?
+            .value
+            .ok_or_else(|| anyhow!("script did not return a response"))?;
+        let response: HttpResponse =
+            serde_json::from_slice(&output).context("script did not return a valid response")?;
+        if !(100..=599).contains(&response.status_code) {
+            bail!("invalid response status code {}", response.status_code);
+        }
+
+        Ok(response)
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data which has already been parsed.
+    ///
+    /// The value is serialized straight to the bytes read by the guest, avoiding the intermediate `String` needed
+    /// to call `try_execute`.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional input data bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute`.
+    pub fn try_execute_value_input(
+        &self,
+        script: &str,
+        data: Option<&serde_json::Value>,
+    ) -> Result<Option<String>> {
+        self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data: data
+                .map(serde_json::to_vec)
+                .transpose()?
+                .unwrap_or_default(),
+            options: self.options(),
+            ..Default::default()
+        })?
+        .into_json()
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data in the format of the transcoder set with
+    /// `QuickJSBuilder::with_transcoder`, returning the result in the same format.
+    ///
+    /// The input data is decoded on the host and bound as `data`, and the result is encoded from its JSON value, so
+    /// scripts are unaware of the format.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional input data encoded in the transcoder's format.
+    ///
+    /// # Returns
+    ///
+    /// The result encoded in the transcoder's format, or `None` if the script evaluated to `undefined`. An error if no
+    /// transcoder is set or transcoding fails.
+    pub fn try_execute_transcoded(
+        &self,
+        script: &str,
+        data: Option<&[u8]>,
+    ) -> Result<Option<Vec<u8>>> {
+        let transcoder = self
+            .transcoder
+            .as_ref()
+            .ok_or_else(|| anyhow!("no transcoder is set"))?;
+        let data = data
+            .map(|data| transcoder.decode(data))
+            .transpose()
+            .context("failed to decode input data")?;
+
+        self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data: data
+                .map(|data| serde_json::to_vec(&data))
+                .transpose()?
+                .unwrap_or_default(),
+            options: self.options(),
+            ..Default::default()
+        })?
+        .value
+        .map(|output| {
+            transcoder
+                .encode(serde_json::from_slice(&output)?)
+                .context("failed to encode output")
+        })
+        .transpose()
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, deserializing the output into `T`.
+    ///
+    /// The JSON output of the guest is deserialized directly into `T` without an intermediate `String` or
+    /// `serde_json::Value`, which suits typed pipelines.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// The output deserialized into `T`, or `None` if the script evaluated to `undefined`. An output which does not
+    /// match `T` returns an error.
+    pub fn try_execute_as<T: DeserializeOwned>(
+        &self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<Option<T>> {
+        self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data: data
+                .map(|data| data.as_bytes().to_vec())
+                .unwrap_or_default(),
+            options: self.options(),
+            ..Default::default()
+        })?
+        .value
+        .map(|output| Ok(serde_json::from_slice(&output)?))
+        .transpose()
+    }
+
+    /// Executes the given JavaScript code once with representative sample data and returns the peak memory it used, to
+    /// choose a `with_memory_limit` for production.
+    ///
+    /// The script runs without this instance's memory limit or JavaScript heap limit, so the
+    /// peak is measured even if it would exceed them. Other settings, such as the time limit, still apply. The peak is
+    /// the largest size of the wasm linear memory, which includes the memory of the module's snapshot, so it is the
+    /// smallest memory limit the script runs within. Allow headroom for inputs larger than the sample.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `sample_data`: Optional representative input data.
+    ///
+    /// # Returns
+    ///
+    /// The peak memory in bytes, or an error if the script fails.
+    pub fn profile_memory(&self, script: &str, sample_data: Option<&str>) -> Result<usize> {
+        let quickjs = QuickJS {
+            memory_limit: None,
+            js_heap_limit: None,
+            ..self.clone()
+        };
+
+        Ok(quickjs
+            .try_execute_detailed(script, sample_data)?
+            .stats
+            .peak_memory)
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, returning its result together with its
+    /// resource usage even if it fails.
+    ///
+    /// A script which exceeds a limit, e.g. its time limit, memory limit or fuel limit, reports the time, memory and
+    /// fuel it consumed before failing, to tell whether it came close to succeeding.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// An `ExecuteReport` containing the same result as `try_execute` and the resource usage of the execution.
+    pub fn try_execute_with_stats(&self, script: &str, data: Option<&str>) -> ExecuteReport {
+        let stats = Arc::new(Mutex::new(ExecuteStats::default()));
+
+        let result = self
+            .invocation(script, data, &ExecuteOptions::default())
+            .and_then(|invocation| {
+                self.execute(Invocation {
+                    stats: Some(stats.clone()),
+                    ..invocation
+                })
+            })
+            .and_then(RawOutput::into_json);
+
+        let stats = *stats.lock().unwrap();
+        ExecuteReport { result, stats }
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, returning its console output and result
+    /// as a transcript in the order they occurred, e.g. for a notebook.
+    ///
+    /// Console lines are captured as with `QuickJSBuilder::with_live_console`, so secrets are redacted, but are not
+    /// passed to the instance's live console callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// A `OutputEvent::Log` or `OutputEvent::Error` for each console line, followed by the `OutputEvent::Result`
+    /// unless the script evaluated to `undefined`.
+    pub fn try_execute_transcript(
+        &self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<Vec<OutputEvent>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let console_events = events.clone();
+
+        let options = ExecuteOptions::new().with_console(Arc::new(move |stream, line, _| {
+            let line = line.to_string();
+            console_events.lock().unwrap().push(match stream {
+                ConsoleStream::Stdout => OutputEvent::Log(line),
+                ConsoleStream::Stderr => OutputEvent::Error(line),
+            })
+        }));
+        let output = self.try_execute_with_options(script, data, &options)?;
+
+        let mut events = std::mem::take(&mut *events.lock().unwrap());
+        if let Some(value) = output.output {
+            events.push(OutputEvent::Result(serde_json::from_str(&value)?));
+        }
+        Ok(events)
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, returning the output alongside details of the execution.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// An `ExecuteOutput` containing the same output as `try_execute`, any warnings, such as console output which was
+    /// discarded because `inherit_stdout` or `inherit_stderr` is false, the resource usage of the execution and how
+    /// close it came to its memory limit or time limit.
+    pub fn try_execute_detailed(&self, script: &str, data: Option<&str>) -> Result<ExecuteOutput> {
+        self.try_execute_with_options(script, data, &ExecuteOptions::default())
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data and per-call options, returning the
+    /// output alongside details of the execution.
+    ///
+    /// The options compose, e.g. a guarded script can run with a request id, its own time limit and a subset of the
+    /// host functions. Each option behaves as the `try_execute_*` shorthand which sets it alone, see `ExecuteOptions`.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    /// * `options`: The options for this execution.
+    ///
+    /// # Returns
+    ///
+    /// An `ExecuteOutput` as with `try_execute_detailed`, or an error if an option is not supported by the instance,
+    /// e.g. a time limit on an instance which is not interruptible.
+    pub fn try_execute_with_options(
+        &self,
+        script: &str,
+        data: Option<&str>,
+        options: &ExecuteOptions,
+    ) -> Result<ExecuteOutput> {
+        self.execute(self.invocation(script, data, options)?)?
+            .into_output()
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, stopping if `cancellation` is cancelled.
+    ///
+    /// The instance must be built with a time limit, `QuickJSBuilder::with_interruptible` or
+    /// `QuickJSBuilder::with_interrupt_fuel_interval` so that cancellation can be observed while the script is running.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    /// * `cancellation`: A token which can be cancelled from another thread to stop the execution.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute`, or `QuickJSError::Cancelled` if the token was cancelled before the script completed.
+    pub fn try_execute_cancellable(
+        &self,
+        script: &str,
+        data: Option<&str>,
+        cancellation: &CancellationToken,
+    ) -> Result<Option<String>> {
+        let options = ExecuteOptions::new().with_cancellation(cancellation.clone());
+        Ok(self
+            .try_execute_with_options(script, data, &options)?
+            .output)
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, exposing only the named host functions.
+    ///
+    /// This allows one instance to serve scripts with different capabilities, e.g. one per tenant. Host functions
+    /// registered with `QuickJSBuilder::with_host_function` but not named in `capabilities` are not defined for this
+    /// execution so calling them throws a `ReferenceError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    /// * `capabilities`: The names of the host functions the script can call.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute`, or an error if a capability is not a registered host function.
+    pub fn try_execute_with_capabilities(
+        &self,
+        script: &str,
+        data: Option<&str>,
+        capabilities: &[&str],
+    ) -> Result<Option<String>> {
+        let options = ExecuteOptions::new().with_capabilities(capabilities);
+        Ok(self
+            .try_execute_with_options(script, data, &options)?
+            .output)
+    }
+
+    /// Stops every in-flight execution of this instance and its clones with `QuickJSError::Shutdown`, e.g. to shut
+    /// down a server without waiting for long-running scripts.
+    ///
+    /// In-flight executions observe the shutdown at the next epoch tick or fuel checkpoint so the instance must be
+    /// built with a time limit, `QuickJSBuilder::with_interruptible` or `QuickJSBuilder::with_interrupt_fuel_interval`
+    /// for running scripts to be stopped. Executions started after the shutdown fail immediately. A shutdown cannot
+    /// be undone.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Returns the version of the QuickJS engine in the loaded module, e.g. to check compatibility at startup.
+    ///
+    /// The version is read from the module's `quickjs_version` export without evaluating a script, so is much cheaper
+    /// than `try_execute`. Compiles the module if compilation is lazy and it has not been compiled yet.
+    ///
+    /// # Returns
+    ///
+    /// The QuickJS release, e.g. `2021-03-27`, or an error if the module does not export its version.
+    pub fn quickjs_version(&self) -> Result<String> {
+        let module = self.module()?;
+
+        // Only the version export is called so the module's imports are never used.
+        let mut linker = Linker::new(&self.engine);
+        linker.define_unknown_imports_as_traps(module)?;
+        let mut store = Store::new(&self.engine, ());
+        if self.ticker.is_some() {
+            store.set_epoch_deadline(u64::from(u32::MAX));
+        }
+        if self.fuel_limit.is_some() || self.interrupt_fuel_interval.is_some() {
+            store.set_fuel(u64::MAX)?;
+        }
+
+        let (instance, packed) = match self.interrupt_fuel_interval {
+            Some(_) => interrupt::block_on(
+                async {
+                    let instance = linker.instantiate_async(&mut store, module).await?;
+                    let version = instance
+                        .get_typed_func::<(), u64>(&mut store, "quickjs_version")
+                        .context("module does not export quickjs_version")?;
+                    let packed = version.call_async(&mut store, ()).await?;
+                    anyhow::Ok((instance, packed))
+                },
+                None,
+                &self.shutdown,
+            )??,
+            None => {
+                let instance = linker.instantiate(&mut store, module)?;
+                let version = instance
+                    .get_typed_func::<(), u64>(&mut store, "quickjs_version")
+                    .context("module does not export quickjs_version")?;
+                let packed = version.call(&mut store, ())?;
+                (instance, packed)
+            }
+        };
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("failed to find module memory"))?;
+        let mut buffer = vec![0; (packed & u64::from(u32::MAX)) as usize];
+        memory.read(&store, (packed >> 32) as usize, &mut buffer)?;
+
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// Lists the exports of the loaded module by name, e.g. to check a custom module passed to
+    /// `QuickJSBuilder::with_module` exports the `memory` and entrypoint the host expects.
+    ///
+    /// Compiles the module if compilation is lazy and it has not been compiled yet.
+    pub fn module_exports(&self) -> Result<Vec<(String, ExternType)>> {
+        Ok(self
+            .module()?
+            .exports()
+            .map(|export| (export.name().to_string(), export.ty()))
+            .collect())
+    }
+
+    /// Lists the imports of the loaded module as `(namespace, name, type)`, e.g. to debug a custom module which fails
+    /// to link because it imports functions the host does not define.
+    ///
+    /// Compiles the module if compilation is lazy and it has not been compiled yet.
+    pub fn module_imports(&self) -> Result<Vec<(String, String, ExternType)>> {
+        Ok(self
+            .module()?
+            .imports()
+            .map(|import| {
+                (
+                    import.module().to_string(),
+                    import.name().to_string(),
+                    import.ty(),
+                )
+            })
+            .collect())
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, stopping if it exceeds `time_limit`.
+    ///
+    /// The time limit applies to this call only and overrides any time limit the instance was built with. The
+    /// instance must be built with a time limit or `QuickJSBuilder::with_interruptible` so that the engine is
+    /// interruptible. The limit is checked at the instance's evaluation interval rather than the interval of
+    /// `time_limit`.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    /// * `time_limit`: The time limit for this execution.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute`, or an error if the execution exceeds `time_limit`.
+    pub fn try_execute_with_timeout(
+        &self,
+        script: &str,
+        data: Option<&str>,
+        time_limit: TimeLimit,
+    ) -> Result<Option<String>> {
+        let options = ExecuteOptions::new().with_time_limit(time_limit);
+        Ok(self
+            .try_execute_with_options(script, data, &options)?
+            .output)
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data on a separate thread, abandoning the
+    /// execution if it has not completed within `timeout`.
+    ///
+    /// This is a safeguard on top of epoch interruption in case a script is not interrupted (e.g. due to a bug in the
+    /// engine), returning `QuickJSError::HardTimeout` without waiting for the execution. A thread cannot be killed so
+    /// an abandoned execution keeps its thread and memory until it completes. Build the instance with a time limit
+    /// so that abandoned executions are still interrupted and their resources reclaimed.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    /// * `timeout`: How long to wait for the execution before abandoning it.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute`, or an error if the execution does not complete within `timeout`.
+    pub fn try_execute_with_hard_timeout(
+        &self,
+        script: &str,
+        data: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Option<String>> {
+        let quickjs = self.clone();
+        let script = script.to_string();
+        let data = data.map(str::to_string);
+
+        // The receiver is dropped if the execution is abandoned so the result of a late execution is discarded.
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let _ = sender.send(quickjs.try_execute(&script, data.as_deref()));
+        });
+
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(QuickJSError::HardTimeout(timeout).into()),
+            Err(RecvTimeoutError::Disconnected) => bail!("execution thread panicked"),
+        }
+    }
+
+    /// Attempts to execute many independent scripts, each with optional input data, in a single instantiation of the
+    /// module.
+    ///
+    /// This amortizes the cost of instantiating the module across the jobs. Each job runs in a fresh JavaScript
+    /// context, freed once the job completes, so jobs cannot observe each other's globals, but they share the instance's
+    /// memory limit. The time limit applies to each job rather than the whole batch. If a job aborts the guest (e.g. by
+    /// exceeding the time limit) the jobs after it are not executed. Values passed
+    /// to `emit` are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `jobs`: The JavaScript code and optional input data of each job.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute` for each job, in order.
+    pub fn try_execute_batch(&self, jobs: &[(&str, Option<&str>)]) -> Vec<Result<Option<String>>> {
+        self.execute_batch(jobs, false)
+    }
+
+    /// Attempts to execute many independent scripts, each with optional input data, in a single instantiation of the
+    /// module, failing with every error rather than returning a result per job.
+    ///
+    /// Every job is executed as with `try_execute_batch`, so e.g. validating many records reports all of the invalid
+    /// records at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `jobs`: The JavaScript code and optional input data of each job.
+    ///
+    /// # Returns
+    ///
+    /// The output of every job, in order, if all succeeded. Otherwise `QuickJSError::Batch` with the index and error
+    /// of each job which failed.
+    pub fn try_execute_batch_all(
+        &self,
+        jobs: &[(&str, Option<&str>)],
+    ) -> Result<Vec<Option<String>>> {
+        let mut outputs = Vec::with_capacity(jobs.len());
+        let mut failures = Vec::new();
+        for (index, result) in self.execute_batch(jobs, false).into_iter().enumerate() {
+            match result {
+                Ok(output) => outputs.push(output),
+                Err(err) => failures.push((index, err)),
+            }
+        }
+
+        match failures.is_empty() {
+            true => Ok(outputs),
+            false => Err(QuickJSError::Batch(failures).into()),
+        }
+    }
+
+    /// Attempts to execute many independent named scripts against the same optional input data in a single
+    /// instantiation of the module.
+    ///
+    /// This suits evaluating many small named scripts, e.g. rules of a configuration, where results keyed by name are
+    /// more convenient than positional results. As with `try_execute_batch` each script runs in a fresh JavaScript
+    /// context so scripts cannot observe each other's globals. Scripts are executed in an unspecified order.
+    ///
+    /// # Arguments
+    ///
+    /// * `scripts`: The JavaScript code of each script by name.
+    /// * `data`: Optional input data passed to every script.
+    /// * `fail_fast`: Whether to stop at the first script which returns an error. The scripts which were not executed
+    ///   return an error saying so. Otherwise every script is executed.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute` for each script, keyed by its name.
+    pub fn try_execute_named(
+        &self,
+        scripts: HashMap<String, &str>,
+        data: Option<&str>,
+        fail_fast: bool,
+    ) -> HashMap<String, Result<Option<String>>> {
+        let (names, jobs): (Vec<_>, Vec<_>) = scripts
+            .into_iter()
+            .map(|(name, script)| (name, (script, data)))
+            .unzip();
+
+        names
+            .into_iter()
+            .zip(self.execute_batch(&jobs, fail_fast))
+            .collect()
+    }
+
+    /// Executes the jobs of a batch, optionally stopping at the first job which returns an error.
+    fn execute_batch(
+        &self,
+        jobs: &[(&str, Option<&str>)],
+        fail_fast: bool,
+    ) -> Vec<Result<Option<String>>> {
+        let batch = jobs
+            .iter()
+            .map(|(script, data)| Job {
+                script: script.as_bytes().to_vec(),
+                data: data
+                    .map(|data| data.as_bytes().to_vec())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        self.execute_jobs(batch, self.options(), fail_fast)
+    }
+
+    /// Executes `batch` in a single instantiation of the module with `options`, returning the result of every job.
+    fn execute_jobs(
+        &self,
+        batch: Vec<Job>,
+        options: Options,
+        fail_fast: bool,
+    ) -> Vec<Result<Option<String>>> {
+        let jobs = batch.len();
+        let options = Options {
+            batch: true,
+            ..options
+        };
+
+        let mut results: Vec<Result<Option<String>>> = match self.execute(Invocation {
+            options,
+            batch,
+            fail_fast,
+            ..Default::default()
+        }) {
+            Ok(output) => output
+                .results
+                .into_iter()
+                .map(|result| Ok(result?.map(String::from_utf8).transpose()?))
+                .collect(),
+            Err(err) => vec![Err(err)],
+        };
+
+        // Jobs after one which aborted the guest, or failed if failing fast, were never executed.
+        while results.len() < jobs {
+            if fail_fast {
+                results.push(Err(anyhow!("job not executed as an earlier job failed")));
+            } else {
+                results.push(Err(anyhow!(
+                    "job not executed as an earlier job aborted the batch"
+                )));
+            }
+        }
+        results
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, collecting every value it emits.
+    ///
+    /// Scripts emit values by calling the `emit(value)` global, which suits scripts producing a stream of records
+    /// rather than a single value.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// Each emitted value as JSON in the order it was emitted, followed by the script's result unless it evaluated to `undefined`.
+    pub fn try_execute_many(&self, script: &str, data: Option<&str>) -> Result<Vec<String>> {
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        let output = self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data,
+            options: self.options(),
+            ..Default::default()
+        })?;
+
+        output
+            .emitted
+            .into_iter()
+            .chain(output.value)
+            .map(|output| Ok(String::from_utf8(output)?))
+            .collect()
+    }
+
+    /// Executes the given JavaScript code with optional input data on a background thread, streaming every value it
+    /// emits to an async consumer as soon as it is emitted.
+    ///
+    /// Scripts emit values by calling the `emit(value)` global. The stream yields each emitted value as JSON,
+    /// followed by the script's result unless it evaluated to `undefined`, as with `try_execute_many`. An error,
+    /// including one returned after some values were emitted, is yielded as the last item. The stream does not depend
+    /// on an async runtime: the execution runs on its own thread and wakes the consuming task as values arrive.
+    ///
+    /// Dropping the stream before it ends cancels the execution if the instance has a time limit or is built with
+    /// `with_interruptible`, otherwise the execution runs to completion and its remaining values are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// A `futures_core::Stream` of each emitted value as JSON.
+    pub fn try_execute_stream(&self, script: &str, data: Option<&str>) -> ExecuteStream {
+        let cancellation = CancellationToken::new();
+        let (stream, sender) = ExecuteStream::new(cancellation.clone());
+        let sender = Arc::new(sender);
+        let on_emit: EmitCallback = {
+            let sender = sender.clone();
+            Arc::new(move |value: Vec<u8>| {
+                sender.send(String::from_utf8(value).map_err(Into::into))
+            })
+        };
+
+        let quickjs = self.clone();
+        let invocation = Invocation {
+            script: script.as_bytes().to_vec(),
+            data: data
+                .map(|data| data.as_bytes().to_vec())
+                .unwrap_or_default(),
+            options: self.options(),
+            cancellation: Some(cancellation),
+            on_emit: Some(on_emit),
+            ..Default::default()
+        };
+        thread::spawn(move || match quickjs.execute(invocation) {
+            Ok(output) => {
+                if let Some(value) = output.value {
+                    sender.send(String::from_utf8(value).map_err(Into::into));
+                }
+            }
+            Err(err) => sender.send(Err(err)),
+        });
+
+        stream
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, parsing every value it emits as one
+    /// record of a newline-delimited JSON (NDJSON) stream.
+    ///
+    /// Scripts emit records by calling the `emit(value)` global. Each record is serialized as compact JSON on a
+    /// single line, regardless of `with_pretty_output`, so joining the records with `\n` produces NDJSON for other
+    /// tooling. Unlike `try_execute_many` the script's result is not a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over each emitted record in the order it was emitted.
+    pub fn try_execute_ndjson(
+        &self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<impl Iterator<Item = serde_json::Value>> {
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+        let options = Options {
+            pretty_output: false,
+            ..self.options()
+        };
+
+        let output = self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data,
+            options,
+            ..Default::default()
+        })?;
+
+        let records = output
+            .emitted
+            .iter()
+            .map(|record| Ok(serde_json::from_slice(record)?))
+            .collect::<Result<Vec<serde_json::Value>>>()?;
+        Ok(records.into_iter())
+    }
+
+    /// Attempts to execute the given JavaScript code with plain text, such as CSV or a template, as its input.
+    ///
+    /// The text is bound as the string global `text` without being parsed as JSON, so it does not need to be wrapped
+    /// in a JSON string. The `data` global is not defined and the input transform is not applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `text`: The text to bind as the `text` global.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute`.
+    pub fn try_execute_text(&self, script: &str, text: &str) -> Result<Option<String>> {
+        let options = Options {
+            text_input: true,
+            ..self.options()
+        };
+
+        self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data: text.as_bytes().to_vec(),
+            options,
+            ..Default::default()
+        })?
+        .into_json()
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, returning raw bytes rather than JSON.
+    ///
+    /// The script sets the bytes with the `setBinaryOutput(bytes, contentType)` global, where `bytes` is an
+    /// `ArrayBuffer`, a typed array or a string (encoded as UTF-8) and `contentType` is an optional tag such as
+    /// `"image/png"`. The bytes are sent verbatim rather than serialized to JSON. The script's result is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// The bytes and content type passed to the last call of `setBinaryOutput`, or an error if the script throws or
+    /// does not call `setBinaryOutput`.
+    pub fn try_execute_binary(
+        &self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<(Vec<u8>, Option<String>)> {
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data,
+            options: self.options(),
+            ..Default::default()
+        })?
+        .binary
+        .ok_or_else(|| anyhow!("script did not call setBinaryOutput"))
+    }
+
+    /// Attempts to execute the given JavaScript code with optional input data, returning the names of the globals it
+    /// changed alongside its result, e.g. to track the dependencies between the cells of a spreadsheet-like engine.
+    ///
+    /// A global is changed if it is an enumerable own property of the global object which the script defined,
+    /// deleted or assigned a different value to, compared with `Object.is`. Mutating an object held by a global,
+    /// such as `config.limit = 1`, does not change the global. As with `list_globals`, declarations made with `let`,
+    /// `const` or `class` do not create properties of the global object so are not reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to execute as a string.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// The result of the script as with `try_execute` and the names of the changed globals, in the order they
+    /// appear on the global object followed by any deleted globals.
+    pub fn try_execute_tracked(
+        &self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<(Option<String>, Vec<String>)> {
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+        let options = Options {
+            track_globals: true,
+            ..self.options()
+        };
+
+        let output = self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data,
+            options,
+            ..Default::default()
+        })?;
+        let value = output.value.map(String::from_utf8).transpose()?;
+
+        Ok((value, output.changed_globals))
+    }
+
+    /// Compiles the given JavaScript code without running it and returns the problems found, e.g. for editor tooling.
+    ///
+    /// The QuickJS parser stops at the first error so at most one diagnostic is returned. Early errors which QuickJS
+    /// raises while compiling, such as an invalid assignment target, are included and distinguished by their `kind`.
+    /// Errors which only occur when the script runs, such as referencing an undefined variable, are not.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to compile as a string.
+    ///
+    /// # Returns
+    ///
+    /// The problems found, or an empty vector if the script compiles.
+    pub fn diagnose(&self, script: &str) -> Result<Vec<Diagnostic>> {
+        let options = Options {
+            diagnose: true,
+            ..self.options()
+        };
+
+        let output = self
+            .execute(Invocation {
+                script: script.as_bytes().to_vec(),
+                options,
+                ..Default::default()
+            })?
+            .value
+            .ok_or_else(|| anyhow!("guest did not return diagnostics"))?;
+
+        Ok(serde_json::from_slice(&output)?)
+    }
+
+    /// Compiles the given JavaScript code to QuickJS bytecode without executing it.
+    ///
+    /// The bytecode can be executed with `try_execute_bytecode` to skip parsing the script on every execution.
+    /// Bytecode is specific to the QuickJS version in the module so must be executed by an instance built with
+    /// the same module that compiled it.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to compile as a string.
+    ///
+    /// # Returns
+    ///
+    /// The compiled bytecode, or an error if the script could not be compiled (e.g. a syntax error).
+    pub fn compile(&self, script: &str) -> Result<Vec<u8>> {
+        let options = Options {
+            compile: true,
+            ..self.options()
+        };
+
+        self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            options,
+            ..Default::default()
+        })?
+        .value
+        .ok_or_else(|| anyhow!("guest did not return bytecode"))
+    }
+
+    /// Compiles the given JavaScript code once so it can be executed with many inputs by `PreparedScript::apply`.
+    ///
+    /// This suits serving a single fixed script against a stream of inputs, where only the input data changes between
+    /// executions.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to compile as a string.
+    ///
+    /// # Returns
+    ///
+    /// The prepared script, or an error if the script could not be compiled (e.g. a syntax error).
+    pub fn prepare(&self, script: &str) -> Result<PreparedScript> {
+        Ok(PreparedScript::new(self.clone(), self.compile(script)?))
+    }
+
+    /// Attempts to execute bytecode produced by `compile` with optional input data.
+    ///
+    /// # Safety
+    ///
+    /// Only execute bytecode from a trusted source, e.g. produced by `compile` on this module and stored where
+    /// scripts cannot modify it. QuickJS does not validate bytecode as it reads it, so malformed or malicious bytecode
+    /// can corrupt the guest's memory and escape the JavaScript sandbox. The corruption is confined to the guest's
+    /// linear memory by wasm and the limits enforced by the host, such as the memory limit, time limit and secret
+    /// redaction, still apply, but the script is no longer bound by the engine's own checks, such as the stack size
+    /// limit. Compile untrusted source with `compile` rather than accepting bytecode from its author.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytecode`: The bytecode to execute.
+    /// * `data`: Optional JSON input data, bound as the `data` global.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute` would return for the source the bytecode was compiled from.
+    pub fn try_execute_bytecode(
+        &self,
+        bytecode: &[u8],
+        data: Option<&str>,
+    ) -> Result<Option<String>> {
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+        let options = Options {
+            bytecode: true,
+            ..self.options()
+        };
+
+        self.execute(Invocation {
+            script: bytecode.to_vec(),
+            data,
+            options,
+            ..Default::default()
+        })?
+        .into_json()
+    }
+
+    /// Transforms each of `items` with the given JavaScript code, one execution per item.
+    ///
+    /// The script is compiled once and then evaluated with each item bound as the `item` global, and its result is
+    /// the transformed item. Items are transformed as the returned iterator is advanced so only one item is held in
+    /// the guest at a time, which keeps the memory of processing a huge array proportional to a single item rather
+    /// than the whole array. Each item is a separate execution so items cannot observe each other.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code transforming the `item` global as a string.
+    /// * `items`: The items to transform.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the result of each item, in order. A result of `undefined` is returned as `null`. If the
+    /// script fails to compile every item returns the compile error.
+    pub fn try_map<'a>(
+        &'a self,
+        script: &str,
+        items: impl IntoIterator<Item = serde_json::Value> + 'a,
+    ) -> impl Iterator<Item = Result<serde_json::Value>> + 'a {
+        let bytecode = self.compile(script);
+        items.into_iter().map(move |item| {
+            let bytecode = bytecode.as_ref().map_err(|err| anyhow!("{err:#}"))?;
+            let options = Options {
+                bytecode: true,
+                data_global: Some("item".to_string()),
+                ..self.options()
+            };
+
+            let output = self.execute(Invocation {
+                script: bytecode.clone(),
+                data: serde_json::to_vec(&item)?,
+                options,
+                ..Default::default()
+            })?;
+            match output.value {
+                Some(value) => Ok(serde_json::from_slice(&value)?),
+                None => Ok(serde_json::Value::Null),
+            }
+        })
+    }
+
+    /// Applies the given JavaScript code to each of `inputs` concurrently, compiling the script only once.
+    ///
+    /// This is the fan-out pattern of the `par_iter` example: the script is compiled to bytecode once, the inputs are
+    /// split into chunks across one thread per available CPU and each chunk is executed as a batch in a single
+    /// instantiation of the module, as with `try_execute_batch`, so the cost of instantiating the module is shared by
+    /// the inputs of a chunk. Each input is still evaluated in a fresh JavaScript context bound as `data`, so inputs
+    /// cannot observe each other. Combine with `QuickJSBuilder::with_max_concurrency` to bound the number of
+    /// chunks executing at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to apply to each input as a string.
+    /// * `inputs`: The JSON input data of each execution.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute` for each input, in order. If the script fails to compile every input returns
+    /// the compile error.
+    pub fn try_map_parallel(&self, script: &str, inputs: Vec<&str>) -> Vec<Result<Option<String>>> {
+        let bytecode = match self.compile(script) {
+            Ok(bytecode) => bytecode,
+            Err(err) => return inputs.iter().map(|_| Err(anyhow!("{err:#}"))).collect(),
+        };
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        let chunk_size = inputs.len().div_ceil(threads).max(1);
+        let chunks = inputs.chunks(chunk_size).collect::<Vec<_>>();
+
+        // Each thread takes the next chunk until none remain so threads finishing early pick up the remainder.
+        let next_chunk = AtomicUsize::new(0);
+        let mut results = thread::scope(|scope| {
+            let workers = (0..threads.min(chunks.len()))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = Vec::new();
+                        loop {
+                            let index = next_chunk.fetch_add(1, Ordering::Relaxed);
+                            let Some(chunk) = chunks.get(index) else {
+                                return results;
+                            };
+                            let batch = chunk
+                                .iter()
+                                .map(|data| Job {
+                                    script: bytecode.clone(),
+                                    data: data.as_bytes().to_vec(),
+                                })
+                                .collect();
+                            let options = Options {
+                                bytecode: true,
+                                ..self.options()
+                            };
+                            results.push((index, self.execute_jobs(batch, options, false)));
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        results.sort_by_key(|(index, _)| *index);
+        results
+            .into_iter()
+            .flat_map(|(_, results)| results)
+            .collect()
+    }
+
+    /// Evaluates the script and then calls the function `func` it defines with `params` as its only argument.
+    ///
+    /// This suits scripts written as handlers, e.g. `function handler({ id, name }) { ... }`, which destructure named
+    /// parameters rather than reading the `data` global, and avoids mistakes in the order of positional arguments. The
+    /// parameters are not bound as `data`. Functions cannot be passed as parameters, but host functions registered
+    /// with `QuickJSBuilder::with_host_function` can be called by the handler. The function must be declared at the
+    /// top level of a classic script so cannot be used with `QuickJSBuilder::with_module_eval`, and a returned promise
+    /// is not awaited.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code defining the function as a string.
+    /// * `func`: The name of the function to call. Must be a JavaScript identifier.
+    /// * `params`: The named parameters passed to the function as an object.
+    ///
+    /// # Returns
+    ///
+    /// The JSON result of the function, or `None` if it returned `undefined`.
+    pub fn try_invoke_named(
+        &self,
+        script: &str,
+        func: &str,
+        params: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Option<String>> {
+        if self.module_eval {
+            bail!("functions cannot be invoked with module evaluation");
+        }
+        if !is_identifier(func) {
+            bail!("function name `{func}` is not an identifier");
+        }
+
+        let options = Options {
+            invoke: Some(func.to_string()),
+            ..self.options()
+        };
+
+        self.execute(Invocation {
+            script: script.as_bytes().to_vec(),
+            data: serde_json::to_vec(&params)?,
+            options,
+            ..Default::default()
+        })?
+        .into_json()
+    }
+
+    /// Evaluates the script and returns the names of the globals it defines.
+    ///
+    /// The names are the enumerable own properties of the global object which did not exist before the script was
+    /// evaluated, in the order they were defined. Declarations made with `let`, `const` or `class` do not create
+    /// properties of the global object so are not included. This is useful for discovering the handlers exported
+    /// by a plugin.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: The JavaScript code to evaluate as a string.
+    ///
+    /// # Returns
+    ///
+    /// The names of the globals defined by the script.
+    pub fn list_globals(&self, script: &str) -> Result<Vec<String>> {
+        let options = Options {
+            list_globals: true,
+            ..self.options()
+        };
+
+        let output = self
+            .execute(Invocation {
+                script: script.as_bytes().to_vec(),
+                options,
+                ..Default::default()
+            })?
+            .value
+            .ok_or_else(|| anyhow!("guest did not return globals"))?;
+
+        Ok(serde_json::from_slice(&output)?)
+    }
+
+    /// Builds the invocation of `script` with optional input data and per-call options, checking the instance
+    /// supports the options.
+    fn invocation(
+        &self,
+        script: &str,
+        data: Option<&str>,
+        options: &ExecuteOptions,
+    ) -> Result<Invocation> {
+        if options.cancellation.is_some()
+            && self.ticker.is_none()
+            && self.interrupt_fuel_interval.is_none()
+        {
+            bail!(
+                "cancellation requires a time limit, `with_interruptible` or `with_interrupt_fuel_interval`"
+            );
+        }
+        if options.time_limit.is_some() && self.ticker.is_none() {
+            bail!("a per-execution time limit requires a time limit or `with_interruptible`");
+        }
+        if let Some(name) = options
+            .capabilities
+            .iter()
+            .flatten()
+            .find(|name| !self.host_functions.contains_key(*name))
+        {
+            bail!("capability `{name}` is not a registered host function");
+        }
+
+        let defaults = self.options();
+        Ok(Invocation {
+            script: script.as_bytes().to_vec(),
+            data: data
+                .map(|data| data.as_bytes().to_vec())
+                .unwrap_or_default(),
+            options: Options {
+                guard: options.guard.clone(),
+                host_functions: options
+                    .capabilities
+                    .clone()
+                    .unwrap_or(defaults.host_functions),
+                live_console: defaults.live_console || options.on_console.is_some(),
+                ..defaults
+            },
+            cancellation: options.cancellation.clone(),
+            time_limit: options.time_limit.clone(),
+            request_id: options.request_id.clone(),
+            on_console: options.on_console.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// Returns the guest options derived from this instance's configuration.
+    fn options(&self) -> Options {
+        Options {
+            random_seed: self.random_seed,
+            gc_threshold: self.gc_threshold,
+            pretty_output: self.pretty_output,
+            read_only_data: self.read_only_data,
+            strict_mode: self.strict_mode,
+            max_input_depth: self.max_input_depth,
+            output_allowlist: self.output_allowlist.clone(),
+            output_denylist: self.output_denylist.clone(),
+            live_console: self.console_callback.is_some(),
+            host_functions: self.host_functions.keys().cloned().collect(),
+            js_heap_limit: self.js_heap_limit,
+            secrets: self.secrets.clone(),
+            module_eval: self.module_eval,
+            data_resolver: self.data_resolver.is_some(),
+            shared_cache: self.shared_cache.is_some(),
+            shared_cache_writable: self
+                .shared_cache
+                .as_ref()
+                .is_some_and(|(_, writable)| *writable),
+            statement_mode: self.eval_mode == EvalMode::Statement,
+            data_global: self.data_global.clone(),
+            unhandled_rejection_is_error: self.unhandled_rejection_is_error,
+            output_placeholder: self.output_placeholder,
+            stack_on_timeout: self.stack_on_timeout,
+            big_integers: self.big_integers,
+            max_string_length: self.max_string_length,
+            max_array_length: self.max_array_length,
+            constants: self.constants.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the module, compiling it if compilation is lazy and this is the first use.
+    fn module(&self) -> Result<&Module> {
+        if let Some(module) = self.module.get() {
+            return Ok(module);
+        }
+
+        // Concurrent first executions may each compile the module but only the first to finish is kept.
+        let module = compile_module(&self.engine, self.module_path.as_ref())?;
+        Ok(self.module.get_or_init(|| module))
+    }
+
+    /// Diffs the module's imports and exports against the host interface, returning an error if they do not match.
+    fn incompatible_module(
+        &self,
+        module: &Module,
+        linker: &Linker<State>,
+        store: &mut Store<State>,
+    ) -> Option<QuickJSError> {
+        let missing_exports = MODULE_EXPORTS
+            .iter()
+            .filter(|name| module.get_export(name).is_none())
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        let unexpected_imports = module
+            .imports()
+            .filter(|import| {
+                linker
+                    .get(&mut *store, import.module(), import.name())
+                    .is_none()
+            })
+            .map(|import| format!("{}::{}", import.module(), import.name()))
+            .collect::<Vec<_>>();
+
+        (!missing_exports.is_empty() || !unexpected_imports.is_empty()).then_some(
+            QuickJSError::IncompatibleModule {
+                missing_exports,
+                unexpected_imports,
+            },
+        )
+    }
+
+    /// Executes the invocation between the calls to the before and after execute functions, if set.
+    fn execute(&self, invocation: Invocation) -> Result<RawOutput> {
+        let request_id = invocation.request_id.clone();
+        let output = match &self.before_execute {
+            Some(before_execute) => before_execute().and_then(|_| self.run(invocation)),
+            None => self.run(invocation),
+        };
+        if let Some(after_execute) = &self.after_execute {
+            after_execute(output.as_ref().map(|output| &output.stats));
+        }
+        match request_id {
+            Some(request_id) => output.with_context(|| format!("request {request_id}")),
+            None => output,
+        }
+    }
+
+    /// Instantiates the module and executes the invocation, returning the raw bytes passed to `set_output`.
+    fn run(&self, invocation: Invocation) -> Result<RawOutput> {
+        let Invocation {
+            script,
+            data,
+            options,
+            cancellation,
+            time_limit,
+            batch,
+            fail_fast,
+            script_reader,
+            request_id,
+            on_emit,
+            on_console,
+            stats: stats_cell,
+        } = invocation;
+        let is_batch = options.batch;
+        // The output of `compile` is bytecode rather than JSON so is neither limited nor truncated.
+        let limit_output = !options.compile;
+        let pretty_output = options.pretty_output;
+
+        if self.shutdown.is_cancelled() {
+            return Err(QuickJSError::Shutdown.into());
+        }
+        // The permit is held until the store and its memory are dropped at the end of the execution.
+        let _permit = self
+            .max_concurrency
+            .as_ref()
+            .map(|semaphore| semaphore.acquire())
+            .transpose()?;
+        let module = self.module()?;
+
+        // The job being executed. In a batch the guest replaces it with the next pending job by calling `next_job`.
+        // Text input is not JSON so is passed to the guest without the input transform.
+        let job = Arc::new(Mutex::new(Job {
+            script,
+            data: if options.text_input {
+                data
+            } else {
+                self.transform_data(data)?
+            },
+        }));
+        let pending = Arc::new(Mutex::new(
+            batch
+                .into_iter()
+                .map(|Job { script, data }| {
+                    Ok(Job {
+                        script,
+                        data: self.transform_data(data)?,
+                    })
+                })
+                .collect::<Result<VecDeque<_>>>()?,
+        ));
+
+        // The host functions the script may call. Calls to any other function are rejected by the host even if the
+        // guest attempts them.
+        let host_functions = options
+            .host_functions
+            .iter()
+            .filter_map(|name| {
+                self.host_functions
+                    .get(name)
+                    .map(|function| (name.clone(), function.clone()))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        // Serialize the execution options for the guest
+        let options = serde_json::to_vec(&options)?;
+
+        // Get the size of the options as an i32 (for WASI API calls)
+        let options_size = options.len() as i32;
+
+        // Clone the linker with the WASI library already defined
+        let mut linker = self.linker.clone();
+
+        // Create the WASI context from the instance's configuration with fresh pipes and sinks which count the
+        // output that is discarded because it is not inherited.
+        let WasiInstance {
+            ctx: wasi,
+            discarded_stdout,
+            discarded_stderr,
+        } = self.wasi.instantiate();
+
+        // The secrets redacted from everything the guest sends to the host.
+        let secrets = self.redacted.clone();
+
+        // Determine memory type and limits based on self.memory_limit.
+        let (memory_type, limits) = match self.memory_limit {
+            // If self.memory_limit is Some, the memory type and limits share the same page-aligned limit.
+            Some(memory_limit) => (
+                MemoryType::new(memory_pages(memory_limit), Some(memory_pages(memory_limit))),
+                StoreLimitsBuilder::new()
+                    .instances(1)
+                    .memory_size(effective_memory_limit(memory_limit))
+                    .build(),
+            ),
+            // If self.memory_limit is None, use default values for memory type and limits.
+            None => (
+                MemoryType::new(1, None),
+                StoreLimitsBuilder::new().instances(1).build(),
+            ),
+        };
+
+        // Create a new store instance with the engine and initial state.
+        let mut store = Store::new(
+            &self.engine,
+            State {
+                wasi,
+                limiter: Limiter::new(limits),
+                abort_message: None,
+                binary_output: None,
+                host_function_result: Vec::new(),
+                timings: [Duration::ZERO; 3],
+                changed_globals: Vec::new(),
+                progressed: false,
+                epochs_elapsed: 0,
+                job_started: false,
+                timed_out: false,
+                max_pending_jobs: 0,
+            },
+        );
+
+        // Set the limiter for the store to enforce its limits and collect statistics.
+        store.limiter(move |state| &mut state.limiter);
+
+        // If the instance is interruptible, the shared ticker increments the epoch at regular intervals.
+        if self.ticker.is_some() {
+            let time_limit = time_limit.as_ref().or(self.time_limit.as_ref());
+
+            // The number of epochs between callbacks. Without a time limit the callback runs every epoch.
+            let continue_epochs = time_limit
+                .map(|time_limit| time_limit.continue_epochs.max(1))
+                .unwrap_or(1);
+
+            // Calculate initial epoch limit from time limit. Without a time limit the budget is unlimited.
+            let initial_epoch_limit = time_limit
+                .map(|time_limit| {
+                    u32::try_from(time_limit.limit.as_micros() / self.epoch_interval.as_micros())
+                })
+                .transpose()?;
+            let mut epoch_limit = initial_epoch_limit;
+            // An idle timeout restores the full budget whenever the guest has made progress.
+            let idle = time_limit.is_some_and(|time_limit| time_limit.idle);
+            let stack_on_timeout = self.stack_on_timeout;
+
+            // The number of epochs a cancelled execution may keep running for.
+            let mut grace_epochs =
+                self.cancellation_grace.as_micros() / self.epoch_interval.as_micros();
+            let cancellation = cancellation.clone();
+            let shutdown = self.shutdown.clone();
+
+            // Set up callback for when the epoch deadline is reached.
+            store.epoch_deadline_callback(move |mut context| {
+                // If the instance has been shut down, return error without waiting for the grace period.
+                if shutdown.is_cancelled() {
+                    return Err(QuickJSError::Shutdown.into());
+                }
+                // If the execution has been cancelled and the grace period has passed, return error.
+                if cancellation
+                    .as_ref()
+                    .is_some_and(|cancellation| cancellation.is_cancelled())
+                {
+                    if grace_epochs == 0 {
+                        return Err(QuickJSError::Cancelled.into());
+                    }
+                    grace_epochs = grace_epochs.saturating_sub(continue_epochs as u128);
+                }
+                // Every job of a batch gets the full budget.
+                if std::mem::take(&mut context.data_mut().job_started) {
+                    epoch_limit = initial_epoch_limit;
+                    context.data_mut().epochs_elapsed = 0;
+                }
+                if idle && std::mem::take(&mut context.data_mut().progressed) {
+                    epoch_limit = initial_epoch_limit;
+                    context.data_mut().epochs_elapsed = 0;
+                }
+                context.data_mut().epochs_elapsed += continue_epochs;
+                // If epoch limit reaches 0, return error. Otherwise deduct the epochs since the last callback.
+                match &mut epoch_limit {
+                    // The guest is given one more callback to interrupt the script and report its stack.
+                    Some(0) if stack_on_timeout && !context.data().timed_out => {
+                        context.data_mut().timed_out = true;
+                    }
+                    Some(0) => return Err(QuickJSError::TimeLimitExceeded { stack: None }.into()),
+                    Some(epoch_limit) => {
+                        *epoch_limit = epoch_limit
+                            .saturating_sub(u32::try_from(continue_epochs).unwrap_or(u32::MAX))
+                    }
+                    None => {}
+                }
+                // Continue evaluation.
+                Ok(UpdateDeadline::Continue(continue_epochs))
+            });
+
+            // Set initial epoch deadline.
+            store.set_epoch_deadline(continue_epochs);
+        }
+
+        // If fuel is consumed, the execution is interrupted once the fuel limit is exhausted.
+        if self.fuel_limit.is_some() || self.interrupt_fuel_interval.is_some() {
+            store.set_fuel(self.fuel_limit.unwrap_or(u64::MAX))?;
+        }
+
+        // Yield every `interrupt_fuel_interval` units of fuel so the cancellation token can be checked.
+        if self.interrupt_fuel_interval.is_some() {
+            store.fuel_async_yield_interval(self.interrupt_fuel_interval)?;
+        }
+
+        // Create new memory instance with the store and calculated memory type.
+        Memory::new(&mut store, memory_type)?;
+
+        // Watch the guest's memory growth once the host memory has been created so it does not cross the thresholds.
+        if let (Some((thresholds, callback)), Some(memory_limit)) =
+            (&self.memory_thresholds, self.memory_limit)
+        {
+            store.data_mut().limiter.watch_memory(
+                effective_memory_limit(memory_limit),
+                thresholds.clone(),
+                callback.clone(),
+            );
+        }
+
+        // Wraps the host function to retrieve the size of the script.
+        // This function is exposed as `get_script_size` in the JavaScript context.
+        let script_job = job.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "get_script_size",
+            move |_: Caller<'_, State>| -> Result<i32> {
+                Ok(script_job.lock().unwrap().script.len() as i32)
+            },
+        )?;
+
+        // Wraps the host function to retrieve the script data.
+        // This function is exposed as `get_script` in the JavaScript context.
+        let script_job = job.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "get_script",
+            move |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_memory(&mut caller, ptr, &script_job.lock().unwrap().script)
+            },
+        )?;
+
+        // Wraps the host function to read the next chunk of a streamed script directly into the guest's memory.
+        // Returns the number of bytes read, or 0 once the script has been read.
+        let script_reader = Mutex::new(script_reader);
+        linker.func_wrap(
+            &self.import_namespace,
+            "read_script_chunk",
+            move |mut caller: Caller<'_, State>, ptr: i32, capacity: i32| -> Result<i32> {
+                let mut script_reader = script_reader.lock().unwrap();
+                let Some(script_reader) = script_reader.as_mut() else {
+                    return Ok(0);
+                };
+                let memory = guest_memory(&mut caller)?;
+
+                let offset = ptr as u32 as usize;
+                let capacity = usize::try_from(capacity)
+                    .map_err(|_| anyhow!("invalid capacity {capacity} of script chunk"))?;
+                let end = offset.checked_add(capacity).ok_or_else(|| {
+                    anyhow!("script chunk at {offset} of capacity {capacity} overflows")
+                })?;
+                let buffer = memory
+                    .data_mut(&mut caller)
+                    .get_mut(offset..end)
+                    .ok_or_else(|| {
+                        anyhow!("script chunk at {offset} of capacity {capacity} is out of bounds")
+                    })?;
+                Ok(script_reader.read(buffer)? as i32)
+            },
+        )?;
+
+        // Wraps the host function to retrieve the size of the input data.
+        // This function is exposed as `get_data_size` in the JavaScript context.
+        let data_job = job.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "get_data_size",
+            move |_: Caller<'_, State>| -> Result<i32> {
+                Ok(data_job.lock().unwrap().data.len() as i32)
+            },
+        )?;
+
+        // Wraps the host function to retrieve the input data.
+        // This function is exposed as `get_data` in the JavaScript context.
+        let data_job = job.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "get_data",
+            move |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_memory(&mut caller, ptr, &data_job.lock().unwrap().data)
+            },
+        )?;
+
+        // Whether a job of the batch has returned an error, after which a fail-fast batch executes no more jobs.
+        let failed = Arc::new(AtomicBool::new(false));
+
+        // Wraps the host function to advance to the next job of a batch.
+        // Returns 1 if the next job has replaced the current job, or 0 if every job has been executed.
+        let job_failed = failed.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "next_job",
+            move |mut caller: Caller<'_, State>| -> Result<i32> {
+                if fail_fast && job_failed.load(Ordering::Relaxed) {
+                    return Ok(0);
+                }
+                match pending.lock().unwrap().pop_front() {
+                    Some(next) => {
+                        *job.lock().unwrap() = next;
+                        // The time limit applies to each job, so it restarts with the next one.
+                        caller.data_mut().job_started = true;
+                        caller.data_mut().timed_out = false;
+                        Ok(1)
+                    }
+                    None => Ok(0),
+                }
+            },
+        )?;
+
+        // Wraps the host function to retrieve the size of the execution options.
+        // This function is exposed as `get_options_size` in the JavaScript context.
+        linker.func_wrap(
+            &self.import_namespace,
+            "get_options_size",
+            move |_: Caller<'_, State>| -> Result<i32> { Ok(options_size) },
+        )?;
+
+        // Wraps the host function to retrieve the execution options.
+        // This function is exposed as `get_options` in the JavaScript context.
+        linker.func_wrap(
+            &self.import_namespace,
+            "get_options",
+            move |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_memory(&mut caller, ptr, &options)
+            },
+        )?;
+
+        // A channel to retrieve any emitted values followed by the result once the script completes.
+        let (sender, receiver) = channel();
+
+        // Wraps the host function to retrieve the output data from the host memory.
+        // This function is exposed as `set_output` in the JavaScript context.
+        let output_secrets = secrets.clone();
+        let unmark_emitted = self.big_integers && limit_output;
+        linker.func_wrap(
+            &self.import_namespace,
+            "set_output",
+            move |mut caller: Caller<'_, State>,
+                  ptr: i32,
+                  capacity: i32,
+                  error: i32|
+                  -> Result<()> {
+                // Check for invalid capacity
+                if capacity == 0 && error != OUTPUT_EMITTED {
+                    // If the capacity is zero, send None to the guest.
+                    sender.send(OutputMessage::Result(None)).unwrap();
+                } else {
+                    // Read the output from guest memory and redact any secrets.
+                    let buffer = read_guest_bytes(&mut caller, ptr, capacity)?;
+                    let buffer = output_secrets.redact(buffer);
+
+                    // If an error occurred, convert the message to an error and send it back; otherwise, send the output back.
+                    // The output is left as bytes as it is not necessarily UTF-8 (e.g. bytecode).
+                    if error == OUTPUT_EMITTED {
+                        caller.data_mut().progressed = true;
+                        match &on_emit {
+                            Some(on_emit) if unmark_emitted => on_emit(unmark_big_integers(buffer)),
+                            Some(on_emit) => on_emit(buffer),
+                            None => sender.send(OutputMessage::Emitted(buffer)).unwrap(),
+                        }
+                    } else if error == 0 {
+                        sender
+                            .send(OutputMessage::Result(Some(Ok(buffer))))
+                            .unwrap();
+                    } else {
+                        failed.store(true, Ordering::Relaxed);
+                        let message = String::from_utf8(buffer)?;
+                        sender
+                            .send(OutputMessage::Result(Some(Err(QuickJSError::from_guest(
+                                error, message,
+                            )))))
+                            .unwrap();
+                    };
+                };
+
+                Ok(())
+            },
+        )?;
+
+        // Wraps the host function to deliver a console line to the console callback as soon as it is written.
+        // This function is only called by the guest if `with_live_console` is set.
+        let console_callback = on_console.or_else(|| self.console_callback.clone());
+        let console_secrets = secrets.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "console_line",
+            move |mut caller: Caller<'_, State>, ptr: i32, size: i32, stream: i32| -> Result<()> {
+                caller.data_mut().progressed = true;
+                let buffer = read_guest_bytes(&mut caller, ptr, size)?;
+                if let Some(console_callback) = &console_callback {
+                    let stream = if stream == 1 {
+                        ConsoleStream::Stderr
+                    } else {
+                        ConsoleStream::Stdout
+                    };
+                    let buffer = console_secrets.redact(buffer);
+                    let line = String::from_utf8_lossy(&buffer);
+                    console_callback(stream, &line, request_id.as_deref());
+                }
+
+                Ok(())
+            },
+        )?;
+
+        // Wraps the host function to receive the raw bytes passed to `setBinaryOutput`, bypassing JSON serialization.
+        // A negative content type size means no content type was given.
+        let binary_secrets = secrets.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "set_binary_output",
+            move |mut caller: Caller<'_, State>,
+                  ptr: i32,
+                  size: i32,
+                  content_type_ptr: i32,
+                  content_type_size: i32|
+                  -> Result<()> {
+                caller.data_mut().progressed = true;
+                let bytes = read_guest_bytes(&mut caller, ptr, size)?;
+                let content_type = if content_type_size < 0 {
+                    None
+                } else {
+                    let buffer =
+                        read_guest_bytes(&mut caller, content_type_ptr, content_type_size)?;
+                    Some(String::from_utf8(buffer)?)
+                };
+                caller.data_mut().binary_output =
+                    Some((binary_secrets.redact(bytes), content_type));
+
+                Ok(())
+            },
+        )?;
+
+        // Wraps the host function to report whether the execution has been cancelled.
+        // This function backs the `aborted` flag of `AbortSignal` in the guest.
+        let signal_cancellation = cancellation.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "is_cancelled",
+            move |_: Caller<'_, State>| -> Result<i32> {
+                Ok(signal_cancellation
+                    .as_ref()
+                    .is_some_and(|cancellation| cancellation.is_cancelled())
+                    .into())
+            },
+        )?;
+
+        // Wraps the host function to report whether the time limit has been exceeded.
+        // This function is only called by the guest if `with_stack_on_timeout` is set.
+        linker.func_wrap(
+            &self.import_namespace,
+            "is_timed_out",
+            move |caller: Caller<'_, State>| -> Result<i32> { Ok(caller.data().timed_out.into()) },
+        )?;
+
+        // Wraps the host function to call a host function registered with `with_host_function`.
+        // Returns the size of the JSON result, which the guest then retrieves with `get_host_function_result`. A
+        // `HostFunctionError` is returned as its message instead, with the size encoded as `-size - 1`, so the guest
+        // throws it as an exception rather than the execution being aborted.
+        linker.func_wrap(
+            &self.import_namespace,
+            "call_host_function",
+            move |mut caller: Caller<'_, State>,
+                  name_ptr: i32,
+                  name_size: i32,
+                  args_ptr: i32,
+                  args_size: i32|
+                  -> Result<i32> {
+                caller.data_mut().progressed = true;
+                let name = String::from_utf8(read_guest_bytes(&mut caller, name_ptr, name_size)?)?;
+                let args = read_guest_bytes(&mut caller, args_ptr, args_size)?;
+                let args: Vec<serde_json::Value> = serde_json::from_slice(&args)?;
+
+                let function = host_functions
+                    .get(&name)
+                    .ok_or_else(|| anyhow!("host function `{name}` is not available"))?;
+                let (result, thrown) = match function(&args) {
+                    Ok(result) => (serde_json::to_vec(&result)?, false),
+                    Err(err) => match err.downcast::<HostFunctionError>() {
+                        Ok(err) => (err.message.into_bytes(), true),
+                        Err(err) => return Err(err),
+                    },
+                };
+                let size = result.len() as i32;
+                caller.data_mut().host_function_result = result;
+
+                Ok(if thrown { -size - 1 } else { size })
+            },
+        )?;
+
+        // Wraps the host function to resolve a dataset requested with `loadData` with the data resolver.
+        // Returns the size of the JSON dataset, which the guest then retrieves with `get_host_function_result`.
+        let data_resolver = self.data_resolver.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "load_data",
+            move |mut caller: Caller<'_, State>, name_ptr: i32, name_size: i32| -> Result<i32> {
+                caller.data_mut().progressed = true;
+                let name = String::from_utf8(read_guest_bytes(&mut caller, name_ptr, name_size)?)?;
+
+                let data_resolver = data_resolver
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("no data resolver is set"))?;
+                let data = serde_json::to_vec(&data_resolver(&name)?)?;
+                let size = data.len() as i32;
+                caller.data_mut().host_function_result = data;
+
+                Ok(size)
+            },
+        )?;
+
+        // Wraps the host function to read a value from the shared cache. Returns the size of the JSON value, which the
+        // guest then retrieves with `get_host_function_result`, or -1 if the key is missing.
+        let cache = self.shared_cache.clone();
+        linker.func_wrap(
+            &self.import_namespace,
+            "cache_get",
+            move |mut caller: Caller<'_, State>, key_ptr: i32, key_size: i32| -> Result<i32> {
+                caller.data_mut().progressed = true;
+                let key = String::from_utf8(read_guest_bytes(&mut caller, key_ptr, key_size)?)?;
+
+                let (cache, _) = cache
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("no shared cache is set"))?;
+                let value = match cache.read().unwrap().get(&key) {
+                    Some(value) => serde_json::to_vec(value)?,
+                    None => return Ok(-1),
+                };
+                let size = value.len() as i32;
+                caller.data_mut().host_function_result = value;
+
+                Ok(size)
+            },
+        )?;
+
+        // Wraps the host function to write a JSON value to the shared cache if scripts are allowed to, within the
+        // limits on its entries.
+        let cache = self.shared_cache.clone();
+        let (max_entries, max_entry_size) = self.shared_cache_limits;
+        linker.func_wrap(
+            &self.import_namespace,
+            "cache_set",
+            move |mut caller: Caller<'_, State>,
+                  key_ptr: i32,
+                  key_size: i32,
+                  value_ptr: i32,
+                  value_size: i32|
+                  -> Result<()> {
+                caller.data_mut().progressed = true;
+                let cache = match &cache {
+                    Some((cache, true)) => cache,
+                    _ => bail!("the shared cache is read-only"),
+                };
+                let entry_size = (key_size as i64).saturating_add(value_size as i64);
+                if entry_size > max_entry_size as i64 {
+                    bail!("shared cache entry of {entry_size} bytes exceeds the maximum of {max_entry_size}");
+                }
+                let key = String::from_utf8(read_guest_bytes(&mut caller, key_ptr, key_size)?)?;
+                let value = read_guest_bytes(&mut caller, value_ptr, value_size)?;
+                let value: serde_json::Value = serde_json::from_slice(&value)?;
+
+                let mut cache = cache.write().unwrap();
+                if cache.len() >= max_entries && !cache.contains_key(&key) {
+                    bail!("shared cache exceeds the maximum of {max_entries} entries");
+                }
+                cache.insert(key, value);
+
+                Ok(())
+            },
+        )?;
+
+        // Wraps the host function to retrieve the result of the last call to `call_host_function`, `load_data` or
+        // `cache_get`.
+        linker.func_wrap(
+            &self.import_namespace,
+            "get_host_function_result",
+            move |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                let result = std::mem::take(&mut caller.data_mut().host_function_result);
+                write_memory(&mut caller, ptr, &result)
+            },
+        )?;
+
+        // Wraps the host function to record the names of the globals the script changed as a JSON array.
+        linker.func_wrap(
+            &self.import_namespace,
+            "set_changed_globals",
+            move |mut caller: Caller<'_, State>, ptr: i32, size: i32| -> Result<()> {
+                let names = read_guest_bytes(&mut caller, ptr, size)?;
+                caller.data_mut().changed_globals = serde_json::from_slice(&names)?;
+
+                Ok(())
+            },
+        )?;
+
+        // Wraps the host function to record the time the guest spent transcoding the input, evaluating the script and
+        // transcoding the output, in nanoseconds.
+        linker.func_wrap(
+            &self.import_namespace,
+            "set_timings",
+            move |mut caller: Caller<'_, State>,
+                  input_transcode: i64,
+                  evaluation: i64,
+                  output_transcode: i64|
+                  -> Result<()> {
+                caller.data_mut().timings = [input_transcode, evaluation, output_transcode]
+                    .map(|nanos| Duration::from_nanos(nanos as u64));
+
+                Ok(())
+            },
+        )?;
+
+        // Wraps the host function to receive the most jobs the guest ran in a single drain of its job queue.
+        linker.func_wrap(
+            &self.import_namespace,
+            "set_max_pending_jobs",
+            move |mut caller: Caller<'_, State>, jobs: i32| -> Result<()> {
+                caller.data_mut().max_pending_jobs = jobs as u32;
+
+                Ok(())
+            },
+        )?;
+
+        // Wraps the host function to record why the guest is about to abort.
+        // This function is called by the guest's panic hook and when its entrypoint returns an error.
+        linker.func_wrap(
+            &self.import_namespace,
+            "set_abort_message",
+            move |mut caller: Caller<'_, State>, ptr: i32, size: i32| -> Result<()> {
+                let buffer = read_guest_bytes(&mut caller, ptr, size)?;
+                let buffer = secrets.redact(buffer);
+                caller.data_mut().abort_message =
+                    Some(String::from_utf8_lossy(&buffer).into_owned());
+
+                Ok(())
+            },
+        )?;
+
+        // Create a new module in the store with an empty name and link it to our current module. A custom module
+        // which does not match the host interface is diagnosed rather than returning the linker error.
+        // An asynchronous execution yields at fuel checkpoints, at which the cancellation token is checked.
+        let linked = match self.interrupt_fuel_interval {
+            Some(_) => interrupt::block_on(
+                linker.module_async(&mut store, "", module),
+                cancellation.as_ref(),
+                &self.shutdown,
+            )?,
+            None => linker.module(&mut store, "", module),
+        };
+        if let Err(err) = linked {
+            return Err(self
+                .incompatible_module(module, &linker, &mut store)
+                .map_or(err, Into::into));
+        }
+        let entrypoint = match linker.get_default(&mut store, "") {
+            Ok(entrypoint) => entrypoint,
+            Err(err) => {
+                return Err(self
+                    .incompatible_module(module, &linker, &mut store)
+                    .map_or(err, Into::into))
+            }
+        };
+
+        // Call the module's default entrypoint, measuring the CPU time of the calling thread which runs the guest.
+        let entrypoint = entrypoint.typed::<(), ()>(&store)?;
+        let cpu_start = cpu::thread_cpu_time();
+        let result = match self.interrupt_fuel_interval {
+            Some(_) => interrupt::block_on(
+                entrypoint.call_async(&mut store, ()),
+                cancellation.as_ref(),
+                &self.shutdown,
+            )
+            .unwrap_or_else(|err| Err(err.into())),
+            None => entrypoint.call(&mut store, ()),
+        };
+        let cpu_time = cpu_start
+            .zip(cpu::thread_cpu_time())
+            .map(|(start, end)| end.saturating_sub(start));
+
+        // The resource usage is taken before the result is checked so it is also reported for a failed execution.
+        let [input_transcode_time, evaluation_time, output_transcode_time] = store.data().timings;
+        let fuel_consumed =
+            match self.fuel_limit.is_some() || self.interrupt_fuel_interval.is_some() {
+                true => Some(self.fuel_limit.unwrap_or(u64::MAX) - store.get_fuel()?),
+                false => None,
+            };
+        let stats = ExecuteStats {
+            input_transcode_time,
+            evaluation_time,
+            output_transcode_time,
+            cpu_time,
+            max_pending_jobs: store.data().max_pending_jobs,
+            fuel_consumed,
+            ..store.data().limiter.stats()
+        };
+        if let Some(stats_cell) = &stats_cell {
+            *stats_cell.lock().unwrap() = stats;
+        }
+
+        // Distinguish the guest runtime exiting or trapping from other failures (e.g. exceeding limits).
+        // An exit without an abort message is intentional, e.g. the script calling `exit`, so exit code 0 succeeds
+        // with whatever was output before it.
+        let result = match result {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let message = store.data_mut().abort_message.take();
+                if let Some(exit) = err.downcast_ref::<I32Exit>() {
+                    match (exit.0, message) {
+                        (0, None) => Ok(()),
+                        (exit_code, None) => Err(QuickJSError::GuestExit(exit_code).into()),
+                        (exit_code, message) => Err(QuickJSError::GuestAborted {
+                            exit_code: Some(exit_code),
+                            message,
+                        }
+                        .into()),
+                    }
+                } else if let Some(Trap::UnreachableCodeReached) = err.downcast_ref::<Trap>() {
+                    Err(QuickJSError::GuestAborted {
+                        exit_code: None,
+                        message,
+                    }
+                    .into())
+                } else if let (Some(Trap::OutOfFuel), Some(fuel_limit)) =
+                    (err.downcast_ref::<Trap>(), self.fuel_limit)
+                {
+                    Err(QuickJSError::FuelExhausted(fuel_limit).into())
+                } else {
+                    Err(err)
+                }
+            }
+        };
+
+        // A batch keeps the results of the jobs which completed before the guest failed.
+        if !is_batch {
+            result?;
+        }
+
+        // Receive any message that was sent to this module and return it (if anything was sent)
+        // Warn if the script wrote output that was discarded as this is easily mistaken for the script not running.
+        let mut warnings = Vec::new();
+        for (stream, discarded) in [
+            ("stdout", discarded_stdout.bytes()),
+            ("stderr", discarded_stderr.bytes()),
+        ] {
+            if discarded > 0 {
+                warnings.push(format!(
+                    "script wrote {discarded} bytes to {stream} which were discarded as inherit_{stream} is false"
+                ));
+            }
+        }
+
+        // The guest has returned so every message has already been sent. Collect the emitted values and results.
+        let mut emitted = Vec::new();
+        let mut results = Vec::new();
+        // BigInts are marked by the guest so are written as integers once serialized. Bytecode is not JSON.
+        let unmark = |value: Vec<u8>| match self.big_integers && limit_output {
+            true => unmark_big_integers(value),
+            false => value,
+        };
+        for message in receiver.try_iter() {
+            match message {
+                OutputMessage::Emitted(value) => emitted.push(unmark(value)),
+                OutputMessage::Result(value) => {
+                    results.push(value.map(|value| value.map(unmark)).transpose())
+                }
+            }
+        }
+        // How close the execution came to its memory limit and time limit, as fractions of each.
+        let limit_status = LimitStatus::from_usage(
+            self.memory_limit.map(|memory_limit| {
+                stats.peak_memory as f64 / effective_memory_limit(memory_limit) as f64
+            }),
+            time_limit
+                .as_ref()
+                .or(self.time_limit.as_ref())
+                .map(|time_limit| {
+                    let elapsed =
+                        self.epoch_interval.as_secs_f64() * store.data().epochs_elapsed as f64;
+                    elapsed / time_limit.limit.as_secs_f64()
+                }),
+        );
+        let binary = store.data_mut().binary_output.take();
+        let changed_globals = std::mem::take(&mut store.data_mut().changed_globals);
+
+        if is_batch {
+            if let Err(err) = result {
+                results.push(Err(err));
+            }
+            return Ok(RawOutput {
+                emitted,
+                value: None,
+                warnings,
+                stats,
+                results,
+                binary,
+                changed_globals,
+                limit_status,
+                truncated: false,
+            });
+        }
+
+        match results.into_iter().next() {
+            Some(value) => {
+                let (value, truncated) = match (value?, self.max_output_bytes) {
+                    (Some(value), Some(limit)) if limit_output && value.len() > limit => {
+                        let truncated = match self.truncate_output {
+                            true => truncate_output(&value, limit, pretty_output)?,
+                            false => None,
+                        };
+                        match truncated {
+                            Some(truncated) => (Some(truncated), true),
+                            None => {
+                                return Err(QuickJSError::OutputTooLarge {
+                                    size: value.len(),
+                                    limit,
+                                }
+                                .into())
+                            }
+                        }
+                    }
+                    (value, _) => (value, false),
+                };
+
+                Ok(RawOutput {
+                    emitted,
+                    value,
+                    warnings,
+                    stats,
+                    results: Vec::new(),
+                    binary,
+                    changed_globals,
+                    limit_status,
+                    truncated,
+                })
+            }
+            None => {
+                // A module without an entrypoint is instantiated with a no-op entrypoint so never sets output.
+                if let Some(err) = self.incompatible_module(module, &linker, &mut store) {
+                    return Err(err.into());
+                }
+                bail!("guest did not set output")
+            }
+        }
+    }
+
+    /// Applies the input transform to well-formed data and then marks big integers if enabled. Malformed data is left
+    /// for the guest to report. The transform sees a `serde_json::Value`, so keys are reordered and integers outside
+    /// `i64`/`u64` lose precision, as documented on `with_input_transform`.
+    fn transform_data(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let data = match &self.input_transform {
+            Some(input_transform) if !data.is_empty() => {
+                match serde_json::from_slice::<serde_json::Value>(&data) {
+                    Ok(value) => serde_json::to_vec(&input_transform(value))?,
+                    Err(_) => data,
+                }
+            }
+            _ => data,
+        };
+        if self.big_integers {
+            return Ok(mark_big_integers(&data));
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn try_execute() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            'quickjs' + 'wasm'
+        "#;
+
+        let result = quickjs.try_execute(script, None).unwrap();
+
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_eval() -> Result<()> {
+        assert_eq!(
+            eval("'quickjs' + data.input", Some(r#"{"input":"wasm"}"#))?,
+            Some("\"quickjswasm\"".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_undefined() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            undefined
+        "#;
+
+        let result = quickjs.try_execute(script, None).unwrap();
+
+        assert_eq!(result, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_null() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            null
+        "#;
+
+        let result = quickjs.try_execute(script, None).unwrap();
+
+        assert_eq!(result, Some("null".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_discarded_output_warning() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let result = quickjs.try_execute_detailed("console.log('quickjs')", None)?;
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("inherit_stdout"));
+
+        let result = quickjs.try_execute_detailed("'quickjs'", None)?;
+        assert!(result.warnings.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_transcode_time() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let data = serde_json::to_string(&vec!["quickjs"; 100_000])?;
+        let result = quickjs.try_execute_detailed("data", Some(&data))?;
+        assert!(result.stats.input_transcode_time > Duration::ZERO);
+        assert!(result.stats.output_transcode_time > Duration::ZERO);
+
+        let result =
+            quickjs.try_execute_detailed("let i = 0; while (i < 100000) { i++; }", None)?;
+        assert!(result.stats.evaluation_time > Duration::ZERO);
+        assert_eq!(result.stats.input_transcode_time, Duration::ZERO);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_cpu_time() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let result =
+            quickjs.try_execute_detailed("let i = 0; while (i < 1000000) { i++; }", None)?;
+        if cfg!(unix) {
+            assert!(result
+                .stats
+                .cpu_time
+                .is_some_and(|cpu_time| cpu_time > Duration::ZERO));
+        } else {
+            assert_eq!(result.stats.cpu_time, None);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_transcript() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let events = quickjs.try_execute_transcript(
+            r#"
+            console.log("loading", data.name);
+            console.error("missing", 1);
+            console.log("done");
+            ({ ok: true })
+            "#,
+            Some(r#"{"name":"quickjs"}"#),
+        )?;
+        assert_eq!(
+            events,
+            vec![
+                OutputEvent::Log("loading quickjs".to_string()),
+                OutputEvent::Error("missing 1".to_string()),
+                OutputEvent::Log("done".to_string()),
+                OutputEvent::Result(serde_json::json!({ "ok": true })),
+            ]
+        );
+
+        assert_eq!(
+            quickjs.try_execute_transcript("console.log('only')", None)?,
+            vec![OutputEvent::Log("only".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_live_console() -> Result<()> {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let callback_lines = lines.clone();
+        let quickjs = QuickJSBuilder::new()
+            .with_live_console(Arc::new(move |stream, line, _| {
+                callback_lines
+                    .lock()
+                    .unwrap()
+                    .push((stream, line.to_string()));
+            }))
+            .build()?;
+
+        let script = r#"
+            console.log("progress", 1);
+            console.error("failed");
+            'quickjs'
+        "#;
+
+        let result = quickjs.try_execute_detailed(script, None)?;
+        assert_eq!(result.output, Some("\"quickjs\"".to_string()));
+        assert!(result.warnings.is_empty());
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![
+                (ConsoleStream::Stdout, "progress 1".to_string()),
+                (ConsoleStream::Stderr, "failed".to_string())
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_constant() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_constant("ENVIRONMENT", "production".into())
+            .with_constant(
+                "FEATURES",
+                serde_json::json!({ "beta": { "enabled": false } }),
+            )
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute("[ENVIRONMENT, FEATURES.beta.enabled]", None)?,
+            Some(r#"["production",false]"#.to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute(
+                "FEATURES.beta.enabled = true; ENVIRONMENT = 'dev'; [ENVIRONMENT, FEATURES.beta.enabled]",
+                None
+            )?,
+            Some(r#"["production",false]"#.to_string())
+        );
+        assert!(quickjs
+            .try_execute("'use strict'; FEATURES.beta.enabled = true", None)
+            .is_err());
+
+        for name in [
+            "data",
+            "emit",
+            "exit",
+            "gc",
+            "a-b",
+            r#"x"]; globalThis.pwned = ["#,
+        ] {
+            assert!(
+                QuickJSBuilder::new()
+                    .with_constant(name, 1.into())
+                    .build()
+                    .is_err(),
+                "{name}"
+            );
+        }
+        assert!(QuickJSBuilder::new()
+            .with_data_global("input")
+            .with_constant("data", 1.into())
+            .build()
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_secret() -> Result<()> {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let callback_lines = lines.clone();
+        let quickjs = QuickJSBuilder::new()
+            .with_secret("TOKEN", "s3cr3t\"")
+            .with_live_console(Arc::new(move |_, line, _| {
+                callback_lines.lock().unwrap().push(line.to_string());
+            }))
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute("TOKEN.length", None)?,
+            Some("7".to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute("console.log(`token ${TOKEN}`); ({ TOKEN })", None)?,
+            Some(r#"{"TOKEN":"[REDACTED]"}"#.to_string())
+        );
+        assert_eq!(*lines.lock().unwrap(), vec!["token [REDACTED]"]);
+
+        let err = quickjs
+            .try_execute("throw new Error(`bad token ${TOKEN}`)", None)
+            .unwrap_err();
+        assert!(err.to_string().contains("bad token [REDACTED]"));
+        assert!(!err.to_string().contains("s3cr3t"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_pretty_output() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_pretty_output(true).build()?;
+
+        assert_eq!(
+            quickjs.try_execute("({ a: [1] })", None)?,
+            Some("{\n  \"a\": [\n    1\n  ]\n}".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_base64() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            const attempt = (f) => {
+                try {
+                    return f();
+                } catch (error) {
+                    return error.name;
+                }
+            };
+            let bytes = "";
+            for (let i = 0; i < 256; i++) {
+                bytes += String.fromCharCode(i);
+            }
+            [
+                btoa(""),
+                atob(""),
+                btoa("a"),
+                btoa("ab"),
+                btoa("abc"),
+                btoa(123),
+                atob(btoa(bytes)) === bytes,
+                btoa("\xff"),
+                attempt(() => btoa("Ā")),
+                attempt(() => btoa("€")),
+                atob("YQ"),
+                atob("YWI"),
+                atob("YQ=="),
+                atob(" Y\tQ\n=\f=\r "),
+                attempt(() => atob("YQ=")),
+                attempt(() => atob("YQ===")),
+                attempt(() => atob("Y")),
+                attempt(() => atob("YW=J")),
+                attempt(() => atob("YQ-_")),
+                attempt(() => atob("\u00a0YQ==")),
+                attempt(() => btoa()),
+            ]
+        "#;
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some(
+                serde_json::json!([
+                    "",
+                    "",
+                    "YQ==",
+                    "YWI=",
+                    "YWJj",
+                    "MTIz",
+                    true,
+                    "/w==",
+                    "InvalidCharacterError",
+                    "InvalidCharacterError",
+                    "a",
+                    "ab",
+                    "a",
+                    "a",
+                    "InvalidCharacterError",
+                    "InvalidCharacterError",
+                    "InvalidCharacterError",
+                    "InvalidCharacterError",
+                    "InvalidCharacterError",
+                    "InvalidCharacterError",
+                    "TypeError"
+                ])
+                .to_string()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_stdin() -> Result<()> {
+        let script = r#"
+            const lines = [];
+            let line;
+            while ((line = readLine()) !== null) {
+                lines.push(line.toUpperCase());
+            }
+            lines
+        "#;
+
+        let quickjs = QuickJSBuilder::new()
+            .with_stdin(b"first\r\nsecond\nthird".to_vec())
+            .build()?;
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some(r#"["FIRST","SECOND","THIRD"]"#.to_string())
+        );
+        // every execution reads from the start
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some(r#"["FIRST","SECOND","THIRD"]"#.to_string())
+        );
+
+        let quickjs = QuickJS::new()?;
+        assert_eq!(quickjs.try_execute(script, None)?, Some("[]".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_unhandled_rejection_is_error() -> Result<()> {
+        let script = r#"
+            Promise.reject(new Error("forgotten"));
+            Promise.reject(new Error("caught")).catch(() => {});
+            const later = Promise.reject(new Error("caught later"));
+            Promise.resolve().then(() => later.catch(() => {}));
+            1
+        "#;
+
+        let quickjs = QuickJS::new()?;
+        assert_eq!(quickjs.try_execute(script, None)?, Some("1".to_string()));
+
+        let quickjs = QuickJSBuilder::new()
+            .with_unhandled_rejection_is_error(true)
+            .build()?;
+        match quickjs.try_execute(script, None) {
+            Err(err)
+                if err
+                    .to_string()
+                    .contains("unhandled promise rejection: Error: forgotten") => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            quickjs.try_execute(
+                &script.replace("Promise.reject(new Error(\"forgotten\"));", ""),
+                None
+            )?,
+            Some("1".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_max_pending_jobs() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_unhandled_rejection_is_error(true)
+            .build()?;
+
+        let output = quickjs.try_execute_detailed(
+            "for (let i = 0; i < 1000; i++) { Promise.resolve(i).then(() => {}); } 1",
+            None,
+        )?;
+        assert_eq!(output.stats.max_pending_jobs, 1000);
+
+        let output = quickjs.try_execute_detailed("1", None)?;
+        assert_eq!(output.stats.max_pending_jobs, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_circular_reference() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            const parent = { name: "parent", children: [] };
+            parent.children.push({ name: "child", parent });
+            parent
+        "#;
+        match quickjs.try_execute(script, None) {
+            Err(err) => match err.downcast_ref::<QuickJSError>() {
+                Some(QuickJSError::CircularReference { path }) => {
+                    assert_eq!(path, "$.children[0].parent")
+                }
+                _ => panic!("{:?}", err),
+            },
+            other => panic!("{:?}", other),
+        }
+
+        // a shared reference is not a cycle
+        assert_eq!(
+            quickjs.try_execute("const shared = { a: 1 }; [shared, { shared }]", None)?,
+            Some(r#"[{"a":1},{"shared":{"a":1}}]"#.to_string())
+        );
+
+        // an emitted cycle throws in the script
+        let script = r#"
+            const a = {};
+            a.a = a;
+            try {
+                emit(a);
+            } catch (error) {
+                String(error).includes("$.a")
+            }
+        "#;
+        assert_eq!(quickjs.try_execute(script, None)?, Some("true".to_string()));
+
+        // deep output without a cycle is serialized up to the maximum depth
+        let script = "let value = 1; for (let i = 0; i < 900; i++) value = [value]; value";
+        assert!(quickjs.try_execute(script, None)?.is_some());
+        let script = "let value = 1; for (let i = 0; i < 1100; i++) value = { value }; value";
+        match quickjs.try_execute(script, None) {
+            Err(err) => assert!(err.to_string().contains("exceeds maximum depth of 1000")),
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_output_placeholder() -> Result<()> {
+        let script = r#"
+            const node = { name: "node" };
+            node.self = node;
+            ({ count: 10n, values: [1, Symbol("symbol"), () => {}], shared: [node, node] })
+        "#;
+
+        let quickjs = QuickJSBuilder::new()
+            .with_output_placeholder(OutputPlaceholder::Null)
+            .build()?;
+        let output: serde_json::Value =
+            serde_json::from_str(&quickjs.try_execute(script, None)?.unwrap())?;
+        assert_eq!(output["count"], serde_json::Value::Null);
+        assert_eq!(output["values"], serde_json::json!([1, null, null]));
+        assert_eq!(output["shared"][0]["name"], "node");
+        assert_eq!(output["shared"][0]["self"], serde_json::Value::Null);
+        assert_eq!(output["shared"][1]["self"], serde_json::Value::Null);
+
+        let quickjs = QuickJSBuilder::new()
+            .with_output_placeholder(OutputPlaceholder::Description)
+            .build()?;
+        let output: serde_json::Value =
+            serde_json::from_str(&quickjs.try_execute(script, None)?.unwrap())?;
+        assert_eq!(
+            output["count"],
+            serde_json::json!({ "$unserializable": "BigInt" })
+        );
+        assert_eq!(
+            output["shared"][1]["self"],
+            serde_json::json!({ "$unserializable": "circular reference at $.shared[1].self" })
+        );
+
+        // values which serialize are unchanged
+        assert_eq!(
+            quickjs.try_execute("({ a: [1, 'b', null, { c: true }] })", None)?,
+            Some(r#"{"a":[1,"b",null,{"c":true}]}"#.to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_hooks() -> Result<()> {
+        let executions = Arc::new(Mutex::new(Vec::new()));
+        let before = executions.clone();
+        let after = executions.clone();
+        let quickjs = QuickJSBuilder::new()
+            .with_before_execute(Arc::new(move || -> Result<()> {
+                let mut executions = before.lock().unwrap();
+                if executions.len() >= 4 {
+                    bail!("rate limited");
+                }
+                executions.push("before".to_string());
+                Ok(())
+            }))
+            .with_after_execute(Arc::new(
+                move |outcome: Result<&ExecuteStats, &anyhow::Error>| {
+                    let outcome = match outcome {
+                        Ok(_) => "ok".to_string(),
+                        Err(err) => err.to_string(),
+                    };
+                    after.lock().unwrap().push(outcome);
+                },
+            ))
+            .build()?;
+
+        assert_eq!(quickjs.try_execute("1", None)?, Some("1".to_string()));
+        assert!(quickjs
+            .try_execute("throw new Error('failed')", None)
+            .is_err());
+        match quickjs.try_execute("1", None) {
+            Err(err) if err.to_string() == "rate limited" => {}
+            other => panic!("{:?}", other),
+        }
+
+        let executions = executions.lock().unwrap();
+        assert_eq!(executions[..2], ["before", "ok"]);
+        assert_eq!(executions[2], "before");
+        assert!(executions[3].contains("failed"));
+        assert_eq!(executions[4], "rate limited");
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_data_global() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_data_global("input")
+            .with_read_only_data(true)
+            .build()?;
+
+        let script = r#"
+            const data = "unrelated";
+            input.value += 1;
+            [data, input.value]
+        "#;
+        assert_eq!(
+            quickjs.try_execute(script, Some(r#"{"value": 1}"#))?,
+            Some(r#"["unrelated",1]"#.to_string())
+        );
+
+        for name in [
+            "",
+            "1st",
+            "a-b",
+            "class",
+            r#"x"] = 1; globalThis.pwned = ["#,
+        ] {
+            assert!(
+                QuickJSBuilder::new()
+                    .with_data_global(name)
+                    .build()
+                    .is_err(),
+                "{name}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_strict_mode() -> Result<()> {
+        let script = r#"
+            undeclared = 1;
+            typeof undeclared
+        "#;
+
+        let quickjs = QuickJS::new()?;
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some("\"number\"".to_string())
+        );
+
+        let quickjs = QuickJSBuilder::new().with_strict_mode(true).build()?;
+        match quickjs.try_execute(script, None) {
+            Err(err) if err.to_string().contains("ReferenceError") => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn quickjs_version() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+        assert_eq!(quickjs.quickjs_version()?, "2021-03-27");
+
+        let quickjs = QuickJSBuilder::new()
+            .with_time_limit(TimeLimit::new(Duration::from_secs(1)))
+            .build()?;
+        assert_eq!(quickjs.quickjs_version()?, "2021-03-27");
+
+        Ok(())
+    }
+
+    #[test]
+    fn quickjs_version_matches_pinned_sys_crate() -> Result<()> {
+        // The guest hard-codes the QuickJS release bundled by the `quickjs-wasm-sys` it is built with, so bumping the
+        // crate without updating `QUICKJS_VERSION` in `quickjs-wasm` fails here.
+        let lock = include_str!("../../../Cargo.lock");
+        let sys_version = lock
+            .split("[[package]]")
+            .find(|package| package.contains("name = \"quickjs-wasm-sys\""))
+            .and_then(|package| {
+                package
+                    .lines()
+                    .find_map(|line| line.strip_prefix("version = "))
+            })
+            .map(|version| version.trim_matches('"'))
+            .expect("quickjs-wasm-sys is not in Cargo.lock");
+
+        let expected = match sys_version {
+            "1.2.1" => "2021-03-27",
+            other => panic!(
+                "unknown QuickJS release of quickjs-wasm-sys {other}, update QUICKJS_VERSION"
+            ),
+        };
+        assert_eq!(QuickJS::new()?.quickjs_version()?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn registry() -> Result<()> {
+        let registry = QuickJSRegistry::new();
+        registry.register("default", &QuickJSBuilder::new())?;
+        registry.register("strict", &QuickJSBuilder::new().with_strict_mode(true))?;
+        assert!(registry
+            .register(
+                "missing",
+                &QuickJSBuilder::new().with_module(PathBuf::from("missing.wasm"))
+            )
+            .is_err());
+        assert_eq!(registry.keys(), vec!["default", "strict"]);
+
+        let script = "undeclared = 1";
+        let quickjs = registry.get("default").unwrap();
+        assert_eq!(quickjs.try_execute(script, None)?, Some("1".to_string()));
+        let quickjs = registry.get("strict").unwrap();
+        assert!(quickjs.try_execute(script, None).is_err());
+
+        assert!(registry.remove("strict").is_some());
+        assert!(registry.get("strict").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn module_exports_and_imports() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let exports = quickjs.module_exports()?;
+        assert!(exports
+            .iter()
+            .any(|(name, ty)| name == "memory" && matches!(ty, ExternType::Memory(_))));
+
+        let imports = quickjs.module_imports()?;
+        assert!(imports.iter().any(
+            |(namespace, name, ty)| namespace == "wasi_snapshot_preview1"
+                && name == "fd_write"
+                && matches!(ty, ExternType::Func(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_lazy_compilation() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_lazy_compilation(true).build()?;
+        let clone = quickjs.clone();
+        assert_eq!(
+            quickjs.try_execute("'quickjs' + 'wasm'", None)?,
+            Some("\"quickjswasm\"".to_string())
+        );
+        assert_eq!(clone.try_execute("1 + 1", None)?, Some("2".to_string()));
+
+        // an invalid module is reported by the first execution rather than by build
+        let quickjs = QuickJSBuilder::new()
+            .with_module(PathBuf::from("missing.wasm"))
+            .with_lazy_compilation(true)
+            .build()?;
+        assert!(quickjs.try_execute("1", None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_eval_mode() -> Result<()> {
+        let script = r#"
+            let total = 0;
+            for (const value of data) {
+                total += value;
+            }
+        "#;
+
+        let quickjs = QuickJS::new()?;
+        assert_eq!(
+            quickjs.try_execute(script, Some("[1, 2, 3]"))?,
+            Some("6".to_string())
+        );
+
+        let quickjs = QuickJSBuilder::new()
+            .with_eval_mode(EvalMode::Statement)
+            .build()?;
+        assert_eq!(quickjs.try_execute(script, Some("[1, 2, 3]"))?, None);
+        assert_eq!(
+            quickjs.try_execute(&format!("{script}\nreturn total;"), Some("[1, 2, 3]"))?,
+            Some("6".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_module_eval() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_module_eval(true).build()?;
+
+        let script = r#"
+            export const double = (value) => value * 2;
+            export default double(data);
+        "#;
+        assert_eq!(
+            quickjs.try_execute(script, Some("21"))?,
+            Some("42".to_string())
+        );
+
+        let script = r#"
+            const load = async (value) => value + 1;
+            export default (async () => await load(data))();
+        "#;
+        assert_eq!(
+            quickjs.try_execute(script, Some("41"))?,
+            Some("42".to_string())
+        );
+
+        assert_eq!(quickjs.try_execute("export const a = 1;", None)?, None);
+        assert!(quickjs
+            .try_execute("export default await Promise.resolve(42);", None)
+            .unwrap_err()
+            .to_string()
+            .contains("SyntaxError"));
+        assert!(quickjs
+            .try_execute("export default Promise.reject(new Error('rejected'))", None)
+            .unwrap_err()
+            .to_string()
+            .contains("rejected"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_output_filter() -> Result<()> {
+        let script = r#"
+            ({ id: 1, password: "secret", user: { id: 2, name: "quickjs" }, items: [{ id: 3, password: "secret" }] })
+        "#;
+
+        let quickjs = QuickJSBuilder::new()
+            .with_output_denylist(["password"])
+            .build()?;
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some(r#"{"id":1,"user":{"id":2,"name":"quickjs"},"items":[{"id":3}]}"#.to_string())
+        );
+
+        let quickjs = QuickJSBuilder::new()
+            .with_output_allowlist(["id", "items", "password"])
+            .with_output_denylist(["password"])
+            .build()?;
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some(r#"{"id":1,"items":[{"id":3}]}"#.to_string())
+        );
+
+        // integer-like property names are filtered by their string form
+        let script = r#"({ "1": "one", "2": "two", name: "quickjs" })"#;
+        let quickjs = QuickJSBuilder::new().with_output_denylist(["1"]).build()?;
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some(r#"{"2":"two","name":"quickjs"}"#.to_string())
+        );
+
+        let quickjs = QuickJSBuilder::new()
+            .with_output_allowlist(["1", "name"])
+            .build()?;
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some(r#"{"1":"one","name":"quickjs"}"#.to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_data() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            'quickjs' + data.input
+        "#;
+
+        let data = r#"{"input": "wasm"}"#;
+
+        let result = quickjs.try_execute(script, Some(data)).unwrap();
+
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_text() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_input_transform(Arc::new(|_| serde_json::Value::Null))
+            .build()?;
+
+        let script = r#"
+            text.trim().split("\n").map((line) => line.split(","))
+        "#;
+        assert_eq!(
+            quickjs.try_execute_text(script, "a,b\n1,2\n")?,
+            Some(r#"[["a","b"],["1","2"]]"#.to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute_text("[text, typeof data]", "42")?,
+            Some(r#"["42","undefined"]"#.to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute_text("text.length", "")?,
+            Some("0".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_input_transform() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_input_transform(Arc::new(|mut value| {
+                if let Some(object) = value.as_object_mut() {
+                    object.remove("password");
+                }
+                value
+            }))
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute(
+                "Object.keys(data)",
+                Some(r#"{"user":"quickjs","password":"secret"}"#)
+            )?,
+            Some(r#"["user"]"#.to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_read_only_data() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_read_only_data(true).build()?;
+        let data = Some(r#"{"records":[{"id":1}]}"#);
+
+        let script = r#"
+            data.records[0].id = 2;
+            data.records.push({ id: 3 });
+        "#;
+        assert!(quickjs.try_execute(script, data).is_err());
+
+        let script = r#"
+            data.records[0].id = 2;
+            data = null;
+            data
+        "#;
+        assert_eq!(
+            quickjs.try_execute(script, data)?,
+            Some(r#"{"records":[{"id":1}]}"#.to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_invalid_data() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            'quickjs' + data.input
+        "#;
+
+        match quickjs.try_execute(script, Some("{bad json")) {
+            Err(err) => match err.downcast_ref::<QuickJSError>() {
+                Some(QuickJSError::InvalidInputData { line, column, .. }) => {
+                    assert_eq!((*line, *column), (1, 2));
+                }
+                _ => panic!("{:?}", err),
+            },
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_input_too_deep() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let data = format!("{}{}", "[".repeat(10000), "]".repeat(10000));
+        match quickjs.try_execute("data", Some(&data)) {
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::InputTooDeep(_))
+                ) => {}
+            other => panic!("{:?}", other),
+        }
+
+        let quickjs = QuickJSBuilder::new().with_max_input_depth(2).build()?;
+        assert_eq!(
+            quickjs.try_execute("data", Some(r#"[{"a":"[[["}]"#))?,
+            Some(r#"[{"a":"[[["}]"#.to_string())
+        );
+        assert!(quickjs.try_execute("data", Some("[[[1]]]")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_batch() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let results = quickjs.try_execute_batch(&[
+            ("var leaked = 1; data.input * 2", Some(r#"{"input":21}"#)),
+            ("typeof leaked", None),
+            ("throw new Error('failed')", None),
+            ("'quickjs'", None),
+        ]);
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap(), &Some("42".to_string()));
+        assert_eq!(
+            results[1].as_ref().unwrap(),
+            &Some("\"undefined\"".to_string())
+        );
+        assert!(results[2].is_err());
+        assert_eq!(
+            results[3].as_ref().unwrap(),
+            &Some("\"quickjs\"".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_batch_time_limit() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_time_limit(
+                TimeLimit::new(Duration::from_millis(300))
+                    .with_evaluation_interval(Duration::from_millis(10)),
+            )
+            .build()?;
+
+        // together the jobs exceed the time limit but each is within it
+        let script = r#"
+            const start = Date.now();
+            while (Date.now() - start < 150) {}
+            data.job
+        "#;
+        let results = quickjs.try_execute_batch(&[
+            (script, Some(r#"{"job":1}"#)),
+            (script, Some(r#"{"job":2}"#)),
+            (script, Some(r#"{"job":3}"#)),
+        ]);
+
+        assert_eq!(
+            results.into_iter().collect::<Result<Vec<_>>>()?,
+            vec![
+                Some("1".to_string()),
+                Some("2".to_string()),
+                Some("3".to_string())
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_batch_all() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        assert_eq!(
+            quickjs.try_execute_batch_all(&[("1", None), ("data", Some("2"))])?,
+            vec![Some("1".to_string()), Some("2".to_string())]
+        );
+
+        let err = quickjs
+            .try_execute_batch_all(&[
+                ("throw new Error('first')", None),
+                ("'quickjs'", None),
+                ("data", Some("{")),
+            ])
+            .unwrap_err();
+        match err.downcast_ref::<QuickJSError>() {
+            Some(QuickJSError::Batch(failures)) => {
+                assert_eq!(
+                    failures.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+                    vec![0, 2]
+                );
+                assert!(failures[0].1.to_string().contains("first"));
+                assert!(matches!(
+                    failures[1].1.downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::InvalidInputData { .. })
+                ));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(err.to_string().starts_with("2 jobs failed: job 0: "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_http() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            path: "/greet".to_string(),
+            headers: BTreeMap::from([("content-type".to_string(), "application/json".to_string())]),
+            body: Some(r#"{"name": "quickjs"}"#.to_string()),
+        };
+        let script = r#"
+            const { name } = JSON.parse(request.body);
+            ({
+                statusCode: request.method === "POST" ? 201 : 405,
+                headers: { "content-type": "text/plain" },
+                body: `hello, ${name}!`,
+            })
+        "#;
+        assert_eq!(
+            quickjs.try_execute_http(script, &request)?,
+            HttpResponse {
+                status_code: 201,
+                headers: BTreeMap::from([("content-type".to_string(), "text/plain".to_string())]),
+                body: Some("hello, quickjs!".to_string()),
+            }
+        );
+
+        assert_eq!(
+            quickjs.try_execute_http("({ statusCode: 204 })", &request)?,
+            HttpResponse {
+                status_code: 204,
+                ..Default::default()
+            }
+        );
+        assert!(quickjs.try_execute_http("undefined", &request).is_err());
+        assert!(quickjs
+            .try_execute_http("({ statusCode: 600 })", &request)
+            .is_err());
+        assert!(quickjs
+            .try_execute_http("({ statusCode: 200, body: 1 })", &request)
+            .is_err());
+        assert!(quickjs
+            .try_execute_http("({ status: 200 })", &request)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_reader() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        // larger than a single chunk so the script is read in several
+        let script = format!(
+            "const values = [{}];\nvalues.length + data.offset",
+            vec!["1"; 100_000].join(",")
+        );
+        assert_eq!(
+            quickjs.try_execute_reader(Cursor::new(script), Some(r#"{"offset": 1}"#))?,
+            Some("100001".to_string())
+        );
+        assert_eq!(quickjs.try_execute_reader(std::io::empty(), None)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_value_input() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let data = serde_json::json!({ "values": [1, 2, 3] });
+        assert_eq!(
+            quickjs.try_execute_value_input("data.values.reduce((a, b) => a + b)", Some(&data))?,
+            Some("6".to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute_value_input("typeof data", None)?,
+            Some("\"undefined\"".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_as() -> Result<()> {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let quickjs = QuickJS::new()?;
+        assert_eq!(
+            quickjs
+                .try_execute_as::<Point>("({ x: data.x, y: data.x * 2 })", Some(r#"{"x": 21}"#))?,
+            Some(Point { x: 21, y: 42 })
+        );
+        assert_eq!(quickjs.try_execute_as::<Point>("undefined", None)?, None);
+        assert!(quickjs
+            .try_execute_as::<Point>("({ x: 'quickjs' })", None)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_named() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+        let scripts = HashMap::from([
+            ("double".to_string(), "data.value * 2"),
+            ("invalid".to_string(), "throw new Error('invalid')"),
+            ("name".to_string(), "data.name"),
+        ]);
+        let data = Some(r#"{"value": 21, "name": "quickjs"}"#);
+
+        let results = quickjs.try_execute_named(scripts.clone(), data, false);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results["double"].as_ref().unwrap(), &Some("42".to_string()));
+        assert!(results["invalid"].is_err());
+        assert_eq!(
+            results["name"].as_ref().unwrap(),
+            &Some("\"quickjs\"".to_string())
+        );
+
+        // the scripts after the failure are not executed but which those are depends on the order of execution
+        let results = quickjs.try_execute_named(scripts, data, true);
+        assert_eq!(results.len(), 3);
+        assert!(results["invalid"].is_err());
+
+        let results = quickjs.try_execute_named(
+            HashMap::from([
+                ("first".to_string(), "throw new Error('first')"),
+                ("second".to_string(), "throw new Error('second')"),
+            ]),
+            None,
+            true,
+        );
+        assert_eq!(
+            results
+                .values()
+                .filter(
+                    |result| matches!(result, Err(err) if err.to_string().contains("not executed"))
+                )
+                .count(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_binary() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            const png = new Uint8Array([0, 137, 80, 78, 71]);
+            setBinaryOutput(png.subarray(1), "image/png");
+        "#;
+        assert_eq!(
+            quickjs.try_execute_binary(script, None)?,
+            (vec![137, 80, 78, 71], Some("image/png".to_string()))
+        );
+
+        assert_eq!(
+            quickjs.try_execute_binary("setBinaryOutput('quickjs')", None)?,
+            (b"quickjs".to_vec(), None)
+        );
+
+        assert!(quickjs.try_execute_binary("'quickjs'", None).is_err());
+
+        // objects which only look like a view are checked against their buffer
+        for view in [
+            "{ buffer: new ArrayBuffer(4), byteOffset: 2, byteLength: 8 }",
+            "{ buffer: new ArrayBuffer(4), byteOffset: -1, byteLength: 1 }",
+            "{ buffer: new ArrayBuffer(4), byteOffset: 0, byteLength: '4' }",
+        ] {
+            let err = quickjs
+                .try_execute_binary(&format!("setBinaryOutput({view})"), None)
+                .unwrap_err();
+            assert!(err.to_string().contains("setBinaryOutput"), "{view}: {err}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_isolation() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        quickjs.try_execute(
+            "globalThis.leak = 'tenant'; Array.prototype.leak = true; Math.random = () => 0;",
+            None,
+        )?;
+        assert_eq!(
+            quickjs.try_execute("[typeof leak, [].leak, Math.random() === 0]", None)?,
+            Some(r#"["undefined",null,false]"#.to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_map_parallel() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let inputs = (0..200)
+            .map(|i| format!(r#"{{"value":{i}}}"#))
+            .collect::<Vec<_>>();
+        let results = quickjs.try_map_parallel(
+            "if (data.value === 7) throw new Error('unlucky'); data.value * 2",
+            inputs.iter().map(String::as_str).collect(),
+        );
+        assert_eq!(results.len(), 200);
+        for (i, result) in results.iter().enumerate() {
+            match result {
+                Err(err) if i == 7 => assert!(err.to_string().contains("unlucky")),
+                Ok(output) => assert_eq!(output, &Some((i * 2).to_string())),
+                other => panic!("{i}: {:?}", other),
+            }
+        }
+
+        let results = quickjs.try_map_parallel("data.value +", vec!["{}", "{}"]);
+        assert!(results.iter().all(|result| result.is_err()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_invoke_named() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            function greet({ greeting, name }) {
+                return `${greeting}, ${name}!`;
+            }
+            const describe = ({ name }) => ({ name, length: name.length });
+        "#;
+        let params = serde_json::json!({ "name": "quickjs", "greeting": "hello" });
+        let params = params.as_object().unwrap();
+
+        assert_eq!(
+            quickjs.try_invoke_named(script, "greet", params.clone())?,
+            Some("\"hello, quickjs!\"".to_string())
+        );
+        assert_eq!(
+            quickjs.try_invoke_named(script, "describe", params.clone())?,
+            Some("{\"name\":\"quickjs\",\"length\":7}".to_string())
+        );
+        assert!(quickjs
+            .try_invoke_named(script, "missing", params.clone())
+            .is_err());
+        assert!(quickjs
+            .try_invoke_named(script, "greet(); 1", params.clone())
+            .is_err());
+        assert_eq!(
+            quickjs
+                .try_invoke_named(script, "class", params.clone())
+                .unwrap_err()
+                .to_string(),
+            "function name `class` is not an identifier"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_map() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let items = (1..=3).map(|i| serde_json::json!({ "value": i }));
+        let results = quickjs
+            .try_map("({ doubled: item.value * 2 })", items)
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            results,
+            vec![
+                serde_json::json!({ "doubled": 2 }),
+                serde_json::json!({ "doubled": 4 }),
+                serde_json::json!({ "doubled": 6 })
+            ]
+        );
+
+        let results = quickjs
+            .try_map("item.missing.value", [serde_json::json!({})])
+            .collect::<Vec<_>>();
+        assert!(results[0].is_err());
+
+        let results = quickjs
+            .try_map("(", [serde_json::json!(1), serde_json::json!(2)])
+            .collect::<Vec<_>>();
+        assert!(results.iter().all(|result| result.is_err()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_many() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            emit(1);
+            emit({ quickjs: 'wasm' });
+            'done'
+        "#;
+
+        let result = quickjs.try_execute_many(script, None)?;
+        assert_eq!(result, vec!["1", "{\"quickjs\":\"wasm\"}", "\"done\""]);
+
+        let result = quickjs.try_execute(script, None)?;
+        assert_eq!(result, Some("\"done\"".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_stream() -> Result<()> {
+        /// Wakes the test thread, which parks while the stream is pending.
+        struct ThreadWaker(thread::Thread);
+
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        fn collect(mut stream: ExecuteStream) -> Vec<Result<String>> {
+            use futures_core::Stream;
+            use std::task::{Context, Poll, Waker};
+
+            let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+            let mut context = Context::from_waker(&waker);
+            let mut items = Vec::new();
+            loop {
+                match std::pin::Pin::new(&mut stream).poll_next(&mut context) {
+                    Poll::Ready(Some(item)) => items.push(item),
+                    Poll::Ready(None) => return items,
+                    Poll::Pending => thread::park(),
+                }
+            }
+        }
+
+        let quickjs = QuickJS::new()?;
+
+        let items = collect(quickjs.try_execute_stream(
+            "for (const n of data) { emit({ n }); } 'done'",
+            Some("[1, 2]"),
+        ));
+        let items = items.into_iter().collect::<Result<Vec<_>>>()?;
+        assert_eq!(items, vec![r#"{"n":1}"#, r#"{"n":2}"#, r#""done""#]);
+
+        let items = collect(quickjs.try_execute_stream("emit(1); throw new Error('oops')", None));
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap(), "1");
+        assert!(items[1].is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_ndjson() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_pretty_output(true).build()?;
+
+        let script = r#"
+            for (const line of data) {
+                emit({ level: line.level, nested: { message: line.message } });
+            }
+            'done'
+        "#;
+        let data = r#"[{"level":"info","message":"started"},{"level":"error","message":"failed"}]"#;
+
+        let records = quickjs
+            .try_execute_ndjson(script, Some(data))?
+            .map(|record| record.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records.join("\n"),
+            "{\"level\":\"info\",\"nested\":{\"message\":\"started\"}}\n{\"level\":\"error\",\"nested\":{\"message\":\"failed\"}}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_throw_error() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            throw new Error('myerror');
+        "#;
+
+        match quickjs.try_execute(script, None) {
+            Err(err) if err.to_string().contains("Uncaught Error: myerror") => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_structured_clone() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            const record = { id: 1, tags: ["a", "b"], created: new Date(0) };
+            record.self = record;
+            const copy = structuredClone(record);
+            copy.tags.push("c");
+            [
+                copy !== record,
+                copy.self === copy,
+                record.tags.length,
+                deepEqual(structuredClone(data), data),
+                deepEqual({ a: [1, { b: 2 }] }, { a: [1, { b: 3 }] }),
+                deepEqual(new Set([1, { id: 2 }, [3]]), new Set([[3], { id: 2 }, 1])),
+                deepEqual(new Set([{ id: 1 }, { id: 1 }]), new Set([{ id: 1 }, { id: 2 }])),
+            ]
+        "#;
+
+        assert_eq!(
+            quickjs.try_execute(script, Some(r#"{"a":[1,{"b":null}]}"#))?,
+            Some("[true,true,2,true,false,true,false]".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_random_seed() -> Result<()> {
+        let script = r#"
+            [Math.random(), Array.from(crypto.getRandomValues(new Uint8Array(8)))]
+        "#;
+
+        let quickjs = QuickJSBuilder::new().with_random_seed(42).build()?;
+        let first = quickjs.try_execute(script, None)?;
+        let second = quickjs.try_execute(script, None)?;
+        assert_eq!(first, second);
+
+        let quickjs = QuickJSBuilder::new().with_random_seed(43).build()?;
+        let other = quickjs.try_execute(script, None)?;
+        assert_ne!(first, other);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_math_random_seed() -> Result<()> {
+        let script = r#"
+            Array.from({ length: 4 }, () => Math.random())
+        "#;
+
+        // separate instances with the same seed produce the same sequence
+        let first = QuickJSBuilder::new()
+            .with_random_seed(42)
+            .build()?
+            .try_execute(script, None)?
+            .unwrap();
+        let second = QuickJSBuilder::new()
+            .with_random_seed(42)
+            .build()?
+            .try_execute(script, None)?
+            .unwrap();
+        assert_eq!(first, second);
+
+        // the sequence is not a repeated value and every value is in [0, 1)
+        let values: Vec<f64> = serde_json::from_str(&first)?;
+        assert!(values.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(values.iter().all(|value| (0.0..1.0).contains(value)));
+
+        Ok(())
+    }
+
+    /// Writes a WebAssembly text module to a temporary file so it can be loaded with `with_module`.
+    fn write_module(name: &str, wat: &str) -> Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!("{name}.wat"));
+        std::fs::write(&path, wat)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn try_execute_exit() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        assert_eq!(
+            quickjs.try_execute_many("emit(1); exit(); 'unreachable'", None)?,
+            vec!["1"]
+        );
+        assert_eq!(quickjs.try_execute("exit(0)", None)?, None);
+
+        match quickjs.try_execute("exit(2)", None) {
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::GuestExit(2))
+                ) => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_guest_exit() -> Result<()> {
+        let module = write_module(
+            "quickjs_guest_exit",
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (memory (export "memory") 1)
+                (func (export "_start") (call $proc_exit (i32.const 3))))
+            "#,
+        )?;
+        let quickjs = QuickJSBuilder::new().with_module(module).build()?;
+
+        match quickjs.try_execute("'quickjs'", None) {
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::GuestExit(3))
+                ) => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_linker_extension() -> Result<()> {
+        let module = write_module(
+            "quickjs_linker_extension",
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (import "custom" "exit_code" (func $exit_code (result i32)))
+                (memory (export "memory") 1)
+                (func (export "_start") (call $proc_exit (call $exit_code))))
+            "#,
+        )?;
+
+        assert!(QuickJSBuilder::new()
+            .with_module(module.clone())
+            .build()?
+            .try_execute("'quickjs'", None)
+            .is_err_and(|err| !matches!(
+                err.downcast_ref::<QuickJSError>(),
+                Some(QuickJSError::GuestExit(_))
+            )));
+
+        let quickjs = QuickJSBuilder::new()
+            .with_module(module)
+            .with_linker_extension(Arc::new(|linker| {
+                linker.func_wrap("custom", "exit_code", |_: Caller<'_, State>| 7)?;
+                Ok(())
+            }))
+            .build()?;
+
+        match quickjs.try_execute("'quickjs'", None) {
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::GuestExit(7))
+                ) => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_incompatible_module() -> Result<()> {
+        let module = write_module(
+            "quickjs_incompatible_module",
+            r#"
+            (module
+                (import "host" "get_script_v2" (func $get_script_v2 (param i32)))
+                (memory (export "memory") 1))
+            "#,
+        )?;
+        let quickjs = QuickJSBuilder::new().with_module(module).build()?;
+
+        match quickjs.try_execute("'quickjs'", None) {
+            Err(err) => match err.downcast_ref::<QuickJSError>() {
+                Some(QuickJSError::IncompatibleModule {
+                    missing_exports,
+                    unexpected_imports,
+                }) => {
+                    assert_eq!(missing_exports, &vec!["_start".to_string()]);
+                    assert_eq!(unexpected_imports, &vec!["host::get_script_v2".to_string()]);
+                }
+                _ => panic!("{:?}", err),
+            },
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_import_namespace() -> Result<()> {
+        let module = write_module(
+            "quickjs_import_namespace",
+            r#"
+            (module
+                (import "env" "set_output" (func $set_output (param i32 i32 i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0) "42")
+                (func (export "_start")
+                    (call $set_output (i32.const 0) (i32.const 2) (i32.const 0))))
+            "#,
+        )?;
+        let quickjs = QuickJSBuilder::new()
+            .with_module(module)
+            .with_import_namespace("env")
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute("'quickjs'", None)?,
+            Some("42".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_guest_panic() -> Result<()> {
+        let module = write_module(
+            "quickjs_guest_panic",
+            r#"
+            (module
+                (import "host" "set_abort_message" (func $set_abort_message (param i32 i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0) "panicked")
+                (func (export "_start")
+                    (call $set_abort_message (i32.const 0) (i32.const 8))
+                    unreachable))
+            "#,
+        )?;
+        let quickjs = QuickJSBuilder::new().with_module(module).build()?;
+
+        match quickjs.try_execute("'quickjs'", None) {
+            Err(err) => match err.downcast_ref::<QuickJSError>() {
+                Some(QuickJSError::GuestAborted {
+                    exit_code: None,
+                    message: Some(message),
+                }) if message == "panicked" => {}
+                _ => panic!("{:?}", err),
+            },
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_bytecode() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            'quickjs' + data.input
+        "#;
+
+        let data = r#"{"input": "wasm"}"#;
+
+        let bytecode = quickjs.compile(script)?;
+        let result = quickjs.try_execute_bytecode(&bytecode, Some(data))?;
+
+        assert_eq!(result, quickjs.try_execute(script, Some(data))?);
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_compile_syntax_error() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        match quickjs.compile("'quickjs' +") {
+            Err(err) if err.to_string().contains("SyntaxError") => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_list_globals() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        let script = r#"
+            function onRequest(request) {
+                return request;
+            }
+            var version = 1;
+            globalThis.onResponse = (response) => response;
+            const hidden = true;
+        "#;
+
+        assert_eq!(
+            quickjs.list_globals(script)?,
+            vec!["onRequest", "version", "onResponse"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_with_capabilities() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_host_function(
+                "add",
+                Arc::new(|args: &[serde_json::Value]| -> Result<serde_json::Value> {
+                    Ok(args
+                        .iter()
+                        .filter_map(serde_json::Value::as_i64)
+                        .sum::<i64>()
+                        .into())
+                }),
+            )
+            .with_host_function(
+                "secret",
+                Arc::new(|_: &[serde_json::Value]| -> Result<serde_json::Value> {
+                    Ok("hunter2".into())
+                }),
+            )
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute("[add(1, 2), secret()]", None)?,
+            Some(r#"[3,"hunter2"]"#.to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute_with_capabilities("[add(1, 2), typeof secret]", None, &["add"])?,
+            Some(r#"[3,"undefined"]"#.to_string())
+        );
+
+        let err = quickjs
+            .try_execute_with_capabilities("secret()", None, &["add"])
+            .unwrap_err();
+        assert!(err.to_string().contains("ReferenceError"));
+
+        let err = quickjs
+            .try_execute_with_capabilities("add(1, 2)", None, &["missing"])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "capability `missing` is not a registered host function"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_host_function_error() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_host_function(
+                "lookup",
+                Arc::new(|args: &[serde_json::Value]| -> Result<serde_json::Value> {
+                    match args.first().and_then(serde_json::Value::as_str) {
+                        Some("quickjs") => Ok("wasm".into()),
+                        Some(key) => {
+                            Err(HostFunctionError::new(format!("cache miss for {key}")).into())
+                        }
+                        None => bail!("lookup expects a key"),
+                    }
+                }),
+            )
+            .build()?;
+
+        let script = r#"
+            try {
+                lookup('missing');
+            } catch (err) {
+                [err instanceof Error, err.message, lookup('quickjs')]
+            }
+        "#;
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some(r#"[true,"cache miss for missing","wasm"]"#.to_string())
+        );
+
+        let err = quickjs
+            .try_execute("try { lookup() } catch (err) { 'caught' }", None)
+            .unwrap_err();
+        assert!(format!("{err:?}").contains("lookup expects a key"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_data_resolver() -> Result<()> {
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let resolver_requested = requested.clone();
+        let quickjs = QuickJSBuilder::new()
+            .with_data_resolver(Arc::new(move |name: &str| -> Result<serde_json::Value> {
+                resolver_requested.lock().unwrap().push(name.to_string());
+                match name {
+                    "users" => Ok(serde_json::json!([{ "name": "quickjs" }])),
+                    _ => bail!("unknown dataset {name}"),
+                }
+            }))
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute(
+                "data.useUsers ? loadData('users')[0].name : null",
+                Some(r#"{"useUsers":true}"#)
+            )?,
+            Some("\"quickjs\"".to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute("typeof loadData", None)?,
+            Some("\"function\"".to_string())
+        );
+        assert_eq!(*requested.lock().unwrap(), vec!["users"]);
+
+        let err = quickjs.try_execute("loadData('orders')", None).unwrap_err();
+        assert!(format!("{err:?}").contains("unknown dataset orders"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_shared_cache() -> Result<()> {
+        let cache = SharedCache::default();
+        cache
+            .write()
+            .unwrap()
+            .insert("rates".to_string(), serde_json::json!({ "usd": 1.5 }));
+
+        let quickjs = QuickJSBuilder::new()
+            .with_shared_cache(cache.clone(), false)
+            .build()?;
+        assert_eq!(
+            quickjs.try_execute(
+                "data.amount * cacheGet('rates').usd",
+                Some(r#"{"amount":2}"#)
+            )?,
+            Some("3".to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute("[typeof cacheGet('missing'), typeof cacheSet]", None)?,
+            Some(r#"["undefined","undefined"]"#.to_string())
+        );
+
+        let quickjs = QuickJSBuilder::new()
+            .with_shared_cache(cache.clone(), true)
+            .build()?;
+        quickjs.try_execute("cacheSet('seen', [data.id])", Some(r#"{"id":1}"#))?;
+        assert_eq!(
+            quickjs.try_execute("cacheGet('seen')", None)?,
+            Some("[1]".to_string())
+        );
+        assert_eq!(
+            cache.read().unwrap().get("seen"),
+            Some(&serde_json::json!([1]))
+        );
+
+        let quickjs = QuickJSBuilder::new()
+            .with_shared_cache(cache.clone(), true)
+            .with_shared_cache_limits(3, 16)
+            .build()?;
+        quickjs.try_execute("cacheSet('seen', [2])", None)?;
+        quickjs.try_execute("cacheSet('next', 1)", None)?;
+        let err = quickjs
+            .try_execute("cacheSet('more', 1)", None)
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("shared cache exceeds the maximum of 3 entries"),
+            "{err:#}"
+        );
+        let err = quickjs
+            .try_execute("cacheSet('seen', 'x'.repeat(16))", None)
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("exceeds the maximum of 16"),
+            "{err:#}"
+        );
+        assert_eq!(cache.read().unwrap().len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_tracked() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_host_function(
+                "rate",
+                Arc::new(|_: &[serde_json::Value]| -> Result<serde_json::Value> { Ok(2.into()) }),
+            )
+            .with_secret("TOKEN", "s3cr3t")
+            .with_secret("STALE", "stale")
+            .build()?;
+
+        let (value, changed) = quickjs.try_execute_tracked(
+            r#"
+            var total = data.amount * rate();
+            TOKEN = 'rotated';
+            rate.calls = 1;
+            var label = 'total';
+            let local = 1;
+            delete globalThis.STALE;
+            total
+            "#,
+            Some(r#"{"amount":2}"#),
+        )?;
+        assert_eq!(value, Some("4".to_string()));
+        assert_eq!(changed, vec!["TOKEN", "total", "label", "STALE"]);
+
+        let (_, changed) = quickjs.try_execute_tracked("TOKEN = 's3cr3t'; null", None)?;
+        assert!(changed.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_with_request_id() -> Result<()> {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let console_lines = lines.clone();
+        let quickjs = QuickJSBuilder::new()
+            .with_live_console(Arc::new(move |_, line, request_id| {
+                console_lines
+                    .lock()
+                    .unwrap()
+                    .push((line.to_string(), request_id.map(str::to_string)));
+            }))
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute_with_request_id("console.log('start'); 1", None, "42")?,
+            Some("1".to_string())
+        );
+        quickjs.try_execute("console.log('anonymous')", None)?;
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![
+                ("start".to_string(), Some("42".to_string())),
+                ("anonymous".to_string(), None)
+            ]
+        );
+
+        let err = quickjs
+            .try_execute_with_request_id("throw new Error('failed')", None, "43")
+            .unwrap_err();
+        assert!(err.to_string().starts_with("request 43"));
+        assert!(format!("{err:?}").contains("failed"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_diagnose() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+
+        assert!(quickjs.diagnose("'quickjs' + data")?.is_empty());
+
+        let diagnostics = quickjs.diagnose("const a = 1;\n'quickjs' +")?;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, "SyntaxError");
+        assert_eq!(diagnostics[0].line, Some(2));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_gc() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_memory_limit(4194304)
+            .with_gc_threshold(65536)
+            .build()?;
+
+        // each chunk allocates unreachable cycles which are only freed by the garbage collector
+        let script = r#"
+            for (let chunk = 0; chunk < 10; chunk++) {
+                for (let i = 0; i < 1000; i++) {
+                    const a = {};
+                    const b = { a };
+                    a.b = b;
+                }
+                gc();
+            }
+            'quickjs' + 'wasm'
+        "#;
+
+        let result = quickjs.try_execute(script, None)?;
+
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+
+        // with automatic collection effectively disabled, collecting each chunk's cycles lets later chunks reuse
+        // their memory so the linear memory grows far less than when every cycle is kept until the end
+        let quickjs = QuickJSBuilder::new().with_gc_threshold(u32::MAX).build()?;
+        let script = |collect: bool| {
+            format!(
+                r#"
+                for (let chunk = 0; chunk < 10; chunk++) {{
+                    for (let i = 0; i < 10000; i++) {{
+                        const a = {{}};
+                        const b = {{ a }};
+                        a.b = b;
+                    }}
+                    if ({collect}) gc();
+                }}
+                "#
+            )
+        };
+        let collected = quickjs.try_execute_detailed(&script(true), None)?;
+        let uncollected = quickjs.try_execute_detailed(&script(false), None)?;
+        assert!(
+            collected.stats.peak_memory < uncollected.stats.peak_memory,
+            "{} >= {}",
+            collected.stats.peak_memory,
+            uncollected.stats.peak_memory
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_deterministic() -> Result<()> {
+        let script = r#"
+            [Date.now(), new Date().toISOString(), Math.random(), [...crypto.getRandomValues(new Uint8Array(4))]]
+        "#;
+
+        let first = QuickJSBuilder::deterministic()
+            .build()?
+            .try_execute(script, None)?;
+        let second = QuickJSBuilder::deterministic()
+            .build()?
+            .try_execute(script, None)?;
+        assert_eq!(first, second);
+        assert!(first
+            .unwrap()
+            .starts_with(r#"[0,"1970-01-01T00:00:00.000Z","#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_virtual_clock() -> Result<()> {
+        let clock = VirtualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_000_000));
+        let quickjs = QuickJSBuilder::new()
+            .with_virtual_clock(clock.clone())
+            .build()?;
+
+        let script = r#"
+            Date.now()
+        "#;
+
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some("1000000000".to_string())
+        );
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some("1000001000".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_memory_limit_normal() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_memory_limit(4194304).build()?;
+
+        let script = r#"
+            'quickjs' + 'wasm'
+        "#;
+
+        let result = quickjs.try_execute(script, None).unwrap();
+
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_max_string_length() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_max_string_length(10).build()?;
+
+        assert_eq!(
+            quickjs.try_execute("'ab'.repeat(5) + 'c'.padEnd(10, '-')", None)?,
+            Some(r#""ababababab"#.to_string() + r#"c---------""#)
+        );
+        for script in [
+            "'ab'.repeat(6)",
+            "'a'.padStart(11)",
+            "'abcdef'.concat('ghijk')",
+            "new Array(12).join('x')",
+        ] {
+            let err = quickjs.try_execute(script, None).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("RangeError: string length exceeds the maximum of 10"),
+                "{script}: {err}"
+            );
+        }
+        assert_eq!(
+            quickjs.try_execute(
+                "try { 'x'.repeat(1e9) } catch (err) { err instanceof RangeError }",
+                None
+            )?,
+            Some("true".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_max_array_length() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_max_array_length(3).build()?;
+
+        assert_eq!(
+            quickjs.try_execute(
+                "const a = new Array(2).fill(0); a.push(1); [a, Array.of(1, 2), [] instanceof Array, [].constructor === Array]",
+                None
+            )?,
+            Some("[[0,0,1],[1,2],true,true]".to_string())
+        );
+        for script in [
+            "new Array(1e9).fill(0)",
+            "[].constructor(4).fill(0)",
+            "Array.from({ length: 4 })",
+            "[1, 2, 3].push(4)",
+            "[1, 2].concat([3, 4])",
+        ] {
+            let err = quickjs.try_execute(script, None).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("RangeError: array length exceeds the maximum of 3"),
+                "{script}: {err}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn profile_memory() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_memory_limit(4194304).build()?;
+        let script = "new Array(data.length).fill('quickjs').join('')";
+        let data = format!("{{\"length\":{}}}", 1024 * 1024);
+
+        assert!(quickjs.try_execute(script, Some(&data)).is_err());
+        let peak_memory = quickjs.profile_memory(script, Some(&data))?;
+        assert!(peak_memory > 4194304);
+
+        let quickjs = QuickJSBuilder::new()
+            .with_memory_limit(peak_memory as u32)
+            .build()?;
+        assert!(quickjs.try_execute(script, Some(&data))?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn effective_memory_limit_is_page_aligned() -> Result<()> {
+        assert_eq!(effective_memory_limit(4194304), 4194304);
+        assert_eq!(
+            effective_memory_limit(4194305),
+            4194304 + PAGE_SIZE as usize
+        );
+        assert_eq!(effective_memory_limit(1), PAGE_SIZE as usize);
+        assert_eq!(effective_memory_limit(u32::MAX), u32::MAX as usize + 1);
+
+        let quickjs = QuickJSBuilder::new().with_memory_limit(4194305).build()?;
+        assert_eq!(quickjs.try_execute("1 + 1", None)?, Some("2".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_memory_limit_exceed() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_memory_limit(4194304).build()?;
+
+        let script = r#"
+            let memory = [];
+            while (true) {
+                memory.push("allocate");
+            }
+        "#;
+
+        match quickjs.try_execute(script, None) {
+            Err(err) if err.to_string().contains("out of memory") => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_js_heap_limit() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_memory_limit(16777216)
+            .with_js_heap_limit(4194304)
+            .build()?;
+
+        let script = r#"
+            try {
+                let memory = [];
+                while (true) {
+                    memory.push("allocate");
+                }
+            } catch (err) {
+                "caught";
+            }
+        "#;
+
+        assert_eq!(
+            quickjs.try_execute(script, None)?,
+            Some("\"caught\"".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_memory_threshold_callback() -> Result<()> {
+        let crossed = Arc::new(Mutex::new(Vec::new()));
+        let callback_crossed = crossed.clone();
+        let quickjs = QuickJSBuilder::new()
+            .with_memory_limit(16777216)
+            .with_memory_threshold_callback(
+                [0.9, 0.5],
+                Arc::new(move |threshold, size| {
+                    callback_crossed.lock().unwrap().push(threshold);
+                    assert!(size as f64 >= threshold * 16777216.0);
+                }),
+            )
+            .build()?;
+
+        assert!(quickjs.try_execute("'quickjs'", None).is_ok());
+        assert!(crossed.lock().unwrap().is_empty());
+
+        let script = r#"
+            let memory = [];
+            while (true) {
+                memory.push(new ArrayBuffer(65536));
+            }
+        "#;
+        assert!(quickjs.try_execute(script, None).is_err());
+        assert_eq!(*crossed.lock().unwrap(), vec![0.5, 0.9]);
+
+        assert!(QuickJSBuilder::new()
+            .with_memory_threshold_callback([0.5], Arc::new(|_, _| {}))
+            .build()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_cancellable() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_interruptible(true).build()?;
+
+        let script = r#"
+            while (true) {}
+        "#;
+
+        let cancellation = CancellationToken::new();
+        let cancellation_clone = cancellation.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            cancellation_clone.cancel();
+        });
+
+        match quickjs.try_execute_cancellable(script, None, &cancellation) {
+            Err(err)
+                if matches!(
+                    err.root_cause().downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::Cancelled)
+                ) => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_interrupt_fuel_interval() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_interrupt_fuel_interval(10_000)
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute("'quickjs'", None)?,
+            Some("\"quickjs\"".to_string())
+        );
+
+        let cancellation = CancellationToken::new();
+        let cancellation_clone = cancellation.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            cancellation_clone.cancel();
+        });
+
+        match quickjs.try_execute_cancellable("while (true) {}", None, &cancellation) {
+            Err(err)
+                if matches!(
+                    err.root_cause().downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::Cancelled)
+                ) => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_fuel_limit() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_fuel_limit(100_000_000).build()?;
+
+        assert_eq!(
+            quickjs.try_execute("'quickjs'", None)?,
+            Some("\"quickjs\"".to_string())
+        );
+
+        match quickjs.try_execute("while (true) {}", None) {
+            Err(err)
+                if matches!(
+                    err.root_cause().downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::FuelExhausted(100_000_000))
+                ) => {}
+            other => panic!("{:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn prepare() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+        let prepared = quickjs.prepare("data.a + data.b")?;
+
+        assert_eq!(
+            prepared.apply(Some(r#"{"a":1,"b":2}"#))?,
+            Some("3".to_string())
+        );
+        assert_eq!(
+            prepared.apply(Some(r#"{"a":3,"b":4}"#))?,
+            Some("7".to_string())
+        );
+
+        let outputs = prepared.apply_all(&[r#"{"a":5,"b":6}"#, r#"{"a":"x","b":"y"}"#, "invalid"]);
+        assert_eq!(outputs[0].as_ref().ok(), Some(&Some("11".to_string())));
+        assert_eq!(outputs[1].as_ref().ok(), Some(&Some(r#""xy""#.to_string())));
+        assert!(outputs[2].is_err());
+
+        assert!(quickjs.prepare("data.").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_with_stats() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_fuel_limit(100_000_000).build()?;
+
+        let report = quickjs.try_execute_with_stats("'quickjs'", None);
+        assert_eq!(report.result?, Some("\"quickjs\"".to_string()));
+        assert!(report
+            .stats
+            .fuel_consumed
+            .is_some_and(|fuel| fuel < 100_000_000));
+
+        let report = quickjs.try_execute_with_stats("while (true) {}", None);
+        match report.result {
+            Err(err)
+                if matches!(
+                    err.root_cause().downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::FuelExhausted(100_000_000))
+                ) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(report.stats.fuel_consumed, Some(100_000_000));
+        assert!(report.stats.peak_memory > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_max_concurrency() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_max_concurrency(1)
+            .with_fail_at_capacity(true)
+            .with_interruptible(true)
+            .build()?;
+
+        let cancellation = CancellationToken::new();
+        let running = quickjs.clone();
+        let token = cancellation.clone();
+        let handle =
+            thread::spawn(move || running.try_execute_cancellable("while (true) {}", None, &token));
+        thread::sleep(Duration::from_millis(200));
+
+        let err = quickjs.try_execute("1", None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<QuickJSError>(),
+            Some(QuickJSError::AtCapacity(1))
+        ));
+
+        cancellation.cancel();
+        assert!(handle.join().unwrap().is_err());
+        assert_eq!(quickjs.try_execute("1", None)?, Some("1".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_shutdown() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_interruptible(true).build()?;
+
+        let handles = (0..2)
+            .map(|_| {
+                let quickjs = quickjs.clone();
+                std::thread::spawn(move || quickjs.try_execute("while (true) {}", None))
+            })
+            .collect::<Vec<_>>();
+        std::thread::sleep(Duration::from_millis(100));
+        quickjs.shutdown();
+
+        for handle in handles {
+            match handle.join().unwrap() {
+                Err(err)
+                    if matches!(
+                        err.root_cause().downcast_ref::<QuickJSError>(),
+                        Some(QuickJSError::Shutdown)
+                    ) => {}
+                other => panic!("{:?}", other),
+            }
+        }
+        assert!(matches!(
+            quickjs
+                .try_execute("'quickjs'", None)
+                .unwrap_err()
+                .downcast_ref::<QuickJSError>(),
+            Some(QuickJSError::Shutdown)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_abort_signal() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_interruptible(true)
+            .with_cancellation_grace(Duration::from_secs(5))
+            .build()?;
+
+        let cancellation = CancellationToken::new();
+        let cancel = cancellation.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            cancel.cancel();
+        });
+
+        let script = r#"
+            const controller = new AbortController();
+            let iterations = 0;
+            while (!controller.signal.aborted) {
+                iterations++;
+            }
+            ({ aborted: controller.signal.aborted, reason: controller.signal.reason.message })
+        "#;
+
+        assert_eq!(
+            quickjs.try_execute_cancellable(script, None, &cancellation)?,
+            Some(r#"{"aborted":true,"reason":"execution cancelled"}"#.to_string())
+        );
+
+        let script = r#"
+            const controller = new AbortController();
+            controller.abort();
+            controller.signal.aborted
+        "#;
+        assert_eq!(quickjs.try_execute(script, None)?, Some("true".to_string()));
+
+        Ok(())
+    }
 
-        // Optionally convert the data string to a byte vector and set its default value if it's not provided
-        let data = data
-            .map(|data| data.as_bytes().to_vec())
-            .unwrap_or_default();
+    #[test]
+    fn try_execute_cancellable_requires_interruptible() -> Result<()> {
+        let quickjs = QuickJS::new()?;
 
-        // Get the size of the data as an i32 (for WASI API calls)
-        let data_size = data.len() as i32;
+        assert!(quickjs
+            .try_execute_cancellable("'quickjs'", None, &CancellationToken::new())
+            .is_err());
 
-        // Create a new linker for the engine
-        let mut linker = Linker::new(&self.engine);
+        Ok(())
+    }
 
-        // Add the WASI library to the linker
-        wasi_common::sync::add_to_linker(&mut linker, |state: &mut State| &mut state.wasi)?;
+    #[test]
+    fn try_execute_time_limit() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_time_limit(
+                TimeLimit::new(Duration::from_secs(2))
+                    .with_evaluation_interval(Duration::from_millis(100)),
+            )
+            .build()?;
 
-        // Build a new WASI context builder
-        let mut wasi_ctx_builder = WasiCtxBuilder::new();
+        let script = r#"
+            function sleep(milliseconds) {
+                const startDate = Date.now();
+                let currentDate = Date.now();
+                do {
+                    currentDate = Date.now();
+                } while (currentDate - startDate < milliseconds);
+            }
+            sleep(30000);
+        "#;
 
-        // Inherit stdout if requested by the user
-        if self.inherit_stdout {
-            wasi_ctx_builder.inherit_stdout();
-        };
+        match quickjs.try_execute(script, None) {
+            Err(err) if err.root_cause().to_string().contains("exceeds time limit") => {}
+            other => panic!("{:?}", other),
+        }
 
-        // Inherit stderr if requested by the user
-        if self.inherit_stderr {
-            wasi_ctx_builder.inherit_stderr();
-        };
+        Ok(())
+    }
 
-        // Build the WASI context with the provided options
-        let wasi = wasi_ctx_builder.build();
+    #[test]
+    fn try_execute_time_limit_continue_epochs() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_time_limit(
+                TimeLimit::new(Duration::from_millis(500))
+                    .with_evaluation_interval(Duration::from_millis(10))
+                    .with_continue_epochs(10),
+            )
+            .build()?;
 
-        // Determine memory type and limits based on self.memory_limit.
-        let (memory_type, limits) = match self.memory_limit {
-            // If self.memory_limit is Some, calculate memory type and limits based on PAGE_SIZE.
-            Some(memory_limit) => (
-                MemoryType::new(memory_limit / PAGE_SIZE, Some(memory_limit / PAGE_SIZE)),
-                StoreLimitsBuilder::new()
-                    .instances(1)
-                    .memory_size(memory_limit as usize)
-                    .build(),
-            ),
-            // If self.memory_limit is None, use default values for memory type and limits.
-            None => (
-                MemoryType::new(1, None),
-                StoreLimitsBuilder::new().instances(1).build(),
-            ),
-        };
+        assert_eq!(quickjs.try_execute("1 + 1", None)?, Some("2".to_string()));
+        match quickjs.try_execute("while (true) {}", None) {
+            Err(err) if err.root_cause().to_string().contains("exceeds time limit") => {}
+            other => panic!("{:?}", other),
+        }
 
-        // Create a new store instance with the engine and initial state.
-        let mut store = Store::new(&self.engine, State { wasi, limits });
-
-        // Set the limiter for the store to access its limits.
-        store.limiter(move |state| &mut state.limits);
-
-        // If self.time_limit is Some, set up a thread to increment the epoch at regular intervals.
-        if let Some(time_limit) = &self.time_limit {
-            // Calculate evaluation interval from time limit.
-            let evaluation_interval = time_limit.evaluation_interval;
-            // Clone engine instance for use in separate thread.
-            let engine_clone = self.engine.clone();
-            // Start new thread to increment epoch every evaluation interval.
-            thread::spawn(move || loop {
-                thread::sleep(evaluation_interval);
-                engine_clone.increment_epoch();
-            });
+        Ok(())
+    }
 
-            // Calculate initial epoch limit from time limit.
-            let mut epoch_limit = u32::try_from(
-                time_limit.limit.as_micros() / time_limit.evaluation_interval.as_micros(),
-            )?;
+    #[test]
+    fn try_execute_stack_on_timeout() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_time_limit(
+                TimeLimit::new(Duration::from_millis(200))
+                    .with_evaluation_interval(Duration::from_millis(10)),
+            )
+            .with_stack_on_timeout(true)
+            .build()?;
 
-            // Set up callback for when the epoch deadline is reached.
-            store.epoch_deadline_callback(move |_| {
-                // If epoch limit reaches 0, return error.
-                if epoch_limit == 0 {
-                    bail!("exceeds time limit");
-                }
-                // Decrement epoch limit and continue evaluation.
-                epoch_limit -= 1;
-                Ok(UpdateDeadline::Continue(1))
-            });
+        let script = r#"
+            function spin() {
+                while (true) {}
+            }
+            function process() {
+                spin();
+            }
+            process();
+        "#;
 
-            // Set initial epoch deadline to 1.
-            store.set_epoch_deadline(1);
+        match quickjs.try_execute(script, None) {
+            Err(err) => match err.downcast_ref::<QuickJSError>() {
+                Some(QuickJSError::TimeLimitExceeded { stack: Some(stack) }) => {
+                    let frames = stack.lines().collect::<Vec<_>>();
+                    assert!(frames[0].contains("spin"), "{stack}");
+                    assert!(frames[1].contains("process"), "{stack}");
+                }
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
         }
+        assert_eq!(quickjs.try_execute("1 + 1", None)?, Some("2".to_string()));
 
-        // Create new memory instance with the store and calculated memory type.
-        Memory::new(&mut store, memory_type)?;
+        Ok(())
+    }
 
-        // Wraps the host function to retrieve the size of the script.
-        // This function is exposed as `get_script_size` in the JavaScript context.
-        linker.func_wrap(
-            "host",
-            "get_script_size",
-            move |_: Caller<'_, State>| -> Result<i32> { Ok(script_size) },
-        )?;
+    #[test]
+    fn try_execute_time_limit_idle() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_time_limit(
+                TimeLimit::new(Duration::from_millis(300))
+                    .with_evaluation_interval(Duration::from_millis(10))
+                    .with_idle(true),
+            )
+            .build()?;
 
-        // Wraps the host function to retrieve the script data.
-        // This function is exposed as `get_script` in the JavaScript context.
-        linker.func_wrap(
-            "host",
-            "get_script",
-            move |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
-                // The memory export from the host environment.
-                let memory = match caller.get_export("memory") {
-                    Some(Extern::Memory(memory)) => memory,
-                    _ => return Err(anyhow!("failed to find host memory")),
-                };
+        // runs for longer than the limit but emits a value every 100ms
+        let script = r#"
+            for (let i = 0; i < 10; i++) {
+                const start = Date.now();
+                while (Date.now() - start < 100) {}
+                emit(i);
+            }
+        "#;
+        assert_eq!(quickjs.try_execute_many(script, None)?.len(), 10);
 
-                // The offset in bytes at which to write the script data.
-                let offset = ptr as u32 as usize;
+        match quickjs.try_execute("while (true) {}", None) {
+            Err(err) if err.root_cause().to_string().contains("exceeds time limit") => {}
+            other => panic!("{:?}", other),
+        }
 
-                Ok(memory.write(&mut caller, offset, &script)?)
-            },
-        )?;
+        Ok(())
+    }
 
-        // Wraps the host function to retrieve the size of the input data.
-        // This function is exposed as `get_data_size` in the JavaScript context.
-        linker.func_wrap(
-            "host",
-            "get_data_size",
-            move |_: Caller<'_, State>| -> Result<i32> { Ok(data_size) },
-        )?;
+    #[test]
+    fn try_execute_bundle() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+        let path = std::env::temp_dir().join("quickjs_bundle.qjsb");
 
-        // Wraps the host function to retrieve the input data.
-        // This function is exposed as `get_data` in the JavaScript context.
-        linker.func_wrap(
-            "host",
-            "get_data",
-            move |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
-                // The memory export from the host environment.
-                let memory = match caller.get_export("memory") {
-                    Some(Extern::Memory(memory)) => memory,
-                    _ => return Err(anyhow!("failed to find host memory")),
-                };
+        let bundle = Bundle::new("data.a + 1", Some(r#"{"a":1}"#));
+        assert_eq!(
+            bundle.to_bytes(),
+            b"quickjs-bundle 1\n10 7\ndata.a + 1{\"a\":1}".to_vec()
+        );
+        assert_eq!(Bundle::from_bytes(&bundle.to_bytes())?, bundle);
+        std::fs::write(&path, bundle.to_bytes())?;
+        assert_eq!(quickjs.try_execute_bundle(&path)?, Some("2".to_string()));
 
-                // The offset in bytes at which to write the input data.
-                let offset = ptr as u32 as usize;
+        std::fs::write(&path, Bundle::new("typeof data", None::<String>).to_bytes())?;
+        assert_eq!(
+            quickjs.try_execute_bundle(&path)?,
+            Some(r#""undefined""#.to_string())
+        );
 
-                Ok(memory.write(&mut caller, offset, &data)?)
-            },
-        )?;
+        std::fs::write(&path, b"quickjs-bundle 1\n10 7\ndata.a + 1")?;
+        let err = quickjs.try_execute_bundle(&path).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("bundle body is 10 bytes but the header declares 10 + 7")
+        );
+        assert!(Bundle::from_bytes(b"data.a + 1").is_err());
+        assert!(quickjs.try_execute_bundle("missing.qjsb").is_err());
+
+        Ok(())
+    }
 
-        // A simulated one-shot channel to wait for the script to complete and retrieve the result.
-        let (sender, receiver) = sync_channel(1);
+    #[test]
+    fn try_execute_encoded() -> Result<()> {
+        let script = b"'caf\xe9'.length";
 
-        // Wraps the host function to retrieve the output data from the host memory.
-        // This function is exposed as `set_output` in the JavaScript context.
-        linker.func_wrap(
-            "host",
-            "set_output",
-            move |mut caller: Caller<'_, State>,
-                  ptr: i32,
-                  capacity: i32,
-                  error: i32|
-                  -> Result<()> {
-                // Check for invalid capacity
-                if capacity == 0 {
-                    // If the capacity is zero, send None to the guest.
-                    sender.send(None).unwrap();
-                } else {
-                    // Get the host memory object from the caller's exports.
-                    let memory = match caller.get_export("memory") {
-                        Some(Extern::Memory(memory)) => Ok(memory),
-                        _ => Err(anyhow!("failed to find host memory")),
-                    }?;
+        let err = QuickJS::new()?
+            .try_execute_encoded(script, None)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("script is not valid UTF-8"));
+        assert_eq!(
+            QuickJS::new()?.try_execute_encoded("'café'".as_bytes(), None)?,
+            Some(r#""café""#.to_string())
+        );
+
+        let quickjs = QuickJSBuilder::new()
+            .with_source_encoding(SourceEncoding::Latin1)
+            .build()?;
+        assert_eq!(
+            quickjs.try_execute_encoded(script, None)?,
+            Some("4".to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute_encoded(b"'caf\xe9'", None)?,
+            Some(r#""café""#.to_string())
+        );
 
-                    // Calculate the offset of the string in host memory.
-                    let offset = ptr as u32 as usize;
+        Ok(())
+    }
 
-                    // Allocate a buffer to store the read string.
-                    let mut buffer: Vec<u8> = vec![0; capacity as usize];
+    #[cfg(feature = "testing")]
+    #[test]
+    fn testing_assert_output() {
+        testing::assert_output(
+            "({ now: Date.now(), total: data.reduce((a, b) => a + b) })",
+            Some("[1, 2, 3]"),
+            r#"{ "total": 6, "now": 946684800000 }"#,
+        );
+        testing::assert_output("undefined", None, "undefined");
 
-                    // Read the string from host memory into the buffer.
-                    memory.read(&caller, offset, &mut buffer)?;
+        let panic = std::panic::catch_unwind(|| {
+            testing::assert_output("({ a: 1, b: 2 })", None, r#"{ "a": 1, "b": 3 }"#)
+        })
+        .unwrap_err();
+        assert_eq!(
+            panic.downcast_ref::<String>().map(String::as_str),
+            Some("script output does not match (- expected, + actual):\n  {\n    \"a\": 1,\n-   \"b\": 3\n+   \"b\": 2\n  }")
+        );
+    }
 
-                    // Convert the buffer to a string and try to parse it as UTF-8.
-                    let result = String::from_utf8(buffer)?;
+    #[test]
+    fn try_execute_transcoded() -> Result<()> {
+        /// Transcodes `key=value` lines to and from an object of strings.
+        struct Properties;
 
-                    // If an error occurred while reading the string, send the error back to the guest; otherwise, send the read string back.
-                    if error == 0 {
-                        sender.send(Some(Ok(result))).unwrap();
-                    } else {
-                        sender.send(Some(Err(anyhow!(result)))).unwrap();
-                    };
+        impl Transcoder for Properties {
+            fn decode(&self, bytes: &[u8]) -> Result<serde_json::Value> {
+                std::str::from_utf8(bytes)?
+                    .lines()
+                    .map(|line| match line.split_once('=') {
+                        Some((key, value)) => Ok((key.to_string(), value.into())),
+                        None => bail!("line `{line}` is not a property"),
+                    })
+                    .collect()
+            }
+
+            fn encode(&self, value: serde_json::Value) -> Result<Vec<u8>> {
+                let Some(properties) = value.as_object() else {
+                    bail!("output is not an object");
                 };
+                Ok(properties
+                    .iter()
+                    .map(|(key, value)| format!("{key}={}\n", value.as_str().unwrap_or_default()))
+                    .collect::<String>()
+                    .into_bytes())
+            }
+        }
 
-                Ok(())
-            },
-        )?;
+        let quickjs = QuickJSBuilder::new()
+            .with_transcoder(Arc::new(Properties))
+            .build()?;
 
-        // Create a new module in the store with an empty name and link it to our current module.
-        linker.module(&mut store, "", &self.module)?;
+        assert_eq!(
+            quickjs.try_execute_transcoded(
+                "({ greeting: `hello ${data.name}`, engine: 'quickjs' })",
+                Some(b"name=wasm")
+            )?,
+            Some(b"engine=quickjs\ngreeting=hello wasm\n".to_vec())
+        );
 
-        // Call the module's default entrypoint.
-        linker
-            .get_default(&mut store, "")?
-            .typed::<(), ()>(&store)?
-            .call(&mut store, ())?;
+        let err = quickjs
+            .try_execute_transcoded("data", Some(b"name"))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("line `name` is not a property"));
+        assert!(quickjs.try_execute_transcoded("[1]", None).is_err());
 
-        // Receive any message that was sent to this module and return it (if anything was sent)
-        receiver.recv()?.transpose()
-    }
-}
+        let err = QuickJS::new()?
+            .try_execute_transcoded("1", None)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no transcoder is set");
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        Ok(())
+    }
 
     #[test]
-    fn try_execute() -> Result<()> {
-        let quickjs = QuickJSBuilder::new().build()?;
+    fn try_execute_big_integers() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_big_integers(true).build()?;
+
+        let data =
+            r#"{"id":12345678901234567890,"count":3,"label":"99999999999999999999","price":1.5}"#;
+        assert_eq!(
+            quickjs.try_execute(
+                "[typeof data.id, typeof data.count, data.label, data.price, data.id + 1n]",
+                Some(data)
+            )?,
+            Some(
+                r#"["bigint","number","99999999999999999999",1.5,12345678901234567891]"#
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            quickjs.try_execute("data", Some(data))?,
+            Some(data.to_string())
+        );
+        assert_eq!(
+            quickjs.try_execute_many("emit(-(2n ** 64n)); emit(1)", None)?,
+            vec!["-18446744073709551616", "1"]
+        );
 
+        // only a whole string of the marker followed by an integer is written as an integer
         let script = r#"
-            'quickjs' + 'wasm'
+            const marker = "\u0000bigint:";
+            ({ [marker + "1"]: [`x"${marker}1`, marker + "--", marker + "01", 2n ** 60n] })
         "#;
+        let output = quickjs.try_execute(script, None)?.unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&output)?,
+            serde_json::json!({
+                "\u{0}bigint:1": ["x\"\u{0}bigint:1", "\u{0}bigint:--", "\u{0}bigint:01", 1152921504606846976u64]
+            })
+        );
 
-        let result = quickjs.try_execute(script, None).unwrap();
-
-        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+        let quickjs = QuickJS::new()?;
+        assert_eq!(
+            quickjs.try_execute("typeof data.id", Some(data))?,
+            Some(r#""number""#.to_string())
+        );
 
         Ok(())
     }
 
     #[test]
-    fn try_execute_data() -> Result<()> {
-        let quickjs = QuickJSBuilder::new().build()?;
+    fn try_execute_max_output_bytes() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_max_output_bytes(16).build()?;
+        assert_eq!(
+            quickjs.try_execute("[1, 2, 3]", None)?,
+            Some("[1,2,3]".to_string())
+        );
+        let err = quickjs
+            .try_execute("({ name: 'quickjs', runtime: 'wasm' })", None)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<QuickJSError>(),
+            Some(QuickJSError::OutputTooLarge {
+                size: 35,
+                limit: 16
+            })
+        ));
 
-        let script = r#"
-            'quickjs' + data.input
-        "#;
+        let quickjs = QuickJSBuilder::new()
+            .with_max_output_bytes(16)
+            .with_truncate_output(true)
+            .build()?;
+        let result = quickjs.try_execute_detailed("[{ b: 1, a: 2 }, { b: 3, a: 4 }]", None)?;
+        assert_eq!(result.output, Some(r#"[{"b":1,"a":2}]"#.to_string()));
+        assert!(result.truncated);
+        let result = quickjs.try_execute_detailed("'quickjs runtime in wasm'", None)?;
+        assert_eq!(result.output, Some(r#""quickjs runtim""#.to_string()));
+        assert!(result.truncated);
+        let result = quickjs.try_execute_detailed("'quickjs'", None)?;
+        assert_eq!(result.output, Some(r#""quickjs""#.to_string()));
+        assert!(!result.truncated);
+        assert!(quickjs
+            .try_execute("({ name: 'quickjs', runtime: 'wasm' })", None)
+            .is_err());
 
-        let data = r#"{"input": "wasm"}"#;
+        Ok(())
+    }
 
-        let result = quickjs.try_execute(script, Some(data)).unwrap();
+    #[test]
+    fn try_execute_limit_status() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_time_limit(
+                TimeLimit::new(Duration::from_millis(500))
+                    .with_evaluation_interval(Duration::from_millis(10)),
+            )
+            .build()?;
 
-        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+        let result = quickjs.try_execute_detailed("1", None)?;
+        assert_eq!(result.limit_status, LimitStatus::None);
+
+        // Epochs are counted against the wall clock so nearing the time limit is asserted on the usage alone rather
+        // than by running a script for most of the limit.
+        assert_eq!(LimitStatus::from_usage(None, None), LimitStatus::None);
+        assert_eq!(LimitStatus::from_usage(None, Some(0.89)), LimitStatus::None);
+        assert_eq!(
+            LimitStatus::from_usage(None, Some(0.9)),
+            LimitStatus::NearTime
+        );
+        assert_eq!(
+            LimitStatus::from_usage(Some(0.95), Some(0.5)),
+            LimitStatus::NearMemory
+        );
+        assert_eq!(
+            LimitStatus::from_usage(Some(0.92), Some(0.97)),
+            LimitStatus::NearTime
+        );
 
         Ok(())
     }
 
     #[test]
-    fn try_throw_error() -> Result<()> {
-        let quickjs = QuickJSBuilder::new().build()?;
+    fn try_execute_with_hard_timeout() -> Result<()> {
+        let quickjs = QuickJSBuilder::new()
+            .with_time_limit(TimeLimit::new(Duration::from_secs(2)))
+            .build()?;
+
+        assert_eq!(
+            quickjs.try_execute_with_hard_timeout("'quickjs'", None, Duration::from_secs(2))?,
+            Some("\"quickjs\"".to_string())
+        );
 
         let script = r#"
-            throw new Error('myerror');
+            while (true) {}
         "#;
 
-        match quickjs.try_execute(script, None) {
-            Err(err) if err.to_string().contains("Uncaught Error: myerror") => {}
+        match quickjs.try_execute_with_hard_timeout(script, None, Duration::from_millis(100)) {
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::HardTimeout(_))
+                ) => {}
             other => panic!("{:?}", other),
         }
 
@@ -469,33 +6829,53 @@ mod tests {
     }
 
     #[test]
-    fn try_execute_memory_limit_normal() -> Result<()> {
-        let quickjs = QuickJSBuilder::new().with_memory_limit(4194304).build()?;
-
-        let script = r#"
-            'quickjs' + 'wasm'
+    fn try_execute_guarded() -> Result<()> {
+        let quickjs = QuickJS::new()?;
+        let guard = r#"
+            function isOrder(order) { return Array.isArray(order.items) && order.items.length > 0 }
+            isOrder(data)
         "#;
 
-        let result = quickjs.try_execute(script, None).unwrap();
-
-        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+        assert_eq!(
+            quickjs.try_execute_guarded(
+                guard,
+                "isOrder(data) && data.items.length",
+                Some(r#"{"items":[1,2]}"#)
+            )?,
+            Some("2".to_string())
+        );
 
-        Ok(())
-    }
+        match quickjs.try_execute_guarded(guard, "data.items.length", Some(r#"{"items":[]}"#)) {
+            Err(err) => assert_eq!(
+                err.downcast_ref::<QuickJSError>().map(ToString::to_string),
+                Some("guard rejected: guard returned false".to_string())
+            ),
+            other => panic!("{:?}", other),
+        }
 
-    #[test]
-    fn try_execute_memory_limit_exceed() -> Result<()> {
-        let quickjs = QuickJSBuilder::new().with_memory_limit(4194304).build()?;
+        match quickjs.try_execute_guarded(
+            "if (!data.id) throw new Error('id is required'); true",
+            "data.id",
+            Some("{}"),
+        ) {
+            Err(err) => match err.downcast_ref::<QuickJSError>() {
+                Some(QuickJSError::GuardRejected(reason)) => {
+                    assert!(reason.contains("id is required"))
+                }
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
 
-        let script = r#"
-            let memory = [];
-            while (true) {
-                memory.push("allocate");
+        // exhausting the stack fails the execution rather than being a rejection
+        match quickjs.try_execute_guarded("function f() { f() } f()", "data", None) {
+            Err(err) => {
+                assert!(!matches!(
+                    err.downcast_ref::<QuickJSError>(),
+                    Some(QuickJSError::GuardRejected(_))
+                ));
+                assert!(err.to_string().contains("stack overflow"), "{err}");
             }
-        "#;
-
-        match quickjs.try_execute(script, None) {
-            Err(err) if err.to_string().contains("out of memory") => {}
             other => panic!("{:?}", other),
         }
 
@@ -503,26 +6883,96 @@ mod tests {
     }
 
     #[test]
-    fn try_execute_time_limit() -> Result<()> {
+    fn try_execute_with_options() -> Result<()> {
         let quickjs = QuickJSBuilder::new()
-            .with_time_limit(
-                TimeLimit::new(Duration::from_secs(2))
-                    .with_evaluation_interval(Duration::from_millis(100)),
+            .with_host_function(
+                "add",
+                Arc::new(|args: &[serde_json::Value]| -> Result<serde_json::Value> {
+                    Ok(args
+                        .iter()
+                        .filter_map(serde_json::Value::as_i64)
+                        .sum::<i64>()
+                        .into())
+                }),
+            )
+            .with_host_function(
+                "secret",
+                Arc::new(|_: &[serde_json::Value]| -> Result<serde_json::Value> {
+                    Ok("hunter2".into())
+                }),
             )
             .build()?;
 
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let console_lines = lines.clone();
+        let options = ExecuteOptions::new()
+            .with_guard("Array.isArray(data)")
+            .with_request_id("42")
+            .with_capabilities(&["add"])
+            .with_console(Arc::new(move |_, line, request_id| {
+                console_lines
+                    .lock()
+                    .unwrap()
+                    .push((line.to_string(), request_id.map(str::to_string)));
+            }));
+
+        let output = quickjs.try_execute_with_options(
+            "console.log(typeof secret); add(...data)",
+            Some("[1,2]"),
+            &options,
+        )?;
+        assert_eq!(output.output, Some("3".to_string()));
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![("undefined".to_string(), Some("42".to_string()))]
+        );
+
+        let err = quickjs
+            .try_execute_with_options("add(1, 2)", Some("{}"), &options)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("request 42"));
+        assert!(matches!(
+            err.downcast_ref::<QuickJSError>(),
+            Some(QuickJSError::GuardRejected(_))
+        ));
+
+        let err = quickjs
+            .try_execute_with_options(
+                "1",
+                None,
+                &ExecuteOptions::new().with_time_limit(TimeLimit::new(Duration::from_secs(1))),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a per-execution time limit requires a time limit or `with_interruptible`"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_execute_with_timeout() -> Result<()> {
+        let quickjs = QuickJSBuilder::new().with_interruptible(true).build()?;
+
+        assert_eq!(
+            quickjs.try_execute_with_timeout(
+                "'quickjs'",
+                None,
+                TimeLimit::new(Duration::from_secs(2))
+            )?,
+            Some("\"quickjs\"".to_string())
+        );
+
         let script = r#"
-            function sleep(milliseconds) {
-                const startDate = Date.now();
-                let currentDate = Date.now();
-                do {
-                    currentDate = Date.now();
-                } while (currentDate - startDate < milliseconds);
-            }
-            sleep(30000);
+            while (true) {}
         "#;
 
-        match quickjs.try_execute(script, None) {
+        match quickjs.try_execute_with_timeout(
+            script,
+            None,
+            TimeLimit::new(Duration::from_millis(200)),
+        ) {
             Err(err) if err.root_cause().to_string().contains("exceeds time limit") => {}
             other => panic!("{:?}", other),
         }
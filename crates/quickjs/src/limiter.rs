@@ -0,0 +1,96 @@
+use crate::{ExecuteStats, MemoryThresholdCallback};
+use anyhow::Result;
+use wasmtime::{ResourceLimiter, StoreLimits};
+
+/// A `ResourceLimiter` which enforces the store limits and tracks the peak memory of an execution.
+pub(crate) struct Limiter {
+    /// The memory and instance limits of the store.
+    limits: StoreLimits,
+    /// Statistics collected while the execution runs.
+    stats: ExecuteStats,
+    /// Optional thresholds of memory growth at which to notify a callback.
+    memory_thresholds: Option<MemoryThresholds>,
+}
+
+/// The thresholds of memory growth which have not been crossed yet and the callback to notify when they are.
+struct MemoryThresholds {
+    /// The memory limit in bytes which the thresholds are fractions of.
+    memory_limit: usize,
+    /// The fractions of the memory limit which have not been crossed yet, in ascending order.
+    pending: Vec<f64>,
+    callback: MemoryThresholdCallback,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: StoreLimits) -> Self {
+        Self {
+            limits,
+            stats: ExecuteStats::default(),
+            memory_thresholds: None,
+        }
+    }
+
+    /// Notifies `callback` when a memory grows past each of `thresholds`, which are fractions of `memory_limit` in
+    /// ascending order.
+    pub(crate) fn watch_memory(
+        &mut self,
+        memory_limit: usize,
+        thresholds: Vec<f64>,
+        callback: MemoryThresholdCallback,
+    ) {
+        self.memory_thresholds = Some(MemoryThresholds {
+            memory_limit,
+            pending: thresholds,
+            callback,
+        });
+    }
+
+    /// Returns the statistics collected so far.
+    pub(crate) fn stats(&self) -> ExecuteStats {
+        self.stats
+    }
+}
+
+impl ResourceLimiter for Limiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool> {
+        let allowed = self.limits.memory_growing(current, desired, maximum)?;
+        if allowed {
+            self.stats.peak_memory = self.stats.peak_memory.max(desired);
+
+            if let Some(memory_thresholds) = &mut self.memory_thresholds {
+                let crossed = memory_thresholds
+                    .pending
+                    .iter()
+                    .take_while(|threshold| {
+                        desired as f64 >= **threshold * memory_thresholds.memory_limit as f64
+                    })
+                    .count();
+                for threshold in memory_thresholds.pending.drain(..crossed) {
+                    (memory_thresholds.callback)(threshold, desired);
+                }
+            }
+        }
+        Ok(allowed)
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> Result<bool> {
+        self.limits.table_growing(current, desired, maximum)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories()
+    }
+}
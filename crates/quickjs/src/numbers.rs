@@ -0,0 +1,134 @@
+/// The prefix of the strings standing in for integers which JavaScript numbers cannot represent exactly. The guest
+/// binds these strings as `BigInt`s and encodes `BigInt`s in the output as them.
+const BIG_INTEGER_MARKER: &str = "\\u0000bigint:";
+
+/// The largest integer a JavaScript number represents exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: &str = "9007199254740991";
+
+/// Replaces the integer literals of the JSON `data` which are outside the safe integer range with marker strings,
+/// e.g. `12345678901234567890` becomes `"\u0000bigint:12345678901234567890"`, so they reach the guest exactly.
+///
+/// Numbers with a fraction or exponent are left as they are. Strings are copied verbatim.
+pub(crate) fn mark_big_integers(data: &[u8]) -> Vec<u8> {
+    let mut marked = Vec::with_capacity(data.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else if byte == b'"' {
+            in_string = true;
+        } else if byte == b'-' || byte.is_ascii_digit() {
+            let end = data[i..]
+                .iter()
+                .position(|byte| !matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+                .map_or(data.len(), |len| i + len);
+            let number = &data[i..end];
+            if is_big_integer(number) {
+                marked.push(b'"');
+                marked.extend_from_slice(BIG_INTEGER_MARKER.as_bytes());
+                marked.extend_from_slice(number);
+                marked.push(b'"');
+            } else {
+                marked.extend_from_slice(number);
+            }
+            i = end;
+            continue;
+        }
+        marked.push(byte);
+        i += 1;
+    }
+    marked
+}
+
+/// Replaces the marker strings the guest encodes `BigInt`s as in the JSON `output` with the integer literals they
+/// stand for.
+///
+/// Only a whole string value consisting of the marker followed by an integer, e.g. `"\u0000bigint:-42"`, is replaced.
+/// Object keys and strings which merely contain the marker are left as they are, so the output stays valid JSON.
+pub(crate) fn unmark_big_integers(output: Vec<u8>) -> Vec<u8> {
+    let marker = format!("\"{BIG_INTEGER_MARKER}");
+    if !output
+        .windows(marker.len())
+        .any(|window| window == marker.as_bytes())
+    {
+        return output;
+    }
+
+    let mut unmarked = Vec::with_capacity(output.len());
+    let mut i = 0;
+    while i < output.len() {
+        if output[i] != b'"' {
+            unmarked.push(output[i]);
+            i += 1;
+            continue;
+        }
+        let end = string_end(&output, i);
+        let token = &output[i..end];
+        let is_key = output[end..]
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+            .is_some_and(|byte| *byte == b':');
+        match marked_integer(token) {
+            Some(digits) if !is_key => unmarked.extend_from_slice(digits),
+            _ => unmarked.extend_from_slice(token),
+        }
+        i = end;
+    }
+    unmarked
+}
+
+/// Returns the index just past the JSON string token starting with the quote at `start`.
+fn string_end(json: &[u8], start: usize) -> usize {
+    let mut escaped = false;
+    for (offset, byte) in json[start + 1..].iter().enumerate() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'"' => return start + offset + 2,
+            _ => {}
+        }
+    }
+    json.len()
+}
+
+/// Returns the integer literal a JSON string token stands for if it is a marker string, i.e. the marker followed by
+/// `-?(0|[1-9][0-9]*)`.
+fn marked_integer(token: &[u8]) -> Option<&[u8]> {
+    let digits = token
+        .strip_prefix(b"\"")?
+        .strip_suffix(b"\"")?
+        .strip_prefix(BIG_INTEGER_MARKER.as_bytes())?;
+    let unsigned = digits.strip_prefix(b"-").unwrap_or(digits);
+    let canonical = match unsigned {
+        [] => false,
+        [b'0'] => unsigned.len() == digits.len(),
+        [b'0', ..] => false,
+        _ => unsigned.iter().all(u8::is_ascii_digit),
+    };
+    canonical.then_some(digits)
+}
+
+/// Returns whether `number` is an integer literal outside the range JavaScript numbers represent exactly.
+fn is_big_integer(number: &[u8]) -> bool {
+    let digits = number.strip_prefix(b"-").unwrap_or(number);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return false;
+    }
+    let digits = match digits.iter().position(|digit| *digit != b'0') {
+        Some(start) => &digits[start..],
+        None => return false,
+    };
+    match digits.len().cmp(&MAX_SAFE_INTEGER.len()) {
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Less => false,
+        std::cmp::Ordering::Equal => digits > MAX_SAFE_INTEGER.as_bytes(),
+    }
+}
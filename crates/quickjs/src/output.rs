@@ -0,0 +1,223 @@
+use serde::Serialize;
+use serde_json::value::RawValue;
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// The detailed result of an execution returned by `QuickJS::try_execute_detailed`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecuteOutput {
+    /// The script's result as JSON, or `None` if it evaluated to `undefined`. The same as `try_execute`.
+    pub output: Option<String>,
+    /// Non-fatal problems observed by the host during the execution, e.g. console output that was discarded. QuickJS
+    /// does not report compile warnings so none are included.
+    pub warnings: Vec<String>,
+    /// Resource usage of the execution.
+    pub stats: ExecuteStats,
+    /// How close the execution came to its memory limit or time limit, e.g. to tune the limits from metrics.
+    pub limit_status: LimitStatus,
+    /// Whether `output` was truncated to fit `QuickJSBuilder::with_max_output_bytes`, see
+    /// `QuickJSBuilder::with_truncate_output` for how.
+    pub truncated: bool,
+}
+
+/// How close a successful execution came to the limits of its instance.
+///
+/// An execution is near a limit if it used at least 90% of it: its peak memory for the memory limit, or the epochs
+/// counted against the time limit, which are measured in whole evaluation intervals so are accurate to one interval.
+/// An execution near both limits reports the one it came closest to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LimitStatus {
+    /// The execution is not near either limit, or the instance has neither.
+    #[default]
+    None,
+    /// The execution's peak memory is near the memory limit set with `QuickJSBuilder::with_memory_limit`.
+    NearMemory,
+    /// The execution's running time is near the time limit.
+    NearTime,
+}
+
+impl LimitStatus {
+    /// The fraction of a limit at or above which an execution is near it.
+    const NEAR_LIMIT: f64 = 0.9;
+
+    /// Returns the status for the fractions of the memory limit and time limit used, if the instance has them.
+    pub(crate) fn from_usage(memory: Option<f64>, time: Option<f64>) -> Self {
+        let memory = memory.unwrap_or_default();
+        let time = time.unwrap_or_default();
+        if memory.max(time) < Self::NEAR_LIMIT {
+            LimitStatus::None
+        } else if memory >= time {
+            LimitStatus::NearMemory
+        } else {
+            LimitStatus::NearTime
+        }
+    }
+}
+
+/// Resource usage of a single execution.
+///
+/// The guest times the phases of the execution with its monotonic clock so the times are zero with
+/// `QuickJSBuilder::with_virtual_clock` unless the clock is advanced during the execution.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExecuteStats {
+    /// The largest size of a linear memory in bytes.
+    pub peak_memory: usize,
+    /// The time the guest spent transcoding the input data, and the results of host functions, from JSON.
+    pub input_transcode_time: Duration,
+    /// The time the guest spent evaluating the script, including transcoding any values it passed to `emit`.
+    pub evaluation_time: Duration,
+    /// The time the guest spent transcoding the result and emitted values to JSON.
+    pub output_transcode_time: Duration,
+    /// The CPU time consumed by the thread running the guest, for billing. Unlike the other times this is measured
+    /// by the host so includes host functions called by the guest. `None` on platforms without a per-thread CPU
+    /// clock, such as Windows.
+    pub cpu_time: Option<Duration>,
+    /// The most jobs, such as promise reactions, run by a single drain of the guest's job queue. The queue is drained
+    /// when awaiting the default export of an ES module and with `QuickJSBuilder::with_unhandled_rejection_is_error`.
+    /// QuickJS does not expose the length of the queue so this counts every job queued while draining, an upper bound
+    /// of its depth. A high count flags a script scheduling work without bound.
+    pub max_pending_jobs: u32,
+    /// The fuel consumed by the execution, if fuel is enabled with `QuickJSBuilder::with_fuel_limit` or
+    /// `QuickJSBuilder::with_interrupt_fuel_interval`.
+    pub fuel_consumed: Option<u64>,
+}
+
+/// The result of an execution returned by `QuickJS::try_execute_with_stats` together with its resource usage, which is
+/// reported whether or not the execution succeeded.
+#[derive(Debug)]
+pub struct ExecuteReport {
+    /// The same result as `try_execute`.
+    pub result: anyhow::Result<Option<String>>,
+    /// Resource usage of the execution up to the point it completed or failed. Zero if it failed before the guest ran,
+    /// e.g. the instance was shut down.
+    pub stats: ExecuteStats,
+}
+
+/// Truncates the JSON `output` to a value whose serialization is at most `limit` bytes, keeping the longest prefix
+/// of an array's elements or a string's characters. Returns `None` if the output is neither or its empty prefix does
+/// not fit.
+///
+/// Array elements are kept as the raw JSON the guest produced so their formatting and key order are unchanged.
+pub(crate) fn truncate_output(
+    output: &[u8],
+    limit: usize,
+    pretty: bool,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    fn serialize(value: &impl Serialize, pretty: bool) -> serde_json::Result<Vec<u8>> {
+        if pretty {
+            serde_json::to_vec_pretty(value)
+        } else {
+            serde_json::to_vec(value)
+        }
+    }
+
+    // The longest prefix which fits is found by bisecting its length, serializing each candidate.
+    let longest_prefix = |len: usize, prefix: &dyn Fn(usize) -> serde_json::Result<Vec<u8>>| {
+        let fits = |n: usize| prefix(n).map(|bytes| bytes.len() <= limit);
+        if !fits(0)? {
+            return Ok::<_, serde_json::Error>(None);
+        }
+        let (mut low, mut high) = (0, len);
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if fits(mid)? {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        prefix(low).map(Some)
+    };
+
+    if let Ok(elements) = serde_json::from_slice::<Vec<&RawValue>>(output) {
+        return Ok(longest_prefix(elements.len(), &|n| {
+            serialize(&elements[..n], pretty)
+        })?);
+    }
+    if let Ok(string) = serde_json::from_slice::<String>(output) {
+        let chars = string.chars().collect::<Vec<_>>();
+        return Ok(longest_prefix(chars.len(), &|n| {
+            serialize(&chars[..n].iter().collect::<String>(), pretty)
+        })?);
+    }
+    Ok(None)
+}
+
+/// A function receiving each line written to the console, set with `QuickJSBuilder::with_live_console`. The last
+/// argument is the identifier passed to `QuickJS::try_execute_with_request_id`, if any, so the line itself is exactly
+/// what the script wrote.
+pub type ConsoleCallback = Arc<dyn Fn(ConsoleStream, &str, Option<&str>) + Send + Sync>;
+
+/// A function called before every execution, set with `QuickJSBuilder::with_before_execute`. Returning an error
+/// rejects the execution.
+pub type BeforeExecuteCallback = Arc<dyn Fn() -> anyhow::Result<()> + Send + Sync>;
+
+/// A function called after every execution with its statistics or the error it failed with, set with
+/// `QuickJSBuilder::with_after_execute`.
+pub type AfterExecuteCallback = Arc<dyn Fn(Result<&ExecuteStats, &anyhow::Error>) + Send + Sync>;
+
+/// A function notified when the guest's memory grows past a fraction of the memory limit, set with
+/// `QuickJSBuilder::with_memory_threshold_callback`. Receives the fraction and the size of the memory in bytes.
+pub type MemoryThresholdCallback = Arc<dyn Fn(f64, usize) + Send + Sync>;
+
+/// The value substituted for values in the output which cannot be serialized to JSON, set with
+/// `QuickJSBuilder::with_output_placeholder`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputPlaceholder {
+    /// `null`.
+    Null,
+    /// An object describing the value, e.g. `{"$unserializable": "BigInt"}`.
+    Description,
+}
+
+/// An event of an execution's transcript, returned by `QuickJS::try_execute_transcript` in the order they occurred.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputEvent {
+    /// A line written with `console.log`.
+    Log(String),
+    /// A line written with `console.error`.
+    Error(String),
+    /// The result of the script. Always the last event, and absent if the script evaluated to `undefined`.
+    Result(serde_json::Value),
+}
+
+/// The console stream a line was written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleStream {
+    /// Written with `console.log`.
+    Stdout,
+    /// Written with `console.error`.
+    Stderr,
+}
+
+/// A sink for guest output which is not inherited. It discards the output but counts the bytes written so
+/// the host can warn that output was lost.
+#[derive(Clone, Default)]
+pub(crate) struct DiscardedOutput {
+    bytes: Arc<AtomicUsize>,
+}
+
+impl DiscardedOutput {
+    /// Returns the number of bytes written to the sink.
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+impl Write for DiscardedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.bytes.fetch_add(buf.len(), Ordering::Relaxed);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
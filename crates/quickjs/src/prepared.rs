@@ -0,0 +1,74 @@
+use anyhow::Result;
+
+use crate::{Invocation, Job, Options, QuickJS};
+
+/// A script compiled once by `QuickJS::prepare` and applied to many inputs, e.g. a fixed transform serving a stream
+/// of records.
+///
+/// Only the input data changes between applications so the script is never parsed again. Each `apply` is a separate
+/// execution, with the limits of the instance it was prepared by, which binds the input data in a fresh JavaScript
+/// context so inputs cannot observe each other. The module is instantiated for every `apply` as the guest's state
+/// cannot be reset between executions; `apply_all` shares a single instantiation across many inputs instead.
+#[derive(Clone)]
+pub struct PreparedScript {
+    /// The instance which compiled the script. Bytecode is specific to its module so it also executes it.
+    quickjs: QuickJS,
+    /// The bytecode of the script.
+    bytecode: Vec<u8>,
+}
+
+impl PreparedScript {
+    /// Creates a prepared script from bytecode compiled by `quickjs`.
+    pub(crate) fn new(quickjs: QuickJS, bytecode: Vec<u8>) -> Self {
+        Self { quickjs, bytecode }
+    }
+
+    /// Returns the bytecode of the script, e.g. to cache it for `QuickJS::try_execute_bytecode`.
+    pub fn bytecode(&self) -> &[u8] {
+        &self.bytecode
+    }
+
+    /// Attempts to execute the script with optional input data.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `try_execute` would return for the script.
+    pub fn apply(&self, data: Option<&str>) -> Result<Option<String>> {
+        self.quickjs
+            .execute(Invocation {
+                script: self.bytecode.clone(),
+                data: data
+                    .map(|data| data.as_bytes().to_vec())
+                    .unwrap_or_default(),
+                options: self.options(),
+                ..Default::default()
+            })?
+            .into_json()
+    }
+
+    /// Attempts to execute the script with each of `inputs` in a single instantiation of the module, as with
+    /// `QuickJS::try_execute_batch`.
+    ///
+    /// # Returns
+    ///
+    /// The same output as `apply` for each input, in order.
+    pub fn apply_all(&self, inputs: &[&str]) -> Vec<Result<Option<String>>> {
+        let batch = inputs
+            .iter()
+            .map(|data| Job {
+                script: self.bytecode.clone(),
+                data: data.as_bytes().to_vec(),
+            })
+            .collect();
+
+        self.quickjs.execute_jobs(batch, self.options(), false)
+    }
+
+    /// Returns the options to execute the bytecode with.
+    fn options(&self) -> Options {
+        Options {
+            bytecode: true,
+            ..self.quickjs.options()
+        }
+    }
+}
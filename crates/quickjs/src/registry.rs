@@ -0,0 +1,55 @@
+use crate::{QuickJS, QuickJSBuilder};
+use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+/// A cache of `QuickJS` instances by key, for hosts which run scripts against several different modules.
+///
+/// Each instance is built, and so its module compiled, once when it is registered. `get` then returns a ready
+/// instance without recompiling the module, so switching between modules is cheap. Clones share the same instances.
+#[derive(Clone, Debug, Default)]
+pub struct QuickJSRegistry {
+    /// The registered instances by key.
+    instances: Arc<RwLock<BTreeMap<String, QuickJS>>>,
+}
+
+impl QuickJSRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds an instance from `builder`, e.g. configured with `QuickJSBuilder::with_module`, and registers it as
+    /// `key`, replacing any instance already registered as `key`.
+    ///
+    /// # Returns
+    ///
+    /// The registered instance, or an error if it could not be built, in which case the registry is unchanged.
+    pub fn register(&self, key: impl Into<String>, builder: &QuickJSBuilder) -> Result<QuickJS> {
+        let quickjs = builder.build()?;
+        self.instances
+            .write()
+            .unwrap()
+            .insert(key.into(), quickjs.clone());
+
+        Ok(quickjs)
+    }
+
+    /// Returns the instance registered as `key`. Instances are cheap to clone as they share the compiled module.
+    pub fn get(&self, key: &str) -> Option<QuickJS> {
+        self.instances.read().unwrap().get(key).cloned()
+    }
+
+    /// Removes the instance registered as `key`, returning it if there was one. The module is freed once every
+    /// clone of the instance has been dropped.
+    pub fn remove(&self, key: &str) -> Option<QuickJS> {
+        self.instances.write().unwrap().remove(key)
+    }
+
+    /// Returns the registered keys in order.
+    pub fn keys(&self) -> Vec<String> {
+        self.instances.read().unwrap().keys().cloned().collect()
+    }
+}
@@ -0,0 +1,80 @@
+use std::{io::Write, sync::Arc};
+
+/// The text substituted for a secret in the output of the guest.
+const REDACTED: &[u8] = b"[REDACTED]";
+
+/// The values of the secrets set with `QuickJSBuilder::with_secret`, which are redacted from everything the guest
+/// sends to the host.
+#[derive(Clone, Default)]
+pub(crate) struct Secrets {
+    /// Each secret as raw text and as escaped within a JSON string, longest first.
+    patterns: Arc<Vec<Vec<u8>>>,
+}
+
+impl Secrets {
+    /// Creates the patterns to redact from the secret values. Empty values are ignored.
+    pub(crate) fn new<'a>(values: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut patterns = Vec::new();
+        for value in values.into_iter().filter(|value| !value.is_empty()) {
+            patterns.push(value.as_bytes().to_vec());
+            let escaped = serde_json::to_string(value).unwrap_or_default();
+            let escaped = escaped.trim_matches('"').as_bytes().to_vec();
+            if !patterns.contains(&escaped) {
+                patterns.push(escaped);
+            }
+        }
+        patterns.sort_by_key(|pattern| std::cmp::Reverse(pattern.len()));
+        Self {
+            patterns: Arc::new(patterns),
+        }
+    }
+
+    /// Returns whether there are no secrets to redact.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Replaces every occurrence of a secret in `bytes` with `[REDACTED]`.
+    pub(crate) fn redact(&self, mut bytes: Vec<u8>) -> Vec<u8> {
+        for pattern in self.patterns.iter() {
+            let mut redacted = Vec::with_capacity(bytes.len());
+            let mut rest = &bytes[..];
+            while let Some(index) = rest
+                .windows(pattern.len())
+                .position(|window| window == pattern)
+            {
+                redacted.extend_from_slice(&rest[..index]);
+                redacted.extend_from_slice(REDACTED);
+                rest = &rest[index + pattern.len()..];
+            }
+            redacted.extend_from_slice(rest);
+            bytes = redacted;
+        }
+        bytes
+    }
+}
+
+/// A writer which redacts secrets from each write before passing it on, used for inherited stdout and stderr.
+///
+/// A secret split across two writes is not redacted. The console writes each line in a single write.
+pub(crate) struct RedactedWriter<W> {
+    inner: W,
+    secrets: Secrets,
+}
+
+impl<W> RedactedWriter<W> {
+    pub(crate) fn new(inner: W, secrets: Secrets) -> Self {
+        Self { inner, secrets }
+    }
+}
+
+impl<W: Write> Write for RedactedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write_all(&self.secrets.redact(buf.to_vec()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use anyhow::Result;
+use futures_core::Stream;
+
+use crate::CancellationToken;
+
+/// The values of an execution in flight, returned by `QuickJS::try_execute_stream`.
+///
+/// Each value passed to `emit` is yielded as JSON as soon as the guest emits it, followed by the script's result
+/// unless it evaluated to `undefined`. An error ending the execution is yielded last. Dropping the stream cancels the
+/// execution if the instance is interruptible, otherwise it runs to completion in the background.
+pub struct ExecuteStream {
+    shared: Arc<Mutex<Shared>>,
+    cancellation: CancellationToken,
+}
+
+/// The state shared by a stream and the thread executing the script.
+#[derive(Default)]
+struct Shared {
+    /// The values yielded but not yet polled.
+    pending: VecDeque<Result<String>>,
+    /// Whether the execution has completed so no more values follow.
+    finished: bool,
+    /// The waker of the task waiting for the next value.
+    waker: Option<Waker>,
+}
+
+/// The end of an `ExecuteStream` held by the thread executing the script. Dropping it finishes the stream.
+pub(crate) struct StreamSender {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl ExecuteStream {
+    /// Creates a stream and the sender which yields its values, cancelling `cancellation` when the stream is dropped.
+    pub(crate) fn new(cancellation: CancellationToken) -> (Self, StreamSender) {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let sender = StreamSender {
+            shared: shared.clone(),
+        };
+        (
+            Self {
+                shared,
+                cancellation,
+            },
+            sender,
+        )
+    }
+}
+
+impl Stream for ExecuteStream {
+    type Item = Result<String>;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.pending.pop_front() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if shared.finished => Poll::Ready(None),
+            None => {
+                shared.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for ExecuteStream {
+    fn drop(&mut self) {
+        self.cancellation.cancel();
+    }
+}
+
+impl StreamSender {
+    /// Yields `value` to the stream, waking the task waiting for it.
+    pub(crate) fn send(&self, value: Result<String>) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.pending.push_back(value);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for StreamSender {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.finished = true;
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{QuickJS, QuickJSBuilder, VirtualClock};
+
+/// The seed for `Math.random` and `crypto.getRandomValues` of the instance built by `deterministic`.
+const RANDOM_SEED: u64 = 42;
+
+/// Builds an instance whose output depends only on the script and its input data: `Math.random` is seeded and the
+/// clock is fixed at `2000-01-01T00:00:00Z`.
+///
+/// # Panics
+///
+/// Panics if the instance cannot be built.
+pub fn deterministic() -> QuickJS {
+    QuickJSBuilder::new()
+        .with_random_seed(RANDOM_SEED)
+        .with_virtual_clock(VirtualClock::new(
+            UNIX_EPOCH + Duration::from_secs(946_684_800),
+        ))
+        .build()
+        .expect("failed to build a deterministic instance")
+}
+
+/// Returns the `deterministic` instance shared by every `assert_output`, built on first use so the module is compiled
+/// once per test binary rather than once per assertion.
+fn shared() -> &'static QuickJS {
+    static SHARED: OnceLock<QuickJS> = OnceLock::new();
+    SHARED.get_or_init(deterministic)
+}
+
+/// Executes `script` with optional input data on a `deterministic` instance and asserts that its result equals the
+/// JSON `expected`.
+///
+/// The result and `expected` are compared as parsed JSON so formatting and the order of object keys do not matter.
+/// A script whose result is `undefined` matches `expected` of `undefined`.
+///
+/// # Panics
+///
+/// Panics with a line diff of the pretty-printed values if they differ, or if the script fails or `expected` is not
+/// valid JSON.
+#[track_caller]
+pub fn assert_output(script: &str, data: Option<&str>, expected: &str) {
+    let output = match shared().try_execute(script, data) {
+        Ok(output) => output,
+        Err(err) => panic!("script failed: {err:#}"),
+    };
+
+    let parse = |json: &str| -> Option<serde_json::Value> {
+        (json != "undefined").then(|| {
+            serde_json::from_str(json).unwrap_or_else(|err| panic!("invalid JSON `{json}`: {err}"))
+        })
+    };
+    let actual = output.as_deref().and_then(parse);
+    let expected = parse(expected);
+
+    if actual != expected {
+        panic!(
+            "script output does not match (- expected, + actual):\n{}",
+            diff(&pretty(expected.as_ref()), &pretty(actual.as_ref()))
+        );
+    }
+}
+
+/// Pretty-prints a value, or `undefined` if there is none.
+fn pretty(value: Option<&serde_json::Value>) -> String {
+    match value {
+        Some(value) => serde_json::to_string_pretty(value).unwrap_or_default(),
+        None => "undefined".to_string(),
+    }
+}
+
+/// Returns a line diff of `expected` and `actual`, prefixing removed lines with `-`, added lines with `+` and common
+/// lines with a space.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+
+    // The length of the longest common subsequence of the lines from `i` and `j` onwards.
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = match expected[i] == actual[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,14 @@
+use anyhow::Result;
+
+/// Converts between a data format and the JSON values exchanged with scripts, set with
+/// `QuickJSBuilder::with_transcoder` to support formats other than JSON for input data and results.
+///
+/// Transcoding runs on the host, so scripts still see the input data as the `data` global and return plain values
+/// whatever the format. Values pass through `serde_json::Value`, so the keys of objects are sorted.
+pub trait Transcoder: Send + Sync {
+    /// Decodes the input data from the format into the value bound as `data`.
+    fn decode(&self, bytes: &[u8]) -> Result<serde_json::Value>;
+
+    /// Encodes the result of a script into the format.
+    fn encode(&self, value: serde_json::Value) -> Result<Vec<u8>>;
+}
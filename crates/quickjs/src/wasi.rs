@@ -0,0 +1,108 @@
+use std::{io::Cursor, sync::Arc};
+
+use wasi_common::{
+    pipe::{ReadPipe, WritePipe},
+    sync::{clocks_ctx, random_ctx, sched_ctx, stdio},
+    Table, WasiCtx,
+};
+
+use crate::{
+    output::DiscardedOutput,
+    secret::{RedactedWriter, Secrets},
+    VirtualClock,
+};
+
+/// Where the guest's standard output or standard error is written.
+#[derive(Clone)]
+enum Sink {
+    /// Inherited from the host process.
+    Inherit,
+    /// Inherited from the host process with secrets redacted.
+    Redacted(Secrets),
+    /// Discarded, counting the bytes written.
+    Discard,
+}
+
+/// The WASI configuration of an instance, resolved once when the instance is built so each execution only creates
+/// the parts of its `WasiCtx` which cannot be shared: the table, the pipes and the counters of discarded output.
+#[derive(Clone)]
+pub(crate) struct WasiTemplate {
+    /// Bytes the guest reads from standard input, or `None` for an empty standard input.
+    stdin: Option<Arc<[u8]>>,
+    /// Where standard output is written.
+    stdout: Sink,
+    /// Where standard error is written.
+    stderr: Sink,
+    /// The clock the guest reads, or `None` for the host clocks.
+    virtual_clock: Option<VirtualClock>,
+}
+
+/// The `WasiCtx` of one execution and the sinks counting the output it discards.
+pub(crate) struct WasiInstance {
+    pub(crate) ctx: WasiCtx,
+    pub(crate) discarded_stdout: DiscardedOutput,
+    pub(crate) discarded_stderr: DiscardedOutput,
+}
+
+impl WasiTemplate {
+    pub(crate) fn new(
+        stdin: Option<Arc<[u8]>>,
+        inherit_stdout: bool,
+        inherit_stderr: bool,
+        secrets: &Secrets,
+        virtual_clock: Option<VirtualClock>,
+    ) -> Self {
+        let sink = |inherit: bool| match inherit {
+            true if secrets.is_empty() => Sink::Inherit,
+            true => Sink::Redacted(secrets.clone()),
+            false => Sink::Discard,
+        };
+
+        Self {
+            stdin,
+            stdout: sink(inherit_stdout),
+            stderr: sink(inherit_stderr),
+            virtual_clock,
+        }
+    }
+
+    /// Creates a `WasiCtx` for one execution with this configuration.
+    pub(crate) fn instantiate(&self) -> WasiInstance {
+        // Use the virtual clock if requested by the user, otherwise the host clocks
+        let clocks = match &self.virtual_clock {
+            Some(virtual_clock) => virtual_clock.clocks(),
+            None => clocks_ctx(),
+        };
+        let ctx = WasiCtx::new(random_ctx(), clocks, sched_ctx(), Table::new());
+
+        // The guest reads the configured standard input from the start, or nothing
+        match &self.stdin {
+            Some(stdin) => ctx.set_stdin(Box::new(ReadPipe::new(Cursor::new(stdin.clone())))),
+            None => ctx.set_stdin(Box::new(ReadPipe::new(std::io::empty()))),
+        }
+
+        let discarded_stdout = DiscardedOutput::default();
+        let discarded_stderr = DiscardedOutput::default();
+
+        match &self.stdout {
+            Sink::Inherit => ctx.set_stdout(Box::new(stdio::stdout())),
+            Sink::Redacted(secrets) => ctx.set_stdout(Box::new(WritePipe::new(
+                RedactedWriter::new(std::io::stdout(), secrets.clone()),
+            ))),
+            Sink::Discard => ctx.set_stdout(Box::new(WritePipe::new(discarded_stdout.clone()))),
+        }
+        match &self.stderr {
+            Sink::Inherit => ctx.set_stderr(Box::new(stdio::stderr())),
+            Sink::Redacted(secrets) => ctx.set_stderr(Box::new(WritePipe::new(
+                RedactedWriter::new(std::io::stderr(), secrets.clone()),
+            ))),
+            Sink::Discard => ctx.set_stderr(Box::new(WritePipe::new(discarded_stderr.clone()))),
+        }
+
+        WasiInstance {
+            ctx,
+            discarded_stdout,
+            discarded_stderr,
+        }
+    }
+}